@@ -0,0 +1,271 @@
+//! The structure of a parsed ABNF grammar.
+//!
+//! https://www.rfc-editor.org/rfc/rfc5234#section-4
+
+use std::fmt;
+
+/// A range of byte offsets. For AST nodes, this is where in the grammar source the node was parsed
+/// from. For a [`ParseTree`](crate::ParseTree), it's where in the input a rule matched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+}
+
+/// `rulelist = 1*( rule / (*c-wsp c-nl) )`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleList {
+    pub rules: Vec<Rule>,
+    /// Comments on lines of their own between rules
+    pub comments: Vec<Comment>,
+}
+
+/// `rule = rulename defined-as elements c-nl`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    pub name: String,
+    pub defined_as: DefinedAs,
+    pub elements: Alternation,
+    pub span: Span,
+    /// Comments within the rule, including any at the end of its last line
+    pub comments: Vec<Comment>,
+}
+
+/// `comment = ";" *(WSP / VCHAR) CRLF`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comment {
+    /// Everything after the `;`, without trailing whitespace or the line ending
+    pub text: String,
+    pub span: Span,
+}
+
+/// https://www.rfc-editor.org/rfc/rfc5234#section-3.3
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefinedAs {
+    /// `=`, a basic rule definition
+    Basic,
+    /// `=/`, an incremental alternative for a previously defined rule
+    Incremental,
+}
+
+impl fmt::Display for DefinedAs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DefinedAs::Basic => write!(f, "="),
+            DefinedAs::Incremental => write!(f, "=/"),
+        }
+    }
+}
+
+/// `alternation = concatenation *(*c-wsp "/" *c-wsp concatenation)`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Alternation {
+    pub concatenations: Vec<Concatenation>,
+    pub span: Span,
+}
+
+impl Alternation {
+    /// Every rule name referenced by this alternation, including those nested in groups and
+    /// options. Each comes with the span of the repetition it appears in.
+    pub fn rule_references(&self) -> Vec<(&str, Span)> {
+        let mut references = Vec::new();
+        for repetition in self.concatenations.iter().flat_map(|c| &c.repetitions) {
+            match &repetition.element {
+                Element::RuleName(name) => references.push((name.as_str(), repetition.span)),
+                Element::Group(alternation) | Element::Option(alternation) => {
+                    references.extend(alternation.rule_references())
+                }
+                Element::CharVal(_) | Element::NumVal(_) | Element::ProseVal(_) => {}
+            }
+        }
+
+        references
+    }
+}
+
+impl fmt::Display for Alternation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, concatenation) in self.concatenations.iter().enumerate() {
+            if i > 0 {
+                write!(f, " / ")?;
+            }
+            write!(f, "{concatenation}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// `concatenation = repetition *(1*c-wsp repetition)`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Concatenation {
+    pub repetitions: Vec<Repetition>,
+    pub span: Span,
+}
+
+impl fmt::Display for Concatenation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, repetition) in self.repetitions.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{repetition}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// `repetition = [repeat] element`
+///
+/// A missing `repeat` is the same as `1*1`. A `max` of `None` means there is no upper bound.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Repetition {
+    pub min: usize,
+    pub max: Option<usize>,
+    pub element: Element,
+    pub span: Span,
+}
+
+impl fmt::Display for Repetition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.min, self.max) {
+            (1, Some(1)) => {}
+            (min, Some(max)) if min == max => write!(f, "{min}")?,
+            (0, None) => write!(f, "*")?,
+            (0, Some(max)) => write!(f, "*{max}")?,
+            (min, None) => write!(f, "{min}*")?,
+            (min, Some(max)) => write!(f, "{min}*{max}")?,
+        }
+
+        write!(f, "{}", self.element)
+    }
+}
+
+/// `element = rulename / group / option / char-val / num-val / prose-val`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Element {
+    RuleName(String),
+    Group(Alternation),
+    Option(Alternation),
+    CharVal(CharVal),
+    NumVal(NumVal),
+    /// The contents of a prose description, without the angle brackets
+    ProseVal(String),
+}
+
+impl fmt::Display for Element {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Element::RuleName(name) => write!(f, "{name}"),
+            Element::Group(alternation) => write!(f, "({alternation})"),
+            Element::Option(alternation) => write!(f, "[{alternation}]"),
+            Element::CharVal(char_val) => write!(f, "{char_val}"),
+            Element::NumVal(num_val) => write!(f, "{num_val}"),
+            Element::ProseVal(prose) => write!(f, "<{prose}>"),
+        }
+    }
+}
+
+/// `char-val = case-insensitive-string / case-sensitive-string`
+///
+/// https://www.rfc-editor.org/rfc/rfc7405#section-2.2
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CharVal {
+    /// The contents of the quoted string, without the quotes
+    pub value: String,
+    /// `true` for `%s"..."` strings. Bare quoted strings and `%i"..."` strings match ASCII
+    /// letters regardless of case.
+    pub case_sensitive: bool,
+}
+
+impl CharVal {
+    /// A quoted string that ignores case, like `"abc"`
+    pub fn insensitive(value: impl Into<String>) -> Self {
+        Self {
+            value: value.into(),
+            case_sensitive: false,
+        }
+    }
+
+    /// A quoted string that respects case, like `%s"abc"`
+    pub fn sensitive(value: impl Into<String>) -> Self {
+        Self {
+            value: value.into(),
+            case_sensitive: true,
+        }
+    }
+}
+
+impl fmt::Display for CharVal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.case_sensitive {
+            write!(f, "%s")?;
+        }
+
+        write!(f, "\"{}\"", self.value)
+    }
+}
+
+/// `num-val = "%" (bin-val / dec-val / hex-val)`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NumVal {
+    pub base: Base,
+    pub value: NumValue,
+}
+
+impl fmt::Display for NumVal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = |v: u32| match self.base {
+            Base::Binary => format!("{v:b}"),
+            Base::Decimal => format!("{v}"),
+            Base::Hexadecimal => format!("{v:02X}"),
+        };
+        let prefix = match self.base {
+            Base::Binary => 'b',
+            Base::Decimal => 'd',
+            Base::Hexadecimal => 'x',
+        };
+
+        write!(f, "%{prefix}")?;
+        match &self.value {
+            NumValue::Sequence(values) => {
+                let values: Vec<_> = values.iter().map(|&v| value(v)).collect();
+                write!(f, "{}", values.join("."))
+            }
+            NumValue::Range(low, high) => write!(f, "{}-{}", value(*low), value(*high)),
+        }
+    }
+}
+
+/// https://www.rfc-editor.org/rfc/rfc5234#section-2.3
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Base {
+    Binary,
+    Decimal,
+    Hexadecimal,
+}
+
+impl Base {
+    pub fn radix(&self) -> u32 {
+        match self {
+            Base::Binary => 2,
+            Base::Decimal => 10,
+            Base::Hexadecimal => 16,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NumValue {
+    /// One or more `.` separated values that must appear in order e.g. `%x20` or `%d13.10`
+    Sequence(Vec<u32>),
+    /// An inclusive range of values e.g. `%x30-39`
+    Range(u32, u32),
+}
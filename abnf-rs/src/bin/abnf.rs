@@ -0,0 +1,147 @@
+//! `cargo run -p abnf-rs --features cli -- check --grammar nsid.abnf --rule nsid com.example.foo`
+
+use abnf_rs::Grammar;
+use anyhow::{bail, Context};
+use clap::{Parser, Subcommand};
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+/// Work with ABNF grammars from the command line
+#[derive(Parser, Debug)]
+#[command(name = "abnf", author, version, about, long_about = None)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Check whether inputs match a rule in a grammar. Exits with 1 if any input doesn't match, or
+    /// with 2 if the grammar couldn't be loaded.
+    Check {
+        /// Path of the file containing the grammar, in RFC 5234 ABNF
+        #[arg(short, long)]
+        grammar: PathBuf,
+
+        /// The rule to match inputs against. Defaults to the first rule in the grammar.
+        #[arg(short, long)]
+        rule: Option<String>,
+
+        /// The inputs to check. If none are given, they're read from stdin, one per line.
+        inputs: Vec<String>,
+    },
+}
+
+fn main() -> ExitCode {
+    // Logs go to stderr so that they don't mix with the results on stdout
+    tracing_subscriber::fmt().with_writer(io::stderr).init();
+    let args = Args::parse();
+
+    let Command::Check {
+        grammar,
+        rule,
+        inputs,
+    } = args.command;
+    match check(grammar, rule, inputs) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(1),
+        Err(e) => {
+            eprintln!("error: {e:#}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// Returns whether every input matched
+fn check(grammar_path: PathBuf, rule: Option<String>, inputs: Vec<String>) -> anyhow::Result<bool> {
+    let source = std::fs::read_to_string(&grammar_path)
+        .with_context(|| format!("couldn't read grammar from {}", grammar_path.display()))?;
+    let grammar = Grammar::from_metasyntax(&source)
+        .with_context(|| format!("couldn't load grammar from {}", grammar_path.display()))?;
+    let Some(rule) = rule.or_else(|| grammar.rules().next().map(|r| r.name.clone())) else {
+        bail!("{} doesn't define any rules", grammar_path.display());
+    };
+
+    let report = grammar.check(&rule)?;
+    for diagnostic in &report.diagnostics {
+        eprintln!("warning: {}", diagnostic.render(&source));
+    }
+
+    let mut stdout = io::stdout().lock();
+    if !inputs.is_empty() {
+        return check_inputs(&grammar, &rule, inputs, &mut stdout);
+    }
+
+    let inputs = io::stdin()
+        .lock()
+        .lines()
+        .collect::<Result<Vec<_>, _>>()
+        .context("couldn't read inputs from stdin")?;
+    check_inputs(&grammar, &rule, inputs, &mut stdout)
+}
+
+/// Match each input against `rule`, writing a line for each to `out` along with where matching
+/// failed for those that didn't match. Returns whether every input matched.
+fn check_inputs(
+    grammar: &Grammar,
+    rule: &str,
+    inputs: impl IntoIterator<Item = String>,
+    out: &mut impl Write,
+) -> anyhow::Result<bool> {
+    let mut all_matched = true;
+    for input in inputs {
+        let Err(e) = grammar.match_rule(rule, &input) else {
+            writeln!(out, "ok       {input}")?;
+            continue;
+        };
+
+        all_matched = false;
+        writeln!(out, "no match {input}")?;
+        match (e.offset(), e.rule_stack()) {
+            (Some(offset), Some(rule_stack)) => {
+                let column = input[..offset].chars().count();
+                writeln!(
+                    out,
+                    "         {}^ failed here, in {}",
+                    " ".repeat(column),
+                    rule_stack.join(" > ")
+                )?;
+            }
+            _ => writeln!(out, "         {e}")?,
+        }
+    }
+
+    Ok(all_matched)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::check_inputs;
+    use abnf_rs::Grammar;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_check_inputs() {
+        let grammar = Grammar::from_metasyntax(
+            "nsid = segment 1*( \".\" segment )\nsegment = ALPHA *( ALPHA / DIGIT / \"-\" )\n",
+        )
+        .unwrap();
+
+        let mut out = Vec::new();
+        let inputs = ["com.example.foo", "com.ex_ample"].map(String::from);
+        let all_matched = check_inputs(&grammar, "nsid", inputs, &mut out).unwrap();
+
+        assert!(!all_matched);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "ok       com.example.foo\n\
+             no match com.ex_ample\n\
+             \x20              ^ failed here, in nsid > segment > ALPHA\n"
+        );
+
+        let mut out = Vec::new();
+        let inputs = ["com.example".to_owned()];
+        assert!(check_inputs(&grammar, "nsid", inputs, &mut out).unwrap());
+    }
+}
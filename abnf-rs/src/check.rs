@@ -0,0 +1,519 @@
+use crate::ast::{Alternation, Concatenation, Element, Repetition, Span};
+use crate::grammar::Grammar;
+use crate::parser::Location;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+
+/// The result of [`Grammar::check`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for diagnostic in &self.diagnostics {
+            writeln!(f, "{diagnostic}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A problem found in a grammar, along with the rule it was found in and where that is in the
+/// grammar source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub rule: String,
+    pub span: Span,
+    pub kind: DiagnosticKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiagnosticKind {
+    /// The rule references a rule that isn't defined
+    UndefinedRule { reference: String },
+    /// The rule can't be reached from the start rule
+    Unreachable,
+    /// The rule can end up calling itself without consuming any input, which would loop a naive
+    /// matcher forever. `cycle` lists the rules involved, starting and ending with this one.
+    LeftRecursive { cycle: Vec<String> },
+    /// The rule can match the empty string, but nothing else
+    OnlyMatchesEmpty,
+}
+
+impl Diagnostic {
+    /// Where the diagnostic is in `source`, the grammar source that the rule was parsed from. It's
+    /// `None` if the diagnostic's span isn't in `source`.
+    pub fn location(&self, source: &str) -> Option<Location> {
+        Location::new(source, self.span.start)
+    }
+
+    /// The diagnostic with its line and column in `source` instead of its byte offsets, followed
+    /// by the source line with a caret under where it is, e.g.
+    ///
+    /// ```text
+    /// rule `a` references undefined rule `b` at line 1, column 5
+    /// 1 | a = b
+    ///   |     ^
+    /// ```
+    ///
+    /// If the diagnostic's span isn't in `source`, it's rendered the same as by `Display`.
+    pub fn render(&self, source: &str) -> String {
+        match self.location(source) {
+            Some(location) => {
+                format!("{} at {location}\n{}", self.message(), location.snippet())
+            }
+            None => self.to_string(),
+        }
+    }
+
+    fn message(&self) -> String {
+        let rule = &self.rule;
+        match &self.kind {
+            DiagnosticKind::UndefinedRule { reference } => {
+                format!("rule `{rule}` references undefined rule `{reference}`")
+            }
+            DiagnosticKind::Unreachable => {
+                format!("rule `{rule}` can't be reached from the start rule")
+            }
+            DiagnosticKind::LeftRecursive { cycle } => {
+                format!("rule `{rule}` is left-recursive: {}", cycle.join(" -> "))
+            }
+            DiagnosticKind::OnlyMatchesEmpty => {
+                format!("rule `{rule}` can only match the empty string")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Span { start, end } = self.span;
+        write!(f, "{} ({start}..{end})", self.message())
+    }
+}
+
+/// Static analysis of the rules a grammar defines itself. Core rules are assumed to be fine.
+pub(crate) struct Checker<'g> {
+    grammar: &'g Grammar,
+    nullable: Vec<bool>,
+    consuming: Vec<bool>,
+}
+
+impl<'g> Checker<'g> {
+    pub(crate) fn new(grammar: &'g Grammar) -> Self {
+        let rule_count = grammar.rules().count();
+        let mut checker = Self {
+            grammar,
+            nullable: vec![false; rule_count],
+            consuming: vec![false; rule_count],
+        };
+        checker.compute_fixpoints();
+
+        checker
+    }
+
+    pub(crate) fn check(&self, start_rule: usize) -> Report {
+        let mut diagnostics = Vec::new();
+        diagnostics.extend(self.undefined_rules());
+        diagnostics.extend(self.unreachable_rules(start_rule));
+        diagnostics.extend(self.left_recursive_rules());
+        diagnostics.extend(self.empty_rules());
+
+        Report { diagnostics }
+    }
+
+    /// Indices of the rules that the grammar defined itself
+    fn user_rules(&self) -> impl Iterator<Item = usize> + '_ {
+        self.grammar
+            .rules()
+            .enumerate()
+            .filter(|(_, rule)| !self.grammar.is_core_rule(&rule.name))
+            .map(|(index, _)| index)
+    }
+
+    fn undefined_rules(&self) -> Vec<Diagnostic> {
+        self.user_rules()
+            .flat_map(|index| {
+                let rule = self.grammar.rule_at(index);
+                rule.elements
+                    .rule_references()
+                    .into_iter()
+                    .filter(|(reference, _)| self.grammar.index_of(reference).is_none())
+                    .map(|(reference, span)| Diagnostic {
+                        rule: rule.name.clone(),
+                        span,
+                        kind: DiagnosticKind::UndefinedRule {
+                            reference: reference.to_owned(),
+                        },
+                    })
+            })
+            .collect()
+    }
+
+    fn unreachable_rules(&self, start_rule: usize) -> Vec<Diagnostic> {
+        let mut reachable = HashSet::from([start_rule]);
+        let mut queue = VecDeque::from([start_rule]);
+        while let Some(index) = queue.pop_front() {
+            let rule = self.grammar.rule_at(index);
+            for (reference, _) in rule.elements.rule_references() {
+                if let Some(referenced) = self.grammar.index_of(reference) {
+                    if reachable.insert(referenced) {
+                        queue.push_back(referenced);
+                    }
+                }
+            }
+        }
+
+        self.user_rules()
+            .filter(|index| !reachable.contains(index))
+            .map(|index| {
+                let rule = self.grammar.rule_at(index);
+                Diagnostic {
+                    rule: rule.name.clone(),
+                    span: rule.span,
+                    kind: DiagnosticKind::Unreachable,
+                }
+            })
+            .collect()
+    }
+
+    pub(crate) fn left_recursive_rules(&self) -> Vec<Diagnostic> {
+        // An edge from a rule to each rule it can call without having consumed any input
+        let left_calls: HashMap<usize, Vec<usize>> = self
+            .grammar
+            .rules()
+            .enumerate()
+            .map(|(index, rule)| {
+                let mut calls = Vec::new();
+                self.left_calls_in_alternation(&rule.elements, &mut calls);
+                (index, calls)
+            })
+            .collect();
+
+        self.user_rules()
+            .filter_map(|index| {
+                let cycle = shortest_cycle(index, &left_calls)?;
+                let rule = self.grammar.rule_at(index);
+                Some(Diagnostic {
+                    rule: rule.name.clone(),
+                    span: rule.span,
+                    kind: DiagnosticKind::LeftRecursive {
+                        cycle: cycle
+                            .into_iter()
+                            .map(|i| self.grammar.rule_at(i).name.clone())
+                            .collect(),
+                    },
+                })
+            })
+            .collect()
+    }
+
+    fn empty_rules(&self) -> Vec<Diagnostic> {
+        self.user_rules()
+            .filter(|&index| self.nullable[index] && !self.consuming[index])
+            .map(|index| {
+                let rule = self.grammar.rule_at(index);
+                Diagnostic {
+                    rule: rule.name.clone(),
+                    span: rule.span,
+                    kind: DiagnosticKind::OnlyMatchesEmpty,
+                }
+            })
+            .collect()
+    }
+
+    /// Work out which rules can match the empty string and which can consume input. Both depend
+    /// on the rules they reference, so iterate until nothing changes.
+    fn compute_fixpoints(&mut self) {
+        loop {
+            let mut changed = false;
+            for (index, rule) in self.grammar.rules().enumerate() {
+                let nullable = self.alternation_is_nullable(&rule.elements);
+                let consuming = self.alternation_consumes(&rule.elements);
+                if nullable != self.nullable[index] || consuming != self.consuming[index] {
+                    self.nullable[index] = nullable;
+                    self.consuming[index] = consuming;
+                    changed = true;
+                }
+            }
+
+            if !changed {
+                break;
+            }
+        }
+    }
+
+    fn alternation_is_nullable(&self, alternation: &Alternation) -> bool {
+        alternation
+            .concatenations
+            .iter()
+            .any(|c| self.concatenation_is_nullable(c))
+    }
+
+    fn concatenation_is_nullable(&self, concatenation: &Concatenation) -> bool {
+        concatenation
+            .repetitions
+            .iter()
+            .all(|r| self.repetition_is_nullable(r))
+    }
+
+    fn repetition_is_nullable(&self, repetition: &Repetition) -> bool {
+        repetition.min == 0 || self.element_is_nullable(&repetition.element)
+    }
+
+    fn element_is_nullable(&self, element: &Element) -> bool {
+        match element {
+            Element::RuleName(name) => self
+                .grammar
+                .index_of(name)
+                .is_some_and(|index| self.nullable[index]),
+            Element::Group(alternation) => self.alternation_is_nullable(alternation),
+            Element::Option(_) => true,
+            Element::CharVal(char_val) => char_val.value.is_empty(),
+            Element::NumVal(_) | Element::ProseVal(_) => false,
+        }
+    }
+
+    fn alternation_consumes(&self, alternation: &Alternation) -> bool {
+        alternation.concatenations.iter().any(|c| {
+            c.repetitions
+                .iter()
+                .any(|r| r.max != Some(0) && self.element_consumes(&r.element))
+        })
+    }
+
+    fn element_consumes(&self, element: &Element) -> bool {
+        match element {
+            Element::RuleName(name) => self
+                .grammar
+                .index_of(name)
+                .is_some_and(|index| self.consuming[index]),
+            Element::Group(alternation) | Element::Option(alternation) => {
+                self.alternation_consumes(alternation)
+            }
+            Element::CharVal(char_val) => !char_val.value.is_empty(),
+            Element::NumVal(_) => true,
+            // Prose can't be matched at all
+            Element::ProseVal(_) => false,
+        }
+    }
+
+    fn left_calls_in_alternation(&self, alternation: &Alternation, calls: &mut Vec<usize>) {
+        for concatenation in &alternation.concatenations {
+            for repetition in &concatenation.repetitions {
+                self.left_calls_in_element(&repetition.element, calls);
+                // Anything after this repetition is only called at the same offset if this
+                // repetition can match nothing
+                if !self.repetition_is_nullable(repetition) {
+                    break;
+                }
+            }
+        }
+    }
+
+    fn left_calls_in_element(&self, element: &Element, calls: &mut Vec<usize>) {
+        match element {
+            Element::RuleName(name) => calls.extend(self.grammar.index_of(name)),
+            Element::Group(alternation) | Element::Option(alternation) => {
+                self.left_calls_in_alternation(alternation, calls)
+            }
+            Element::CharVal(_) | Element::NumVal(_) | Element::ProseVal(_) => {}
+        }
+    }
+}
+
+/// Breadth-first search for the shortest path from `start` back to itself
+fn shortest_cycle(start: usize, edges: &HashMap<usize, Vec<usize>>) -> Option<Vec<usize>> {
+    let mut came_from: HashMap<usize, usize> = HashMap::new();
+    let mut queue = VecDeque::from([start]);
+    while let Some(current) = queue.pop_front() {
+        for &next in edges.get(&current).into_iter().flatten() {
+            if next == start {
+                let mut cycle = vec![start, current];
+                let mut node = current;
+                while node != start {
+                    node = came_from[&node];
+                    cycle.push(node);
+                }
+                cycle.reverse();
+                // A rule calling itself directly would otherwise show up as `a -> a -> a`
+                cycle.dedup();
+                if cycle.len() == 1 {
+                    cycle.push(start);
+                }
+                return Some(cycle);
+            }
+            if let Entry::Vacant(entry) = came_from.entry(next) {
+                entry.insert(current);
+                queue.push_back(next);
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Diagnostic, DiagnosticKind};
+    use crate::ast::Span;
+    use crate::Grammar;
+
+    fn check(source: &str, start_rule: &str) -> Vec<Diagnostic> {
+        Grammar::from_metasyntax(source)
+            .unwrap()
+            .check(start_rule)
+            .unwrap()
+            .diagnostics
+    }
+
+    #[test]
+    fn test_clean_grammar() {
+        let report = Grammar::from_metasyntax(include_str!("../abnf.abnf"))
+            .unwrap()
+            .check("rulelist")
+            .unwrap();
+        assert!(report.is_ok(), "{report}");
+    }
+
+    #[test]
+    fn test_undefined_rules() {
+        let source = "a = b / \"x\" [c]\n";
+        let diagnostics = check(source, "a");
+        assert_eq!(
+            diagnostics,
+            vec![
+                Diagnostic {
+                    rule: "a".to_owned(),
+                    span: Span::new(4, 5),
+                    kind: DiagnosticKind::UndefinedRule {
+                        reference: "b".to_owned()
+                    },
+                },
+                Diagnostic {
+                    rule: "a".to_owned(),
+                    span: Span::new(13, 14),
+                    kind: DiagnosticKind::UndefinedRule {
+                        reference: "c".to_owned()
+                    },
+                },
+            ]
+        );
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "rule `a` references undefined rule `b` (4..5)"
+        );
+        let location = diagnostics[1].location(source).unwrap();
+        assert_eq!((location.line, location.column), (1, 14));
+    }
+
+    #[test]
+    fn test_unreachable_rules() {
+        let source = "a = b\nb = \"b\"\nc = DIGIT\n";
+        let diagnostics = check(source, "a");
+        assert_eq!(
+            diagnostics,
+            vec![Diagnostic {
+                rule: "c".to_owned(),
+                span: Span::new(14, 23),
+                kind: DiagnosticKind::Unreachable,
+            }]
+        );
+        assert_eq!(
+            diagnostics[0].render(source),
+            "rule `c` can't be reached from the start rule at line 3, column 1\n\
+             3 | c = DIGIT\n\
+             \x20 | ^"
+        );
+    }
+
+    #[test]
+    fn test_render_extended_core_rule() {
+        let source = "a = \"a\"\nWSP =/ \"x\"\n";
+        let diagnostics = check(source, "a");
+        assert_eq!(
+            diagnostics[0].render(source),
+            "rule `WSP` can't be reached from the start rule at line 2, column 1\n\
+             2 | WSP =/ \"x\"\n\
+             \x20 | ^"
+        );
+
+        // A span from some other source doesn't have a location in this one
+        let diagnostic = Diagnostic {
+            span: Span::new(1341, 1350),
+            ..diagnostics[0].clone()
+        };
+        assert_eq!(diagnostic.location(source), None);
+        assert_eq!(
+            diagnostic.render(source),
+            "rule `WSP` can't be reached from the start rule (1341..1350)"
+        );
+    }
+
+    #[test]
+    fn test_left_recursion() {
+        // Direct
+        let diagnostics = check("list = list \",\" item / item\nitem = \"x\"\n", "list");
+        assert_eq!(
+            diagnostics
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec!["rule `list` is left-recursive: list -> list (0..27)"]
+        );
+
+        // Indirect, through a rule that can match nothing
+        let source = "a = *\" \" b \"x\"\nb = [c] \"y\"\nc = a\n";
+        let diagnostics = check(source, "a");
+        let cycles: Vec<_> = diagnostics
+            .into_iter()
+            .map(|d| match d.kind {
+                DiagnosticKind::LeftRecursive { cycle } => cycle,
+                kind => panic!("unexpected diagnostic {kind:?}"),
+            })
+            .collect();
+        assert_eq!(
+            cycles,
+            vec![
+                vec!["a", "b", "c", "a"],
+                vec!["b", "c", "a", "b"],
+                vec!["c", "a", "b", "c"],
+            ]
+        );
+
+        // Recursion that isn't leftmost is fine
+        assert!(check("parens = \"(\" [parens] \")\"\n", "parens").is_empty());
+    }
+
+    #[test]
+    fn test_rules_that_only_match_empty() {
+        let source = "a = b c \"a\"\nb = \"\"\nc = *b / [b]\n";
+        let diagnostics = check(source, "a");
+        let rules: Vec<_> = diagnostics
+            .iter()
+            .map(|d| (d.rule.as_str(), &d.kind))
+            .collect();
+        assert_eq!(
+            rules,
+            vec![
+                ("b", &DiagnosticKind::OnlyMatchesEmpty),
+                ("c", &DiagnosticKind::OnlyMatchesEmpty),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unknown_start_rule() {
+        let grammar = Grammar::from_metasyntax("a = \"a\"\n").unwrap();
+        assert!(grammar.check("b").is_err());
+    }
+}
@@ -0,0 +1,479 @@
+//! Generate standalone Rust validators from a [`Grammar`].
+//!
+//! The generated code doesn't depend on `abnf-rs`. For each rule the grammar defines, it contains
+//!
+//! - `pub fn is_valid_<rule>(input: &str) -> bool`
+//! - `pub fn parse_<rule>(input: &str) -> Option<Vec<Capture>>`, which returns the span of every
+//!   rule that matched, outermost first. Core rules (`ALPHA`, `DIGIT`, etc.) aren't captured.
+//!
+//! where `<rule>` is the rule name, lowercased, with `-` replaced by `_`. Matching works the same
+//! way as [`Grammar::match_rule`], backtracking into every way that an element could match, with
+//! the matches of each rule memoized by their starting offset so that ambiguous grammars don't
+//! take exponential time.
+
+use crate::ast::{Alternation, Concatenation, Element, NumValue, Repetition};
+use crate::check::Checker;
+use crate::grammar::Grammar;
+use crate::{Error, ErrorRepr};
+use rust_code_writer::{Crate, Module};
+use std::collections::{BTreeSet, VecDeque};
+use std::fmt::Write;
+
+/// Types and combinators that every generated module needs
+const PRELUDE: &str = r#"// Generated by abnf-rs. Do not edit this by hand.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/// A rule that matched part of the input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capture {
+    pub rule: &'static str,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Every offset that a match could end at, along with the rules that matched on the way
+type Matches = Vec<(usize, Vec<Capture>)>;
+
+/// Matches an element at the given offset
+type Element<'a> = &'a dyn Fn(usize) -> Matches;
+
+/// The matches of each rule that's been tried, by its name and starting offset
+type Memo = RefCell<HashMap<(&'static str, usize), Matches>>;
+
+#[allow(dead_code)]
+fn memoize(memo: &Memo, rule: &'static str, pos: usize, f: impl FnOnce() -> Matches) -> Matches {
+    let memoized = memo.borrow().get(&(rule, pos)).cloned();
+    if let Some(matches) = memoized {
+        return matches;
+    }
+
+    let matches = f();
+    memo.borrow_mut().insert((rule, pos), matches.clone());
+    matches
+}
+
+#[allow(dead_code)]
+fn add(matches: &mut Matches, end: usize, captures: Vec<Capture>) {
+    if !matches.iter().any(|(e, _)| *e == end) {
+        matches.push((end, captures));
+    }
+}
+
+#[allow(dead_code)]
+fn complete(matches: Matches, input: &str) -> Option<Vec<Capture>> {
+    matches
+        .into_iter()
+        .find(|(end, _)| *end == input.len())
+        .map(|(_, captures)| captures)
+}
+
+#[allow(dead_code)]
+fn capture(rule: &'static str, start: usize, matches: Matches) -> Matches {
+    matches
+        .into_iter()
+        .map(|(end, mut captures)| {
+            captures.insert(0, Capture { rule, start, end });
+            (end, captures)
+        })
+        .collect()
+}
+
+#[allow(dead_code)]
+fn alternation(pos: usize, alternatives: &[Element]) -> Matches {
+    let mut matches = Matches::new();
+    for alternative in alternatives {
+        for (end, captures) in alternative(pos) {
+            add(&mut matches, end, captures);
+        }
+    }
+
+    matches
+}
+
+#[allow(dead_code)]
+fn concatenation(pos: usize, repetitions: &[Element]) -> Matches {
+    let mut matches = vec![(pos, Vec::new())];
+    for repetition in repetitions {
+        let mut next = Matches::new();
+        for (start, captures) in &matches {
+            for (end, more) in repetition(*start) {
+                let mut captures = captures.clone();
+                captures.extend(more);
+                add(&mut next, end, captures);
+            }
+        }
+
+        if next.is_empty() {
+            return next;
+        }
+        matches = next;
+    }
+
+    matches
+}
+
+#[allow(dead_code)]
+fn repetition(pos: usize, min: usize, max: Option<usize>, element: Element) -> Matches {
+    let mut matches = Matches::new();
+    let mut frontier = vec![(pos, Vec::new())];
+    // Once the minimum count is reached, offsets that were already reached can't lead anywhere new
+    let mut seen = Vec::new();
+    let mut count = 0;
+
+    loop {
+        if count >= min {
+            for (end, captures) in &frontier {
+                add(&mut matches, *end, captures.clone());
+                seen.push(*end);
+            }
+        }
+        if max == Some(count) {
+            break;
+        }
+
+        let mut next = Matches::new();
+        for (start, captures) in &frontier {
+            for (end, more) in element(*start) {
+                let mut captures = captures.clone();
+                captures.extend(more);
+                add(&mut next, end, captures);
+            }
+        }
+        if count + 1 >= min {
+            next.retain(|(end, _)| !seen.contains(end));
+        }
+        if next.is_empty() {
+            break;
+        }
+
+        frontier = next;
+        count += 1;
+    }
+
+    matches
+}
+
+#[allow(dead_code)]
+fn char_val(input: &str, pos: usize, value: &str, case_sensitive: bool) -> Matches {
+    let end = pos + value.len();
+    match input.as_bytes().get(pos..end) {
+        Some(candidate) if case_sensitive && candidate == value.as_bytes() => {
+            vec![(end, Vec::new())]
+        }
+        Some(candidate) if !case_sensitive && candidate.eq_ignore_ascii_case(value.as_bytes()) => {
+            vec![(end, Vec::new())]
+        }
+        _ => Matches::new(),
+    }
+}
+
+#[allow(dead_code)]
+fn num_sequence(input: &str, pos: usize, values: &[u32]) -> Matches {
+    let mut chars = input[pos..].chars();
+    let mut end = pos;
+    for &value in values {
+        match chars.next() {
+            Some(c) if c as u32 == value => end += c.len_utf8(),
+            _ => return Matches::new(),
+        }
+    }
+
+    vec![(end, Vec::new())]
+}
+
+#[allow(dead_code)]
+fn num_range(input: &str, pos: usize, low: u32, high: u32) -> Matches {
+    match input[pos..].chars().next() {
+        Some(c) if (low..=high).contains(&(c as u32)) => vec![(pos + c.len_utf8(), Vec::new())],
+        _ => Matches::new(),
+    }
+}
+"#;
+
+/// Generate the source of a Rust module that validates input against each rule that `grammar`
+/// defines.
+///
+/// Grammars with undefined or left-recursive rules can't be turned into code.
+pub fn generate(grammar: &Grammar) -> Result<String, Error> {
+    if let Some((rule, reference)) = grammar.undefined_references().into_iter().next() {
+        return Err(Error(ErrorRepr::UndefinedRule {
+            rule: rule.to_owned(),
+            reference: reference.to_owned(),
+        }));
+    }
+    if let Some(diagnostic) = Checker::new(grammar).left_recursive_rules().first() {
+        return Err(Error(ErrorRepr::LeftRecursive(diagnostic.rule.clone())));
+    }
+
+    let mut source = PRELUDE.to_owned();
+    for index in rules_to_generate(grammar) {
+        let rule = grammar.rule_at(index);
+        let ident = to_ident(&rule.name);
+        let is_core_rule = grammar.is_core_rule(&rule.name);
+
+        if !is_core_rule {
+            let name = &rule.name;
+            source.push_str(&format!(
+                "
+/// Whether all of `input` matches the `{name}` rule
+pub fn is_valid_{ident}(input: &str) -> bool {{
+    parse_{ident}(input).is_some()
+}}
+
+/// Match all of `input` against the `{name}` rule, returning the span of every rule that matched,
+/// outermost first
+pub fn parse_{ident}(input: &str) -> Option<Vec<Capture>> {{
+    complete(match_{ident}(input, &Memo::default(), 0), input)
+}}
+"
+            ));
+        }
+
+        let name = &rule.name;
+        let body = alternation_expr(&rule.elements, 2);
+        let body = if is_core_rule {
+            body
+        } else {
+            format!("capture({name:?}, pos, {body})")
+        };
+        source.push_str(&format!(
+            "
+fn match_{ident}(input: &str, memo: &Memo, pos: usize) -> Matches {{
+    memoize(memo, {name:?}, pos, || {{
+        {body}
+    }})
+}}
+"
+        ));
+    }
+
+    Ok(source)
+}
+
+/// Generate validators for `grammar` and write them to `module` of `c`
+pub fn write_to_module(c: &mut Crate, module: Module, grammar: &Grammar) -> Result<(), Error> {
+    let source = generate(grammar)?;
+    let mut writer = c.writer(module);
+    writer
+        .write_str(&source)
+        .map_err(|_| Error::unexpected("failed to write generated validators"))
+}
+
+/// The rules the grammar defines, followed by any core rules that those reference
+fn rules_to_generate(grammar: &Grammar) -> Vec<usize> {
+    let mut rules: Vec<usize> = grammar
+        .rules()
+        .enumerate()
+        .filter(|(_, rule)| !grammar.is_core_rule(&rule.name))
+        .map(|(index, _)| index)
+        .collect();
+
+    let mut core = BTreeSet::new();
+    let mut queue: VecDeque<usize> = rules.iter().copied().collect();
+    while let Some(index) = queue.pop_front() {
+        for (reference, _) in grammar.rule_at(index).elements.rule_references() {
+            let Some(referenced) = grammar.index_of(reference) else {
+                continue;
+            };
+            if grammar.is_core_rule(reference) && core.insert(referenced) {
+                queue.push_back(referenced);
+            }
+        }
+    }
+    rules.extend(core);
+
+    rules
+}
+
+fn to_ident(rule_name: &str) -> String {
+    rule_name.to_ascii_lowercase().replace('-', "_")
+}
+
+fn indentation(level: usize) -> String {
+    "    ".repeat(level)
+}
+
+/// A list of elements, one per line, for passing to a combinator
+fn element_list(elements: Vec<String>, indent: usize) -> String {
+    let mut list = "&[\n".to_owned();
+    for element in elements {
+        let _ = writeln!(list, "{}&|pos| {element},", indentation(indent + 1));
+    }
+    list.push_str(&indentation(indent));
+    list.push(']');
+
+    list
+}
+
+fn alternation_expr(alternation: &Alternation, indent: usize) -> String {
+    match alternation.concatenations.as_slice() {
+        [concatenation] => concatenation_expr(concatenation, indent),
+        concatenations => {
+            let alternatives = concatenations
+                .iter()
+                .map(|c| concatenation_expr(c, indent + 1))
+                .collect();
+            format!("alternation(pos, {})", element_list(alternatives, indent))
+        }
+    }
+}
+
+fn concatenation_expr(concatenation: &Concatenation, indent: usize) -> String {
+    match concatenation.repetitions.as_slice() {
+        [repetition] => repetition_expr(repetition, indent),
+        repetitions => {
+            let repetitions = repetitions
+                .iter()
+                .map(|r| repetition_expr(r, indent + 1))
+                .collect();
+            format!("concatenation(pos, {})", element_list(repetitions, indent))
+        }
+    }
+}
+
+fn repetition_expr(repetition: &Repetition, indent: usize) -> String {
+    match (repetition.min, repetition.max) {
+        (1, Some(1)) => element_expr(&repetition.element, indent),
+        (min, max) => format!(
+            "repetition(pos, {min}, {max:?}, &|pos| {})",
+            element_expr(&repetition.element, indent)
+        ),
+    }
+}
+
+fn element_expr(element: &Element, indent: usize) -> String {
+    match element {
+        Element::RuleName(name) => format!("match_{}(input, memo, pos)", to_ident(name)),
+        Element::Group(alternation) => alternation_expr(alternation, indent),
+        Element::Option(alternation) => format!(
+            "repetition(pos, 0, Some(1), &|pos| {})",
+            alternation_expr(alternation, indent)
+        ),
+        Element::CharVal(char_val) => format!(
+            "char_val(input, pos, {:?}, {})",
+            char_val.value, char_val.case_sensitive
+        ),
+        Element::NumVal(num_val) => match &num_val.value {
+            NumValue::Sequence(values) => {
+                let values: Vec<_> = values.iter().map(|v| format!("{v:#X}")).collect();
+                format!("num_sequence(input, pos, &[{}])", values.join(", "))
+            }
+            NumValue::Range(low, high) => format!("num_range(input, pos, {low:#X}, {high:#X})"),
+        },
+        // Prose is meant for humans, there's no way to match it
+        Element::ProseVal(_) => "Matches::new()".to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate, write_to_module};
+    use crate::Grammar;
+    use pretty_assertions::assert_eq;
+    use rust_code_writer::{Crate, CrateMetadata, Module, Parent, Visibility};
+    use std::borrow::Cow;
+
+    const NSID_ABNF: &str = r#"nsid      = authority "." name
+authority = segment *( "." segment )
+name      = segment
+segment   = ALPHA *( ALPHA / DIGIT / "-" )
+"#;
+
+    // The output of `generate` for `NSID_ABNF`, compiled into the tests so that we know it works
+    #[allow(dead_code)]
+    mod nsid {
+        include!("../testdata/codegen_nsid.rs");
+    }
+
+    #[test]
+    fn test_generated_code_is_stable() {
+        let grammar = Grammar::from_metasyntax(NSID_ABNF).unwrap();
+        assert_eq!(
+            generate(&grammar).unwrap(),
+            include_str!("../testdata/codegen_nsid.rs")
+        );
+    }
+
+    #[test]
+    fn test_generated_validators() {
+        assert!(nsid::is_valid_nsid("com.example.status"));
+        assert!(nsid::is_valid_nsid("io.social.getFeed"));
+        assert!(!nsid::is_valid_nsid("com"));
+        assert!(!nsid::is_valid_nsid("com..example.status"));
+        assert!(!nsid::is_valid_nsid("9com.example.status"));
+        assert!(nsid::is_valid_segment("get-Feed2"));
+
+        let captures = nsid::parse_nsid("com.example.status").unwrap();
+        let spans: Vec<_> = captures.iter().map(|c| (c.rule, c.start, c.end)).collect();
+        assert_eq!(
+            spans,
+            vec![
+                ("nsid", 0, 18),
+                ("authority", 0, 11),
+                ("segment", 0, 3),
+                ("segment", 4, 11),
+                ("name", 12, 18),
+                ("segment", 12, 18),
+            ]
+        );
+        assert!(nsid::parse_nsid("com.example.*").is_none());
+    }
+
+    fn validators_crate() -> Crate {
+        Crate::new(CrateMetadata {
+            name: "validators".to_owned(),
+            description: "Generated validators".to_owned(),
+            version: "0.1.0".to_owned(),
+            authors: vec![],
+        })
+    }
+
+    #[test]
+    fn test_write_to_module() {
+        let grammar = Grammar::from_metasyntax(NSID_ABNF).unwrap();
+        let module = Module {
+            name: Cow::Borrowed("nsid"),
+            documentation: Cow::Borrowed("NSID validators"),
+            parent: Parent::Lib,
+            dependencies: vec![],
+            visibility: Visibility::Public,
+        };
+        let mut c = validators_crate();
+        write_to_module(&mut c, module.clone(), &grammar).unwrap();
+
+        let dir = std::env::temp_dir().join(format!("abnf-rs-codegen-{}", std::process::id()));
+        c.finalize(&dir).unwrap();
+        let written = std::fs::read_to_string(dir.join("validators").join(module.to_file_path()));
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            written.unwrap(),
+            include_str!("../testdata/codegen_nsid.rs")
+        );
+
+        let grammar = Grammar::from_metasyntax("a = a \"x\" / \"x\"\n").unwrap();
+        let mut c = validators_crate();
+        assert_eq!(
+            write_to_module(&mut c, module, &grammar)
+                .unwrap_err()
+                .to_string(),
+            "can't generate code for left-recursive rule `a`"
+        );
+    }
+
+    #[test]
+    fn test_generate_errors() {
+        let grammar = Grammar::from_metasyntax("a = b\n").unwrap();
+        assert_eq!(
+            generate(&grammar).unwrap_err().to_string(),
+            "rule `a` references undefined rule `b`"
+        );
+
+        let grammar = Grammar::from_metasyntax("list = list \",\" \"x\" / \"x\"\n").unwrap();
+        assert_eq!(
+            generate(&grammar).unwrap_err().to_string(),
+            "can't generate code for left-recursive rule `list`"
+        );
+    }
+}
@@ -0,0 +1,69 @@
+use crate::ast::Rule;
+use crate::parser;
+use once_cell::sync::Lazy;
+
+// https://www.rfc-editor.org/rfc/rfc5234#appendix-B.1
+static CORE_RULES: Lazy<Vec<Rule>> = Lazy::new(|| {
+    parser::parse(include_str!("../core.abnf"))
+        .expect("core rules are valid ABNF")
+        .rules
+});
+
+/// The core rules that RFC 5234 grammars may reference without defining, e.g. `ALPHA` and `DIGIT`
+pub(crate) fn core_rules() -> impl Iterator<Item = &'static Rule> {
+    CORE_RULES.iter()
+}
+
+pub(crate) fn core_rule(name: &str) -> Option<&'static Rule> {
+    CORE_RULES
+        .iter()
+        .find(|rule| rule.name.eq_ignore_ascii_case(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::core_rule;
+    use crate::ast::{RuleList, Span};
+    use crate::Grammar;
+
+    fn grammar_without_rules() -> Grammar {
+        Grammar::from_rule_list(RuleList {
+            rules: Vec::new(),
+            comments: Vec::new(),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_core_rules() {
+        let g = grammar_without_rules();
+        assert!(g.match_rule("ALPHA", "q").is_ok());
+        assert!(g.match_rule("alpha", "Q").is_ok());
+        assert!(g.match_rule("DIGIT", "7").is_ok());
+        assert!(g.match_rule("HEXDIG", "f").is_ok());
+        assert!(g.match_rule("HEXDIG", "g").is_err());
+        assert!(g.match_rule("CRLF", "\r\n").is_ok());
+        assert!(g.match_rule("DQUOTE", "\"").is_ok());
+        assert!(g.match_rule("WSP", "\t").is_ok());
+        assert!(g.match_rule("VCHAR", "~").is_ok());
+        assert!(g.match_rule("VCHAR", " ").is_err());
+        assert!(g.match_rule("LWSP", " \r\n\t").is_ok());
+    }
+
+    #[test]
+    fn test_extended_core_rule_span() {
+        let source = "a = \"a\"\nWSP =/ \"x\"\n";
+        let g = Grammar::from_metasyntax(source).unwrap();
+        let wsp = g.rule("WSP").unwrap();
+        assert_eq!(wsp.span, Span::new(8, 18));
+        assert_eq!(&source[wsp.span.start..wsp.span.end], "WSP =/ \"x\"");
+        assert!(g.match_rule("WSP", "x").is_ok());
+        assert!(g.match_rule("WSP", " ").is_ok());
+    }
+
+    #[test]
+    fn test_core_rule_lookup() {
+        assert!(core_rule("octet").is_some());
+        assert!(core_rule("segment").is_none());
+    }
+}
@@ -0,0 +1,428 @@
+use crate::ast::{Alternation, Concatenation, Element, NumValue, Repetition};
+use crate::grammar::Grammar;
+use crate::matcher::Matcher;
+use crate::{Error, ErrorRepr};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// How many times [`Generator::near_miss`] mutates matching input before giving up
+const NEAR_MISS_ATTEMPTS: usize = 100;
+
+/// Generates random input for the rules of a [`Grammar`], for property testing code that's meant
+/// to accept the same input as the grammar.
+///
+/// ```
+/// use abnf_rs::{Generator, Grammar};
+///
+/// let grammar = Grammar::from_metasyntax("number = 1*DIGIT\n").unwrap();
+/// let mut generator = Generator::from_seed(&grammar, 7);
+///
+/// let number = generator.matching("number").unwrap();
+/// assert!(number.parse::<u64>().is_ok());
+/// let not_a_number = generator.near_miss("number").unwrap();
+/// assert!(grammar.match_rule("number", &not_a_number).is_err());
+/// ```
+pub struct Generator<'g, R> {
+    grammar: &'g Grammar,
+    rng: R,
+    max_depth: usize,
+    max_repetitions: usize,
+    // For each rule, the fewest nested rule references it takes to generate something for it, or
+    // `None` if it can't be generated at all
+    depths: Vec<Option<usize>>,
+}
+
+impl<'g> Generator<'g, StdRng> {
+    /// A generator whose output is determined entirely by `seed`
+    pub fn from_seed(grammar: &'g Grammar, seed: u64) -> Self {
+        Self::new(grammar, StdRng::seed_from_u64(seed))
+    }
+}
+
+impl<'g, R: Rng> Generator<'g, R> {
+    pub fn new(grammar: &'g Grammar, rng: R) -> Self {
+        let mut generator = Self {
+            grammar,
+            rng,
+            max_depth: 8,
+            max_repetitions: 4,
+            depths: vec![None; grammar.rules().count()],
+        };
+        generator.compute_depths();
+
+        generator
+    }
+
+    /// How deeply rule references can nest before the generator only takes the shortest way out.
+    /// Defaults to 8.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// How many times an unbounded repetition like `*ALPHA` can repeat beyond its minimum.
+    /// Defaults to 4.
+    pub fn max_repetitions(mut self, max_repetitions: usize) -> Self {
+        self.max_repetitions = max_repetitions;
+        self
+    }
+
+    /// Random input that matches the rule named `rule`
+    pub fn matching(&mut self, rule: &str) -> Result<String, Error> {
+        let rule_index = self
+            .grammar
+            .index_of(rule)
+            .ok_or_else(|| Error(ErrorRepr::UnknownRule(rule.to_owned())))?;
+        if self.depths[rule_index].is_none() {
+            return Err(Error(ErrorRepr::Ungenerable(rule.to_owned())));
+        }
+
+        let mut out = String::new();
+        self.generate_rule(rule_index, self.max_depth, &mut out)?;
+
+        Ok(out)
+    }
+
+    /// Random input that doesn't match the rule named `rule`, made by changing a character or two
+    /// of input that does.
+    ///
+    /// Some rules, like `*CHAR`, match nearly everything. If none of the changes tried stop the
+    /// input from matching, this returns an error.
+    pub fn near_miss(&mut self, rule: &str) -> Result<String, Error> {
+        let rule_index = self
+            .grammar
+            .index_of(rule)
+            .ok_or_else(|| Error(ErrorRepr::UnknownRule(rule.to_owned())))?;
+
+        for _ in 0..NEAR_MISS_ATTEMPTS {
+            let matching = self.matching(rule)?;
+            let mutated = self.mutate(&matching);
+            if Matcher::new(self.grammar, &mutated)
+                .match_all(rule_index)
+                .is_err()
+            {
+                return Ok(mutated);
+            }
+        }
+
+        Err(Error(ErrorRepr::NoNearMiss(rule.to_owned())))
+    }
+
+    fn mutate(&mut self, input: &str) -> String {
+        let mut chars: Vec<char> = input.chars().collect();
+        if chars.is_empty() {
+            return self.random_char().to_string();
+        }
+
+        let i = self.rng.gen_range(0..chars.len());
+        match self.rng.gen_range(0..5) {
+            0 => {
+                chars.remove(i);
+            }
+            1 => {
+                let c = self.random_char();
+                chars.insert(self.rng.gen_range(0..=chars.len()), c);
+            }
+            2 => chars[i] = self.random_char(),
+            3 => {
+                let c = chars[i];
+                chars.insert(i, c);
+            }
+            _ if chars.len() > 1 => {
+                let next = (i + 1) % chars.len();
+                chars.swap(i, next);
+            }
+            _ => chars.clear(),
+        }
+
+        chars.into_iter().collect()
+    }
+
+    /// A printable ASCII character, or occasionally a tab
+    fn random_char(&mut self) -> char {
+        if self.rng.gen_bool(0.05) {
+            '\t'
+        } else {
+            self.rng.gen_range(' '..='~')
+        }
+    }
+
+    /// Work out the depth of each rule. Each depends on the rules it references, so iterate until
+    /// nothing changes.
+    fn compute_depths(&mut self) {
+        loop {
+            let mut changed = false;
+            for (index, rule) in self.grammar.rules().enumerate() {
+                let depth = self.alternation_depth(&rule.elements);
+                if depth != self.depths[index] {
+                    self.depths[index] = depth;
+                    changed = true;
+                }
+            }
+
+            if !changed {
+                break;
+            }
+        }
+    }
+
+    fn alternation_depth(&self, alternation: &Alternation) -> Option<usize> {
+        alternation
+            .concatenations
+            .iter()
+            .filter_map(|c| self.concatenation_depth(c))
+            .min()
+    }
+
+    fn concatenation_depth(&self, concatenation: &Concatenation) -> Option<usize> {
+        concatenation
+            .repetitions
+            .iter()
+            .map(|r| self.repetition_depth(r))
+            .try_fold(0, |depth, r| Some(depth.max(r?)))
+    }
+
+    fn repetition_depth(&self, repetition: &Repetition) -> Option<usize> {
+        if repetition.min == 0 {
+            Some(0)
+        } else {
+            self.element_depth(&repetition.element)
+        }
+    }
+
+    fn element_depth(&self, element: &Element) -> Option<usize> {
+        match element {
+            Element::RuleName(name) => Some(self.depths[self.grammar.index_of(name)?]? + 1),
+            Element::Group(alternation) => self.alternation_depth(alternation),
+            Element::Option(_) => Some(0),
+            Element::CharVal(_) => Some(0),
+            Element::NumVal(num_val) => match &num_val.value {
+                NumValue::Sequence(values) => values
+                    .iter()
+                    .all(|&v| char::from_u32(v).is_some())
+                    .then_some(0),
+                NumValue::Range(low, high) => range_char(*low, *high).map(|_| 0),
+            },
+            Element::ProseVal(_) => None,
+        }
+    }
+
+    fn generate_rule(
+        &mut self,
+        index: usize,
+        budget: usize,
+        out: &mut String,
+    ) -> Result<(), Error> {
+        let rule = self.grammar.rule_at(index);
+        self.generate_alternation(&rule.elements, budget, out)
+    }
+
+    fn generate_alternation(
+        &mut self,
+        alternation: &'g Alternation,
+        budget: usize,
+        out: &mut String,
+    ) -> Result<(), Error> {
+        let depths: Vec<_> = alternation
+            .concatenations
+            .iter()
+            .map(|c| self.concatenation_depth(c))
+            .collect();
+        // Anything that fits in the budget, otherwise the shortest way out
+        let mut candidates: Vec<usize> = (0..depths.len())
+            .filter(|&i| depths[i].is_some_and(|depth| depth <= budget))
+            .collect();
+        if candidates.is_empty() {
+            let shortest = depths
+                .iter()
+                .flatten()
+                .min()
+                .ok_or_else(|| Error::unexpected("no alternative can be generated"))?;
+            candidates = (0..depths.len())
+                .filter(|&i| depths[i] == Some(*shortest))
+                .collect();
+        }
+
+        let chosen = candidates[self.rng.gen_range(0..candidates.len())];
+        for repetition in &alternation.concatenations[chosen].repetitions {
+            self.generate_repetition(repetition, budget, out)?;
+        }
+
+        Ok(())
+    }
+
+    fn generate_repetition(
+        &mut self,
+        repetition: &'g Repetition,
+        budget: usize,
+        out: &mut String,
+    ) -> Result<(), Error> {
+        let fits = self
+            .element_depth(&repetition.element)
+            .is_some_and(|depth| depth <= budget);
+        let count = if fits {
+            let most = repetition
+                .max
+                .unwrap_or(usize::MAX)
+                .min(repetition.min.saturating_add(self.max_repetitions))
+                .max(repetition.min);
+            self.rng.gen_range(repetition.min..=most)
+        } else {
+            repetition.min
+        };
+
+        for _ in 0..count {
+            self.generate_element(&repetition.element, budget, out)?;
+        }
+
+        Ok(())
+    }
+
+    fn generate_element(
+        &mut self,
+        element: &'g Element,
+        budget: usize,
+        out: &mut String,
+    ) -> Result<(), Error> {
+        match element {
+            Element::RuleName(name) => {
+                let index = self
+                    .grammar
+                    .index_of(name)
+                    .ok_or_else(|| Error::unexpected("generating an undefined rule"))?;
+                self.generate_rule(index, budget.saturating_sub(1), out)?;
+            }
+            Element::Group(alternation) => self.generate_alternation(alternation, budget, out)?,
+            Element::Option(alternation) => {
+                let fits = self
+                    .alternation_depth(alternation)
+                    .is_some_and(|depth| depth <= budget);
+                if fits && self.rng.gen_bool(0.5) {
+                    self.generate_alternation(alternation, budget, out)?;
+                }
+            }
+            Element::CharVal(char_val) => {
+                for c in char_val.value.chars() {
+                    if !char_val.case_sensitive && self.rng.gen_bool(0.5) {
+                        out.push(swap_case(c));
+                    } else {
+                        out.push(c);
+                    }
+                }
+            }
+            Element::NumVal(num_val) => match &num_val.value {
+                NumValue::Sequence(values) => {
+                    for &value in values {
+                        out.push(
+                            char::from_u32(value)
+                                .ok_or_else(|| Error::unexpected("num-val isn't a character"))?,
+                        );
+                    }
+                }
+                NumValue::Range(low, high) => {
+                    let value = self.rng.gen_range(*low..=*high);
+                    let c = char::from_u32(value)
+                        .or_else(|| range_char(*low, *high))
+                        .ok_or_else(|| Error::unexpected("num-val range has no characters"))?;
+                    out.push(c);
+                }
+            },
+            Element::ProseVal(_) => return Err(Error::unexpected("generating a prose value")),
+        }
+
+        Ok(())
+    }
+}
+
+/// A character in the range `low..=high`, if there is one. The only values in range that aren't
+/// characters are surrogates, so one of the ends will do.
+fn range_char(low: u32, high: u32) -> Option<char> {
+    char::from_u32(low).or_else(|| char::from_u32(high))
+}
+
+fn swap_case(c: char) -> char {
+    if c.is_ascii_uppercase() {
+        c.to_ascii_lowercase()
+    } else {
+        c.to_ascii_uppercase()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Generator;
+    use crate::Grammar;
+
+    const NSID_ABNF: &str = r#"nsid      = authority "." name
+authority = segment *( "." segment )
+name      = segment
+segment   = ALPHA *( ALPHA / DIGIT / "-" )
+"#;
+
+    #[test]
+    fn test_matching_and_near_misses() {
+        let grammar = Grammar::from_metasyntax(NSID_ABNF).unwrap();
+        let mut generator = Generator::from_seed(&grammar, 1);
+
+        for _ in 0..100 {
+            let nsid = generator.matching("nsid").unwrap();
+            assert!(grammar.match_rule("nsid", &nsid).is_ok(), "{nsid:?}");
+
+            let near_miss = generator.near_miss("nsid").unwrap();
+            assert!(
+                grammar.match_rule("nsid", &near_miss).is_err(),
+                "{near_miss:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_seeded_output_is_repeatable() {
+        let grammar = Grammar::from_metasyntax(NSID_ABNF).unwrap();
+        let generate = |seed| {
+            let mut generator = Generator::from_seed(&grammar, seed);
+            (0..10)
+                .map(|_| generator.near_miss("nsid").unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(generate(42), generate(42));
+        assert_ne!(generate(42), generate(43));
+    }
+
+    #[test]
+    fn test_recursion_is_bounded() {
+        let grammar = Grammar::from_metasyntax("list = \"(\" *list \")\"\n").unwrap();
+        let mut generator = Generator::from_seed(&grammar, 3)
+            .max_depth(3)
+            .max_repetitions(2);
+
+        for _ in 0..20 {
+            let list = generator.matching("list").unwrap();
+            assert!(grammar.match_rule("list", &list).is_ok(), "{list:?}");
+            // 1 + 2 + 4 + 8 pairs of parentheses at most
+            assert!(list.len() <= 30, "{list:?}");
+        }
+    }
+
+    #[test]
+    fn test_generator_errors() {
+        let grammar =
+            Grammar::from_metasyntax("forever = \"x\" forever\nanything = *CHAR\n").unwrap();
+        let mut generator = Generator::from_seed(&grammar, 0);
+
+        assert_eq!(
+            generator.matching("forever").unwrap_err().to_string(),
+            "can't generate input for rule `forever`, it recurses forever or needs prose or an \
+             undefined rule"
+        );
+        assert_eq!(
+            generator.near_miss("anything").unwrap_err().to_string(),
+            "couldn't find input near to rule `anything` that doesn't match it"
+        );
+        assert_eq!(
+            generator.matching("nothing").unwrap_err().to_string(),
+            "rule `nothing` isn't defined in the grammar"
+        );
+    }
+}
@@ -0,0 +1,189 @@
+use crate::ast::{DefinedAs, Rule, RuleList};
+use crate::check::{Checker, Report};
+use crate::core_rules::{core_rule, core_rules};
+use crate::matcher::Matcher;
+use crate::parse_tree::ParseTree;
+use crate::regex_export::rule_to_regex;
+use crate::{parser, Error, ErrorRepr};
+use std::collections::{HashMap, HashSet};
+
+/// A set of ABNF rules that input can be matched against.
+///
+/// Rule names are case-insensitive, as per https://www.rfc-editor.org/rfc/rfc5234#section-2.1
+///
+/// The core rules from https://www.rfc-editor.org/rfc/rfc5234#appendix-B.1 (`ALPHA`, `DIGIT`,
+/// `CRLF`, etc.) are always available. Defining a rule with the same name as a core rule replaces
+/// it.
+#[derive(Debug, Clone)]
+pub struct Grammar {
+    rules: Vec<Rule>,
+    index: HashMap<String, usize>,
+    // Names (lowercased) of the core rules that were added because the grammar didn't define them
+    core: HashSet<String>,
+}
+
+impl Grammar {
+    /// Parse an ABNF rule list into a grammar
+    pub fn from_metasyntax(metasyntax: &str) -> Result<Self, Error> {
+        let rule_list = parser::parse(metasyntax).map_err(ErrorRepr::from)?;
+
+        Self::from_rule_list(rule_list)
+    }
+
+    /// Create a grammar from a parsed rule list. Incremental alternatives (`=/`) are merged into
+    /// the rule they extend.
+    pub fn from_rule_list(rule_list: RuleList) -> Result<Self, Error> {
+        let mut grammar = Self {
+            rules: Vec::with_capacity(rule_list.rules.len()),
+            index: HashMap::new(),
+            core: HashSet::new(),
+        };
+
+        for rule in rule_list.rules {
+            grammar.add_rule(rule)?;
+        }
+
+        for rule in core_rules() {
+            let key = rule.name.to_ascii_lowercase();
+            if !grammar.index.contains_key(&key) {
+                grammar.index.insert(key.clone(), grammar.rules.len());
+                grammar.rules.push(rule.clone());
+                grammar.core.insert(key);
+            }
+        }
+
+        Ok(grammar)
+    }
+
+    fn add_rule(&mut self, rule: Rule) -> Result<(), Error> {
+        let key = rule.name.to_ascii_lowercase();
+        match (self.index.get(&key), rule.defined_as) {
+            (None, DefinedAs::Basic) => {
+                self.index.insert(key, self.rules.len());
+                self.rules.push(rule);
+            }
+            (Some(&existing), DefinedAs::Incremental) => {
+                let existing = &mut self.rules[existing].elements;
+                existing.concatenations.extend(rule.elements.concatenations);
+            }
+            (Some(_), DefinedAs::Basic) => {
+                return Err(Error(ErrorRepr::DuplicateRule(rule.name)));
+            }
+            // Extending a core rule, e.g. `ALPHA =/ "_"`
+            (None, DefinedAs::Incremental) => match core_rule(&key) {
+                Some(core) => {
+                    // The core rule's span is in core.abnf, so point at the extension instead
+                    let mut core = core.clone();
+                    core.elements
+                        .concatenations
+                        .extend(rule.elements.concatenations);
+                    core.span = rule.span;
+                    core.comments = rule.comments;
+                    self.index.insert(key, self.rules.len());
+                    self.rules.push(core);
+                }
+                None => return Err(Error(ErrorRepr::IncrementalWithoutBase(rule.name))),
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Look up a rule by name, ignoring case
+    pub fn rule(&self, name: &str) -> Option<&Rule> {
+        self.index_of(name).map(|i| &self.rules[i])
+    }
+
+    pub(crate) fn index_of(&self, name: &str) -> Option<usize> {
+        self.index.get(&name.to_ascii_lowercase()).copied()
+    }
+
+    pub(crate) fn rule_at(&self, index: usize) -> &Rule {
+        &self.rules[index]
+    }
+
+    /// All rules in the order they were first defined, followed by any core rules that the grammar
+    /// didn't define itself
+    pub fn rules(&self) -> impl Iterator<Item = &Rule> {
+        self.rules.iter()
+    }
+
+    /// Pairs of `(rule, reference)` for every reference to a rule that isn't defined
+    pub(crate) fn undefined_references(&self) -> Vec<(&str, &str)> {
+        self.rules
+            .iter()
+            .flat_map(|rule| {
+                rule.elements
+                    .rule_references()
+                    .into_iter()
+                    .filter(|(reference, _)| self.index_of(reference).is_none())
+                    .map(move |(reference, _)| (rule.name.as_str(), reference))
+            })
+            .collect()
+    }
+
+    /// Whether `name` refers to a built-in core rule rather than one defined by the grammar
+    pub fn is_core_rule(&self, name: &str) -> bool {
+        self.core.contains(&name.to_ascii_lowercase())
+    }
+
+    /// Look for problems in the rules the grammar defines, taking `start_rule` as the rule input
+    /// will be matched against. See [`DiagnosticKind`](crate::DiagnosticKind) for what's checked.
+    ///
+    /// Core rules that the grammar didn't define itself are never reported.
+    pub fn check(&self, start_rule: &str) -> Result<Report, Error> {
+        let rule_index = self
+            .index_of(start_rule)
+            .ok_or_else(|| Error(ErrorRepr::UnknownStartRule(start_rule.to_owned())))?;
+
+        Ok(Checker::new(self).check(rule_index))
+    }
+
+    /// Check that the entirety of `input` matches the rule named `start_rule`.
+    ///
+    /// On failure, the error reports the furthest byte offset that matching reached and the stack
+    /// of rules that were being matched at that point.
+    pub fn match_rule(&self, start_rule: &str, input: &str) -> Result<(), Error> {
+        let rule_index = self
+            .index_of(start_rule)
+            .ok_or_else(|| Error(ErrorRepr::UnknownStartRule(start_rule.to_owned())))?;
+
+        Matcher::new(self, input).match_all(rule_index)
+    }
+
+    /// Like [`Grammar::match_rule`], but for input that isn't necessarily UTF-8.
+    ///
+    /// Each num-val value matches a single byte, so `%x00-FF` matches any byte and values above
+    /// `FF` never match. Quoted strings match their ASCII bytes, the same as for text. For ASCII
+    /// input the result is the same as [`Grammar::match_rule`]; otherwise, text that should match
+    /// a num-val like `%x2603` has to be matched as its UTF-8 encoding, `%xE2.98.83`.
+    pub fn match_bytes(&self, start_rule: &str, input: &[u8]) -> Result<(), Error> {
+        let rule_index = self
+            .index_of(start_rule)
+            .ok_or_else(|| Error(ErrorRepr::UnknownStartRule(start_rule.to_owned())))?;
+
+        Matcher::from_bytes(self, input).match_all(rule_index)
+    }
+
+    /// Like [`Grammar::match_rule`], but on success returns the tree of rules that matched
+    pub fn parse_rule<'i>(&self, start_rule: &str, input: &'i str) -> Result<ParseTree<'i>, Error> {
+        let rule_index = self
+            .index_of(start_rule)
+            .ok_or_else(|| Error(ErrorRepr::UnknownStartRule(start_rule.to_owned())))?;
+
+        Matcher::new(self, input).parse_all(rule_index)
+    }
+
+    /// An anchored regex, in the syntax of the `regex` crate, that matches the same input as the
+    /// rule named `rule`.
+    ///
+    /// Every rule that `rule` references is inlined, so this fails if any of them are recursive or
+    /// contain prose.
+    pub fn to_regex(&self, rule: &str) -> Result<String, Error> {
+        let rule_index = self
+            .index_of(rule)
+            .ok_or_else(|| Error(ErrorRepr::UnknownRule(rule.to_owned())))?;
+
+        rule_to_regex(self, rule_index)
+    }
+}
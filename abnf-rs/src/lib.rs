@@ -1,32 +1,137 @@
-mod parser;
+pub mod ast;
+mod check;
+pub mod codegen;
+mod core_rules;
+mod generator;
+mod grammar;
+mod matcher;
+mod parse_tree;
+pub mod parser;
+pub mod printer;
+mod regex_export;
 
+pub use check::{Diagnostic, DiagnosticKind, Report};
+pub use generator::Generator;
+pub use grammar::Grammar;
+pub use parse_tree::{FromParseTree, ParseTree};
+
+use ast::DefinedAs;
+
+/// A grammar with a chosen start rule that input can be parsed with
 pub struct Abnf<T> {
+    grammar: Grammar,
+    start_rule: String,
     _marker: std::marker::PhantomData<T>,
 }
 
 impl<T> Abnf<T> {
-    pub fn from_metasyntax(metasyntax: &str) -> Result<Self, Error> {
-        let _ = metasyntax;
-        todo!()
+    /// Compile an ABNF rule list, matching input against the rule named `start_rule`
+    pub fn from_metasyntax(metasyntax: &str, start_rule: &str) -> Result<Self, Error> {
+        let grammar = Grammar::from_metasyntax(metasyntax)?;
+
+        Self::new(grammar, start_rule)
+    }
+
+    fn new(grammar: Grammar, start_rule: &str) -> Result<Self, Error> {
+        if let Some((rule, reference)) = grammar.undefined_references().into_iter().next() {
+            return Err(Error(ErrorRepr::UndefinedRule {
+                rule: rule.to_owned(),
+                reference: reference.to_owned(),
+            }));
+        }
+        if grammar.rule(start_rule).is_none() {
+            return Err(Error(ErrorRepr::UnknownStartRule(start_rule.to_owned())));
+        }
+
+        Ok(Self {
+            grammar,
+            start_rule: start_rule.to_owned(),
+            _marker: std::marker::PhantomData,
+        })
+    }
+
+    /// Match all of `input` against the start rule, then build a `T` from the rules that matched
+    pub fn parse(&self, input: &str) -> Result<T, Error>
+    where
+        T: FromParseTree,
+    {
+        T::from_parse_tree(&self.parse_tree(input)?)
     }
 
-    pub fn parse(&self, _input: &str) -> Result<T, Error> {
-        todo!()
+    /// Match all of `input` against the start rule, returning the rules that matched
+    pub fn parse_tree<'i>(&self, input: &'i str) -> Result<ParseTree<'i>, Error> {
+        self.grammar.parse_rule(&self.start_rule, input)
     }
 
+    pub fn grammar(&self) -> &Grammar {
+        &self.grammar
+    }
+}
+
+impl Abnf<()> {
     pub fn builder() -> Builder {
         Builder::default()
     }
 }
 
+/// Define a grammar one rule at a time.
+///
+/// Rules are written the same way they would be in a rule list, minus the rule name and `=`.
+/// Each rule must fit on one line. Nothing is parsed until [`Builder::build`] is called.
 #[derive(Default)]
-pub struct Builder {}
+pub struct Builder {
+    rules: Vec<(String, DefinedAs, String)>,
+    start_rule: Option<String>,
+}
 
-// impl<T> Builder {
-//     pub fn build(self) -> Result<Abnf<T>, Error> {
-//         Ok(Abnf { inner: () })
-//     }
-// }
+impl Builder {
+    /// Define a rule, like `name = elements`
+    pub fn rule(mut self, name: impl Into<String>, elements: impl Into<String>) -> Self {
+        self.rules
+            .push((name.into(), DefinedAs::Basic, elements.into()));
+        self
+    }
+
+    /// Add alternatives to a rule that was already defined, like `name =/ elements`
+    pub fn alternative(mut self, name: impl Into<String>, elements: impl Into<String>) -> Self {
+        self.rules
+            .push((name.into(), DefinedAs::Incremental, elements.into()));
+        self
+    }
+
+    /// Set the rule that input will be matched against. Defaults to the first rule defined.
+    pub fn start_rule(mut self, start_rule: impl Into<String>) -> Self {
+        self.start_rule = Some(start_rule.into());
+        self
+    }
+
+    /// Write the rules out as a rule list, one line per rule
+    fn to_metasyntax(&self) -> Result<String, Error> {
+        let mut metasyntax = String::new();
+        for (name, defined_as, elements) in &self.rules {
+            // A line break could end the rule early and define another one
+            if name.contains(['\r', '\n']) || elements.contains(['\r', '\n']) {
+                return Err(Error(ErrorRepr::LineBreak(
+                    name.lines().next().unwrap_or_default().to_owned(),
+                )));
+            }
+            metasyntax.push_str(&format!("{name} {defined_as} {elements}\n"));
+        }
+
+        Ok(metasyntax)
+    }
+
+    pub fn build<T>(self) -> Result<Abnf<T>, Error> {
+        let start_rule = self
+            .start_rule
+            .clone()
+            .or_else(|| self.rules.first().map(|(name, ..)| name.clone()))
+            .ok_or(Error(ErrorRepr::NoRules))?;
+        let grammar = Grammar::from_metasyntax(&self.to_metasyntax()?)?;
+
+        Abnf::new(grammar, &start_rule)
+    }
+}
 
 #[derive(thiserror::Error, Debug)]
 #[error(transparent)]
@@ -36,90 +141,308 @@ impl Error {
     pub fn unexpected(message: &'static str) -> Self {
         Self(ErrorRepr::Unexpected(message))
     }
+
+    /// For input that didn't match, the furthest byte offset that matching reached
+    pub fn offset(&self) -> Option<usize> {
+        match &self.0 {
+            ErrorRepr::NoMatch { offset, .. } => Some(*offset),
+            _ => None,
+        }
+    }
+
+    /// For input that didn't match, the rules that were being matched at [`Error::offset`],
+    /// outermost first
+    pub fn rule_stack(&self) -> Option<&[String]> {
+        match &self.0 {
+            ErrorRepr::NoMatch { rule_stack, .. } => Some(rule_stack),
+            _ => None,
+        }
+    }
 }
 
 #[derive(thiserror::Error, Debug)]
 enum ErrorRepr {
     #[error("unexpected error: {0}")]
     Unexpected(&'static str),
+    #[error("couldn't parse grammar")]
+    Parse(#[from] parser::Error),
+    #[error("rule `{0}` is defined more than once")]
+    DuplicateRule(String),
+    #[error("incremental alternative `=/` given for rule `{0}` that hasn't been defined")]
+    IncrementalWithoutBase(String),
+    #[error("start rule `{0}` isn't defined in the grammar")]
+    UnknownStartRule(String),
+    #[error("rule `{0}` isn't defined in the grammar")]
+    UnknownRule(String),
+    #[error("rule `{rule}` references undefined rule `{reference}`")]
+    UndefinedRule { rule: String, reference: String },
+    #[error("can't generate code for left-recursive rule `{0}`")]
+    LeftRecursive(String),
+    #[error("rule `{0}` is recursive, so it can't be converted to a regex")]
+    NotRegular(String),
+    #[error("rule `{0}` contains prose, which can't be converted to a regex")]
+    Prose(String),
+    #[error(
+        "can't generate input for rule `{0}`, it recurses forever or needs prose or an undefined \
+         rule"
+    )]
+    Ungenerable(String),
+    #[error("couldn't find input near to rule `{0}` that doesn't match it")]
+    NoNearMiss(String),
+    #[error("grammar doesn't define any rules")]
+    NoRules,
+    #[error("rule `{0}` contains a line break, but builder rules must be on one line")]
+    LineBreak(String),
+    #[error("parse tree doesn't contain a match for rule `{0}`")]
+    MissingRule(String),
+    #[error(
+        "input doesn't match rule `{rule}`: matching failed at byte offset {offset} (in {})",
+        rule_stack.join(" > ")
+    )]
+    NoMatch {
+        rule: String,
+        offset: usize,
+        rule_stack: Vec<String>,
+    },
 }
 
 #[cfg(test)]
 mod tests {
-    // #[test]
-    // fn test_nsid() {
-    //     struct Nsid {
-    //         namespace: String,
-    //         name: String,
-    //     };
-
-    //     const nsid_abnf: &str = r#"
-    //         alpha     = "a" / "b" / "c" / "d" / "e" / "f" / "g" / "h" / "i" / "j" / "k" / "l" / "m" / "n" / "o" / "p" / "q" / "r" / "s" / "t" / "u" / "v" / "w" / "x" / "y" / "z" / "A" / "B" / "C" / "D" / "E" / "F" / "G" / "H" / "I" / "J" / "K" / "L" / "M" / "N" / "O" / "P" / "Q" / "R" / "S" / "T" / "U" / "V" / "W" / "X" / "Y" / "Z"
-    //         number    = "1" / "2" / "3" / "4" / "5" / "6" / "7" / "8" / "9" / "0"
-    //         delim     = "."
-    //         segment   = alpha *( alpha / number / "-" )
-    //         authority = segment *( delim segment )
-    //         name      = segment
-    //         nsid      = authority delim name
-    //         nsid-ns   = authority delim "*"
-    //     "#;
-
-    //     // let nsid =
-    //     //     Abnf::from_metasyntax::<Nsid>(nsid_abnf, "nsid").expect("nsid_abnf is valid ABNF definition");
-
-    //     fn rule(input: &str) {
-    //         todo!();
-    //     }
-
-    //     let nsid = Abnf::builder()
-    //         .rule("alpha", r#""a" / "b" / "c" / "d" / "e" / "f" / "g" / "h" / "i" / "j" / "k" / "l" / "m" / "n" / "o" / "p" / "q" / "r" / "s" / "t" / "u" / "v" / "w" / "x" / "y" / "z" / "A" / "B" / "C" / "D" / "E" / "F" / "G" / "H" / "I" / "J" / "K" / "L" / "M" / "N" / "O" / "P" / "Q" / "R" / "S" / "T" / "U" / "V" / "W" / "X" / "Y" / "Z""#)
-    //         .rule("number", r#""1" / "2" / "3" / "4" / "5" / "6" / "7" / "8" / "9" / "0""#)
-    //         .rule("delim", r#"".""#)
-    //         .rule("segment", r#"alpha *( alpha / number / "-" )"#)
-    //         .rule("authority", "segment *( delim segment )")
-    //         .rule("name", "segment")
-    //         .rule("nsid", "authority delim name ")
-    //         .rule("nsid-ns", r#"authority delim "*""#)
-    //         .build::<Nsid>()
-    //         .expect("nsid_abnf is valid ABNF definition");
-
-    //         assert!(nsid.parse("com.example.status").is_ok());
-    //         assert!(nsid.parse("io.social.getFeed").is_ok());
-    //         assert!(nsid.parse("net.users.bob.ping").is_ok());
-    // }
-
-    // #[test]
-    // fn test_atp_uri() {
-    //     struct AtpUri(String);
-
-    //     const atp_uri_abnf: &str = r##"
-    //         atp-url   = "at://" authority path [ "#" fragment ]
-    //         authority = reg-name / did
-    //         path      = [ "/" coll-nsid [ "/" record-id ] ]
-    //         coll-nsid = nsid
-    //         record-id = 1*pchar
-    //     "##;
-
-    //     // let atp_uri =
-    //     //     Abnf::from_metasyntax::<AtpUri>(atp_uri_abnf, "atp-url").expect("atp_uri_abnf is valid ABNF definition");
-
-    //     let atp_uri = Abnf::builder()
-    //         .rule("atp-url", r##""at://" authority path [ "#" fragment ]"##)
-    //         .rule("authority", "reg-name / did")
-    //         .rule("path", r#"[ "/" coll-nsid [ "/" record-id ] ]"#)
-    //         .rule("coll-nsid", "nsid")
-    //         .rule("record-id", "1*pchar")
-    //         .build::<AtpUri>()
-    //         .expect("atp_uri_abnf is valid ABNF definition");
-
-    //     assert!(atp_uri.parse("at://alice.host.com").is_ok());
-    //     assert!(atp_uri.parse("at://did:plc:bv6ggog3tya2z3vxsub7hnal").is_ok());
-    //     assert!(atp_uri.parse("at://alice.host.com/io.example.song").is_ok());
-    //     assert!(atp_uri
-    //         .parse("at://alice.host.com/io.example.song/3yI5-c1z-cc2p-1a")
-    //         .is_ok());
-    //     assert!(atp_uri
-    //         .parse("at://alice.host.com/io.example.song/3yI5-c1z-cc2p-1a#/title")
-    //         .is_ok());
-    // }
+    use super::{Abnf, Error, FromParseTree, ParseTree};
+
+    #[test]
+    fn test_from_metasyntax() {
+        const NSID_ABNF: &str = r#"alpha     = %x41-5A / %x61-7A
+number    = %x30-39
+delim     = "."
+segment   = alpha *( alpha / number / "-" )
+authority = segment *( delim segment )
+name      = segment
+nsid      = authority delim name
+nsid-ns   = authority delim "*"
+"#;
+
+        let nsid = Abnf::<String>::from_metasyntax(NSID_ABNF, "nsid")
+            .expect("NSID_ABNF is valid ABNF definition");
+
+        assert_eq!(
+            nsid.parse("com.example.status").unwrap(),
+            "com.example.status"
+        );
+        assert!(nsid.parse("io.social.getFeed").is_ok());
+        assert!(nsid.parse("net.users.bob.ping").is_ok());
+        assert!(nsid.parse("com.example.*").is_err());
+
+        let err = nsid.parse("com.example..status").unwrap_err();
+        assert_eq!(err.offset(), Some(12));
+
+        let nsid_ns = Abnf::<String>::from_metasyntax(NSID_ABNF, "nsid-ns").unwrap();
+        assert!(nsid_ns.parse("com.example.*").is_ok());
+
+        assert!(Abnf::<String>::from_metasyntax(NSID_ABNF, "not-a-rule").is_err());
+    }
+
+    #[test]
+    fn test_nsid() {
+        #[derive(Debug, PartialEq)]
+        struct Nsid {
+            namespace: String,
+            name: String,
+        }
+
+        impl FromParseTree for Nsid {
+            fn from_parse_tree(tree: &ParseTree<'_>) -> Result<Self, Error> {
+                Ok(Self {
+                    namespace: tree.expect("authority")?.text.to_owned(),
+                    name: tree.expect("name")?.text.to_owned(),
+                })
+            }
+        }
+
+        let nsid = Abnf::builder()
+            .rule("alpha", r#""a" / "b" / "c" / "d" / "e" / "f" / "g" / "h" / "i" / "j" / "k" / "l" / "m" / "n" / "o" / "p" / "q" / "r" / "s" / "t" / "u" / "v" / "w" / "x" / "y" / "z" / "A" / "B" / "C" / "D" / "E" / "F" / "G" / "H" / "I" / "J" / "K" / "L" / "M" / "N" / "O" / "P" / "Q" / "R" / "S" / "T" / "U" / "V" / "W" / "X" / "Y" / "Z""#)
+            .rule("number", r#""1" / "2" / "3" / "4" / "5" / "6" / "7" / "8" / "9" / "0""#)
+            .rule("delim", r#"".""#)
+            .rule("segment", r#"alpha *( alpha / number / "-" )"#)
+            .rule("authority", "segment *( delim segment )")
+            .rule("name", "segment")
+            .rule("nsid", "authority delim name ")
+            .rule("nsid-ns", r#"authority delim "*""#)
+            .start_rule("nsid")
+            .build::<Nsid>()
+            .expect("nsid_abnf is valid ABNF definition");
+
+        assert!(nsid.parse("com.example.status").is_ok());
+        assert!(nsid.parse("io.social.getFeed").is_ok());
+        assert!(nsid.parse("net.users.bob.ping").is_ok());
+        assert_eq!(
+            nsid.parse("com.example.status").unwrap(),
+            Nsid {
+                namespace: "com.example".to_owned(),
+                name: "status".to_owned(),
+            }
+        );
+
+        assert!(nsid.parse("com.example.*").is_err());
+        assert!(nsid.parse("com..example").is_err());
+        assert!(nsid.parse("9com.example.status").is_err());
+    }
+
+    #[test]
+    fn test_atp_uri() {
+        struct AtpUri {
+            authority: String,
+            collection: Option<String>,
+            record: Option<String>,
+        }
+
+        impl FromParseTree for AtpUri {
+            fn from_parse_tree(tree: &ParseTree<'_>) -> Result<Self, Error> {
+                Ok(Self {
+                    authority: tree.expect("authority")?.text.to_owned(),
+                    collection: tree.get("coll-nsid").map(|t| t.text.to_owned()),
+                    record: tree.get("record-id").map(|t| t.text.to_owned()),
+                })
+            }
+        }
+
+        let atp_uri = Abnf::builder()
+            .rule("atp-url", r##""at://" authority path [ "#" fragment ]"##)
+            .rule("authority", "reg-name / did")
+            .rule("path", r#"[ "/" coll-nsid [ "/" record-id ] ]"#)
+            .rule("coll-nsid", "nsid")
+            .rule("record-id", "1*pchar")
+            // https://atproto.com/specs/nsid
+            .rule("nsid", r#"segment 1*( "." segment )"#)
+            .rule("segment", r#"ALPHA *( ALPHA / DIGIT / "-" )"#)
+            // https://www.w3.org/TR/did-core/#did-syntax
+            .rule("did", r#""did:" method-name ":" method-specific-id"#)
+            .rule("method-name", "1*method-char")
+            .rule("method-char", "%x61-7A / DIGIT")
+            .rule("method-specific-id", r#"*( *idchar ":" ) 1*idchar"#)
+            .rule("idchar", r#"ALPHA / DIGIT / "." / "-" / "_" / pct-encoded"#)
+            // https://www.rfc-editor.org/rfc/rfc3986#appendix-A
+            .rule("reg-name", "*( unreserved / pct-encoded / sub-delims )")
+            .rule("fragment", r#"*( pchar / "/" / "?" )"#)
+            .rule(
+                "pchar",
+                r#"unreserved / pct-encoded / sub-delims / ":" / "@""#,
+            )
+            .rule("unreserved", r#"ALPHA / DIGIT / "-" / "." / "_" / "~""#)
+            .rule("pct-encoded", r#""%" HEXDIG HEXDIG"#)
+            .rule("sub-delims", r#""!" / "$" / "&" / "'" / "(" / ")""#)
+            .alternative("sub-delims", r#""*" / "+" / "," / ";" / "=""#)
+            .build::<AtpUri>()
+            .expect("atp_uri_abnf is valid ABNF definition");
+
+        assert!(atp_uri.parse("at://alice.host.com").is_ok());
+        assert!(atp_uri
+            .parse("at://did:plc:bv6ggog3tya2z3vxsub7hnal")
+            .is_ok());
+        assert!(atp_uri.parse("at://alice.host.com/io.example.song").is_ok());
+        assert!(atp_uri
+            .parse("at://alice.host.com/io.example.song/3yI5-c1z-cc2p-1a")
+            .is_ok());
+        assert!(atp_uri
+            .parse("at://alice.host.com/io.example.song/3yI5-c1z-cc2p-1a#/title")
+            .is_ok());
+        let uri = atp_uri.parse("at://alice.host.com").unwrap();
+        assert_eq!(uri.authority, "alice.host.com");
+        assert_eq!(uri.collection, None);
+        let uri = atp_uri
+            .parse("at://did:plc:bv6ggog3tya2z3vxsub7hnal/io.example.song/3yI5-c1z-cc2p-1a")
+            .unwrap();
+        assert_eq!(uri.authority, "did:plc:bv6ggog3tya2z3vxsub7hnal");
+        assert_eq!(uri.collection.as_deref(), Some("io.example.song"));
+        assert_eq!(uri.record.as_deref(), Some("3yI5-c1z-cc2p-1a"));
+
+        // Quoted strings are case-insensitive, as RFC 5234 requires
+        assert!(atp_uri.parse("AT://alice.host.com").is_ok());
+        assert!(atp_uri.parse("http://alice.host.com").is_err());
+        assert!(atp_uri.parse("at://alice.host.com/not an nsid").is_err());
+    }
+
+    #[test]
+    fn test_builder_errors() {
+        let err = Abnf::builder()
+            .rule("a", "b")
+            .build::<String>()
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "rule `a` references undefined rule `b`");
+
+        let err = Abnf::builder()
+            .rule("a", r#""a""#)
+            .rule("A", r#""b""#)
+            .build::<String>()
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "rule `A` is defined more than once");
+
+        let err = Abnf::builder()
+            .alternative("a", r#""a""#)
+            .build::<String>()
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "incremental alternative `=/` given for rule `a` that hasn't been defined"
+        );
+
+        let err = Abnf::builder().build::<String>().err().unwrap();
+        assert_eq!(err.to_string(), "grammar doesn't define any rules");
+
+        let err = Abnf::builder()
+            .rule("a", r#""a"#)
+            .build::<String>()
+            .err()
+            .unwrap();
+        // The parser's error is the source, so it isn't repeated in the message
+        assert_eq!(err.to_string(), "couldn't parse grammar");
+        assert!(std::error::Error::source(&err).is_some());
+
+        let err = Abnf::builder()
+            .rule("a", r#""a""#)
+            .start_rule("b")
+            .build::<String>()
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "start rule `b` isn't defined in the grammar"
+        );
+
+        // Elements can't sneak in another rule
+        let err = Abnf::builder()
+            .rule("a", "b\nb = \"b\"")
+            .build::<String>()
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "rule `a` contains a line break, but builder rules must be on one line"
+        );
+
+        let err = Abnf::builder()
+            .rule("a\r\nb", r#""b""#)
+            .build::<String>()
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "rule `a` contains a line break, but builder rules must be on one line"
+        );
+    }
+
+    #[test]
+    fn test_builder_alternatives() {
+        let ab = Abnf::builder()
+            .rule("ab", r#""a""#)
+            .alternative("ab", r#""b""#)
+            .build::<String>()
+            .unwrap();
+        assert!(ab.parse("a").is_ok());
+        assert!(ab.parse("b").is_ok());
+        assert!(ab.parse("c").is_err());
+    }
 }
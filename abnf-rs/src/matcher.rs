@@ -0,0 +1,646 @@
+use crate::ast::{
+    Alternation, CharVal, Concatenation, Element, NumVal, NumValue, Repetition, Span,
+};
+use crate::grammar::Grammar;
+use crate::parse_tree::ParseTree;
+use crate::{Error, ErrorRepr};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use tracing::trace;
+
+/// Every byte offset in the input at which a match could end
+type Ends = BTreeSet<usize>;
+
+/// A backtracking recognizer for a [`Grammar`].
+///
+/// Rather than committing to the first way an element can match, each element returns the set of
+/// all offsets where it could end, so that e.g. `*ALPHA "a"` can match `"aaa"`. Rule matches are
+/// memoized by their starting offset, which keeps this from going exponential on most grammars.
+///
+/// Left-recursive rules like `list = list "," item / item` are matched by growing a seed: the
+/// recursive reference first matches nothing, then the ends found so far, until no new ends turn up.
+///
+/// Input is either text, where num-vals match Unicode code points, or bytes, where they match
+/// single bytes. Offsets are byte offsets either way.
+pub(crate) struct Matcher<'g, 'i> {
+    grammar: &'g Grammar,
+    input: &'i [u8],
+    // The input as text, unless matching bytes
+    text: Option<&'i str>,
+    memo: HashMap<(usize, usize), Ends>,
+    // Rules currently being matched at a given offset. Re-entering one of these means that the
+    // rule is left-recursive, which would otherwise loop forever.
+    in_progress: HashSet<(usize, usize)>,
+    // The ends found so far for left-recursive rules that are still being matched
+    seeds: HashMap<(usize, usize), Ends>,
+    // Left-recursive rules whose seeds the current match has used. Until they're finished, the
+    // match can't be memoized, because it might grow along with them.
+    seeds_used: HashSet<(usize, usize)>,
+    stack: Vec<&'g str>,
+    furthest_failure: Option<(usize, Vec<&'g str>)>,
+    // Rules currently being derived between two offsets
+    deriving: HashSet<(usize, usize, usize)>,
+    // Concatenations and repetitions (by address, with the remaining length or count so far) that
+    // couldn't be derived between two offsets. Without these, deriving goes exponential.
+    underivable_concatenations: HashSet<(usize, usize, usize, usize)>,
+    underivable_repetitions: HashSet<(usize, usize, usize, usize)>,
+}
+
+impl<'g, 'i> Matcher<'g, 'i> {
+    pub(crate) fn new(grammar: &'g Grammar, input: &'i str) -> Self {
+        Self {
+            text: Some(input),
+            ..Self::from_bytes(grammar, input.as_bytes())
+        }
+    }
+
+    pub(crate) fn from_bytes(grammar: &'g Grammar, input: &'i [u8]) -> Self {
+        Self {
+            grammar,
+            input,
+            text: None,
+            memo: HashMap::new(),
+            in_progress: HashSet::new(),
+            seeds: HashMap::new(),
+            seeds_used: HashSet::new(),
+            stack: Vec::new(),
+            furthest_failure: None,
+            deriving: HashSet::new(),
+            underivable_concatenations: HashSet::new(),
+            underivable_repetitions: HashSet::new(),
+        }
+    }
+
+    /// Match the rule at `rule_index` against the entire input
+    pub(crate) fn match_all(mut self, rule_index: usize) -> Result<(), Error> {
+        self.match_to_end(rule_index)
+    }
+
+    /// Match the rule at `rule_index` against the entire input, then work out which rules matched
+    /// which parts of it
+    pub(crate) fn parse_all(mut self, rule_index: usize) -> Result<ParseTree<'i>, Error> {
+        if self.text.is_none() {
+            return Err(Error::unexpected("parse trees can only be built from text"));
+        }
+        self.match_to_end(rule_index)?;
+
+        let rule = self.grammar.rule_at(rule_index);
+        let children = self
+            .derive_alternation(&rule.elements, 0, self.input.len())
+            .ok_or(Error::unexpected("a matching rule had no derivation"))?;
+
+        Ok(self.node(rule_index, 0, self.input.len(), children))
+    }
+
+    fn match_to_end(&mut self, rule_index: usize) -> Result<(), Error> {
+        let rule_name = self.grammar.rule_at(rule_index).name.as_str();
+        let ends = self.match_rule(rule_index, 0);
+        if ends.contains(&self.input.len()) {
+            return Ok(());
+        }
+
+        // If the rule matched a prefix of the input and nothing was tried beyond it, then the
+        // failure is the leftover input.
+        if let Some(&end) = ends.last() {
+            if self
+                .furthest_failure
+                .as_ref()
+                .is_none_or(|(offset, _)| end > *offset)
+            {
+                self.furthest_failure = Some((end, vec![rule_name]));
+            }
+        }
+
+        let (offset, rule_stack) = self
+            .furthest_failure
+            .take()
+            .unwrap_or_else(|| (0, vec![rule_name]));
+
+        Err(Error(ErrorRepr::NoMatch {
+            rule: rule_name.to_owned(),
+            offset,
+            rule_stack: rule_stack.into_iter().map(ToOwned::to_owned).collect(),
+        }))
+    }
+
+    fn match_rule(&mut self, rule_index: usize, pos: usize) -> Ends {
+        let key = (rule_index, pos);
+        if let Some(ends) = self.memo.get(&key) {
+            return ends.clone();
+        }
+        if !self.in_progress.insert(key) {
+            trace!("left recursion detected, using the ends found so far");
+            self.seeds_used.insert(key);
+            return self.seeds.get(&key).cloned().unwrap_or_default();
+        }
+
+        let outer_seeds_used = std::mem::take(&mut self.seeds_used);
+        let rule = self.grammar.rule_at(rule_index);
+        self.stack.push(&rule.name);
+        let ends = loop {
+            let ends = self.match_alternation(&rule.elements, pos);
+            if !self.seeds_used.remove(&key) {
+                break ends;
+            }
+
+            // Matching again with a bigger seed can only find more ends, so stop once it doesn't
+            let seed = self.seeds.entry(key).or_default();
+            if ends.is_subset(seed) {
+                break ends;
+            }
+            *seed = ends;
+        };
+        self.stack.pop();
+
+        self.seeds.remove(&key);
+        self.in_progress.remove(&key);
+        if self.seeds_used.is_empty() {
+            self.memo.insert(key, ends.clone());
+        }
+        self.seeds_used.extend(outer_seeds_used);
+
+        ends
+    }
+
+    fn match_alternation(&mut self, alternation: &'g Alternation, pos: usize) -> Ends {
+        let mut ends = Ends::new();
+        for concatenation in &alternation.concatenations {
+            ends.extend(self.match_concatenation(concatenation, pos));
+        }
+
+        ends
+    }
+
+    fn match_concatenation(&mut self, concatenation: &'g Concatenation, pos: usize) -> Ends {
+        let mut positions = Ends::from([pos]);
+        for repetition in &concatenation.repetitions {
+            let mut next = Ends::new();
+            for p in positions {
+                next.extend(self.match_repetition(repetition, p));
+            }
+
+            if next.is_empty() {
+                return next;
+            }
+            positions = next;
+        }
+
+        positions
+    }
+
+    fn match_repetition(&mut self, repetition: &'g Repetition, pos: usize) -> Ends {
+        let mut ends = Ends::new();
+        let mut frontier = Ends::from([pos]);
+        // Once the minimum count is reached, positions we've already been at can't lead anywhere
+        // new. Skipping them is what stops `*` from spinning on elements that match nothing.
+        let mut seen = Ends::new();
+        let mut count = 0;
+
+        loop {
+            if count >= repetition.min {
+                ends.extend(frontier.iter().copied());
+                seen.extend(frontier.iter().copied());
+            }
+            if repetition.max == Some(count) {
+                break;
+            }
+
+            let mut next = Ends::new();
+            for p in frontier {
+                next.extend(self.match_element(&repetition.element, p));
+            }
+            if count + 1 >= repetition.min {
+                next.retain(|p| !seen.contains(p));
+            }
+            if next.is_empty() {
+                break;
+            }
+
+            frontier = next;
+            count += 1;
+        }
+
+        ends
+    }
+
+    fn match_element(&mut self, element: &'g Element, pos: usize) -> Ends {
+        match element {
+            Element::RuleName(name) => match self.grammar.index_of(name) {
+                Some(rule_index) => self.match_rule(rule_index, pos),
+                None => {
+                    trace!("reference to undefined rule `{name}`");
+                    self.fail(pos)
+                }
+            },
+            Element::Group(alternation) => self.match_alternation(alternation, pos),
+            Element::Option(alternation) => {
+                let mut ends = self.match_alternation(alternation, pos);
+                ends.insert(pos);
+                ends
+            }
+            Element::CharVal(char_val) => self.match_char_val(char_val, pos),
+            Element::NumVal(num_val) => self.match_num_val(num_val, pos),
+            // Prose is meant for humans, there's no way to match it
+            Element::ProseVal(_) => self.fail(pos),
+        }
+    }
+
+    // https://www.rfc-editor.org/rfc/rfc5234#section-2.3
+    // https://www.rfc-editor.org/rfc/rfc7405#section-2.2
+    fn match_char_val(&mut self, char_val: &CharVal, pos: usize) -> Ends {
+        let value = char_val.value.as_bytes();
+        let end = pos + value.len();
+        let is_match = match self.input.get(pos..end) {
+            Some(candidate) if char_val.case_sensitive => candidate == value,
+            Some(candidate) => candidate.eq_ignore_ascii_case(value),
+            None => false,
+        };
+
+        if is_match {
+            Ends::from([end])
+        } else {
+            self.fail(pos)
+        }
+    }
+
+    fn match_num_val(&mut self, num_val: &NumVal, pos: usize) -> Ends {
+        let mut end = pos;
+        match &num_val.value {
+            NumValue::Sequence(values) => {
+                for &value in values {
+                    match self.value_at(end) {
+                        Some((v, len)) if v == value => end += len,
+                        _ => return self.fail(end),
+                    }
+                }
+            }
+            NumValue::Range(low, high) => match self.value_at(pos) {
+                Some((v, len)) if (*low..=*high).contains(&v) => end += len,
+                _ => return self.fail(pos),
+            },
+        }
+
+        Ends::from([end])
+    }
+
+    /// The value that a num-val would compare against at `pos`, a code point for text or a byte
+    /// for bytes, along with its length in bytes
+    fn value_at(&self, pos: usize) -> Option<(u32, usize)> {
+        match self.text {
+            Some(text) => {
+                let c = text.get(pos..)?.chars().next()?;
+                Some((c as u32, c.len_utf8()))
+            }
+            None => self.input.get(pos).map(|&b| (b as u32, 1)),
+        }
+    }
+
+    // Once matching has succeeded, the ends that each element can match to tell us how to split
+    // the input between them. The `derive_*` methods find one way of matching an element from
+    // `start` to exactly `end`, returning the parse trees of the rules inside it.
+
+    fn derive_alternation(
+        &mut self,
+        alternation: &'g Alternation,
+        start: usize,
+        end: usize,
+    ) -> Option<Vec<ParseTree<'i>>> {
+        alternation
+            .concatenations
+            .iter()
+            .find_map(|c| self.derive_repetitions(&c.repetitions, start, end))
+    }
+
+    fn derive_repetitions(
+        &mut self,
+        repetitions: &'g [Repetition],
+        start: usize,
+        end: usize,
+    ) -> Option<Vec<ParseTree<'i>>> {
+        let Some((repetition, rest)) = repetitions.split_first() else {
+            return (start == end).then(Vec::new);
+        };
+        let key = (repetitions.as_ptr() as usize, repetitions.len(), start, end);
+        if self.underivable_concatenations.contains(&key) {
+            return None;
+        }
+
+        // Trying the longest match first finds the usual split of e.g. `*ALPHA DIGIT` straight away
+        for middle in self.match_repetition(repetition, start).into_iter().rev() {
+            if middle > end {
+                continue;
+            }
+            let Some(rest) = self.derive_repetitions(rest, middle, end) else {
+                continue;
+            };
+            if let Some(mut trees) = self.derive_repetition(repetition, 0, start, middle) {
+                trees.extend(rest);
+                return Some(trees);
+            }
+        }
+
+        self.underivable_concatenations.insert(key);
+        None
+    }
+
+    fn derive_repetition(
+        &mut self,
+        repetition: &'g Repetition,
+        count: usize,
+        start: usize,
+        end: usize,
+    ) -> Option<Vec<ParseTree<'i>>> {
+        if start == end && count >= repetition.min {
+            return Some(Vec::new());
+        }
+        if repetition.max == Some(count) {
+            return None;
+        }
+        // Past the minimum, the count only matters if there's a maximum
+        let count_key = match repetition.max {
+            Some(_) => count,
+            None => count.min(repetition.min),
+        };
+        let key = (
+            repetition as *const Repetition as usize,
+            count_key,
+            start,
+            end,
+        );
+        if self.underivable_repetitions.contains(&key) {
+            return None;
+        }
+
+        for middle in self
+            .match_element(&repetition.element, start)
+            .into_iter()
+            .rev()
+        {
+            // Matching nothing is only useful for getting up to the minimum count
+            if middle > end || (middle == start && count >= repetition.min) {
+                continue;
+            }
+            let Some(rest) = self.derive_repetition(repetition, count + 1, middle, end) else {
+                continue;
+            };
+            if let Some(mut trees) = self.derive_element(&repetition.element, start, middle) {
+                trees.extend(rest);
+                return Some(trees);
+            }
+        }
+
+        self.underivable_repetitions.insert(key);
+        None
+    }
+
+    fn derive_element(
+        &mut self,
+        element: &'g Element,
+        start: usize,
+        end: usize,
+    ) -> Option<Vec<ParseTree<'i>>> {
+        match element {
+            Element::RuleName(name) => {
+                let rule_index = self.grammar.index_of(name)?;
+                // Core rules are left out of the tree, otherwise every `ALPHA` would get a node
+                if self.grammar.is_core_rule(name) {
+                    return Some(Vec::new());
+                }
+                // The same guard against left recursion as `match_rule`
+                let key = (rule_index, start, end);
+                if !self.deriving.insert(key) {
+                    return None;
+                }
+                let rule = self.grammar.rule_at(rule_index);
+                let children = self.derive_alternation(&rule.elements, start, end);
+                self.deriving.remove(&key);
+
+                Some(vec![self.node(rule_index, start, end, children?)])
+            }
+            Element::Group(alternation) => self.derive_alternation(alternation, start, end),
+            Element::Option(_) if start == end => Some(Vec::new()),
+            Element::Option(alternation) => self.derive_alternation(alternation, start, end),
+            // These were already matched when working out the ends
+            Element::CharVal(_) | Element::NumVal(_) => Some(Vec::new()),
+            Element::ProseVal(_) => None,
+        }
+    }
+
+    fn node(
+        &self,
+        rule_index: usize,
+        start: usize,
+        end: usize,
+        children: Vec<ParseTree<'i>>,
+    ) -> ParseTree<'i> {
+        ParseTree {
+            rule: self.grammar.rule_at(rule_index).name.clone(),
+            span: Span::new(start, end),
+            // Only text input gets parse trees, see `parse_all`
+            text: self.text.map_or("", |text| &text[start..end]),
+            children,
+        }
+    }
+
+    /// Record a failure to match at `pos` and return no ends
+    fn fail(&mut self, pos: usize) -> Ends {
+        if self
+            .furthest_failure
+            .as_ref()
+            .is_none_or(|(offset, _)| pos > *offset)
+        {
+            self.furthest_failure = Some((pos, self.stack.clone()));
+        }
+
+        Ends::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::grammar::Grammar;
+    use crate::parser;
+
+    fn grammar(source: &str) -> Grammar {
+        Grammar::from_rule_list(parser::parse(source).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_char_val_is_case_insensitive() {
+        let g = grammar("greeting = \"hello\"\n");
+        assert!(g.match_rule("greeting", "hello").is_ok());
+        assert!(g.match_rule("greeting", "HeLLo").is_ok());
+        assert!(g.match_rule("greeting", "hell").is_err());
+        assert!(g.match_rule("greeting", "hello!").is_err());
+
+        let g = grammar("greeting = %i\"hello\"\n");
+        assert!(g.match_rule("greeting", "HeLLo").is_ok());
+    }
+
+    #[test]
+    fn test_case_sensitive_char_val() {
+        let g = grammar("scheme = %s\"at://\"\nname = %s\"Bob\" / \"alice\"\n");
+        assert!(g.match_rule("scheme", "at://").is_ok());
+        assert!(g.match_rule("scheme", "AT://").is_err());
+        assert!(g.match_rule("name", "Bob").is_ok());
+        assert!(g.match_rule("name", "bob").is_err());
+        assert!(g.match_rule("name", "ALICE").is_ok());
+
+        let err = g.match_rule("scheme", "At://").unwrap_err();
+        assert_eq!(err.offset(), Some(0));
+    }
+
+    #[test]
+    fn test_repetition_backtracks() {
+        let g = grammar("as = *\"a\" \"a\"\n");
+        assert!(g.match_rule("as", "a").is_ok());
+        assert!(g.match_rule("as", "aaaa").is_ok());
+        assert!(g.match_rule("as", "").is_err());
+
+        let g = grammar("as = 2*3\"a\"\n");
+        assert!(g.match_rule("as", "a").is_err());
+        assert!(g.match_rule("as", "aa").is_ok());
+        assert!(g.match_rule("as", "aaa").is_ok());
+        assert!(g.match_rule("as", "aaaa").is_err());
+    }
+
+    #[test]
+    fn test_repetition_of_nullable_element_terminates() {
+        let g = grammar("r = *( [\"a\"] )\n");
+        assert!(g.match_rule("r", "").is_ok());
+        assert!(g.match_rule("r", "aaa").is_ok());
+        assert!(g.match_rule("r", "b").is_err());
+    }
+
+    #[test]
+    fn test_num_val() {
+        let g = grammar(
+            "digit = %x30-39\nbang = %d33\ncrlf = %d13.10\nabc = %b1100001.1100010.1100011\n\
+             snowman = %x2603\nastral = %x10000-10FFFF\n",
+        );
+        assert!(g.match_rule("digit", "7").is_ok());
+        assert!(g.match_rule("digit", "a").is_err());
+        assert!(g.match_rule("bang", "!").is_ok());
+        assert!(g.match_rule("crlf", "\r\n").is_ok());
+        assert!(g.match_rule("crlf", "\r").is_err());
+        assert!(g.match_rule("crlf", "\n\r").is_err());
+        // Unlike quoted strings, num-vals are case-sensitive
+        assert!(g.match_rule("abc", "abc").is_ok());
+        assert!(g.match_rule("abc", "ABC").is_err());
+        assert!(g.match_rule("snowman", "\u{2603}").is_ok());
+        assert!(g.match_rule("astral", "\u{1F980}").is_ok());
+        assert!(g.match_rule("astral", "\u{FFFF}").is_err());
+
+        let err = g.match_rule("abc", "abd").unwrap_err();
+        assert_eq!(err.offset(), Some(2));
+    }
+
+    #[test]
+    fn test_bytes() {
+        let g = grammar(
+            "data = 1*OCTET\nmultibase = \"z\" 1*ALPHA\nsnowman = %xE2.98.83\n\
+             high = %x2603 / %x100-FFFF\nrange = %x80-FF\n",
+        );
+        assert!(g.match_bytes("data", &[0xFF, 0x00, 0x80]).is_ok());
+        assert!(g.match_bytes("multibase", b"zQmAbc").is_ok());
+        assert!(g.match_bytes("multibase", b"ZQmAbc").is_ok());
+        assert!(g.match_bytes("multibase", b"zQm\xFF").is_err());
+        // Text is matched as its UTF-8 encoding
+        assert!(g.match_bytes("snowman", "\u{2603}".as_bytes()).is_ok());
+        assert!(g.match_rule("snowman", "\u{2603}").is_err());
+        assert!(g.match_bytes("high", "\u{2603}".as_bytes()).is_err());
+        assert!(g.match_bytes("range", &[0x80]).is_ok());
+        assert!(g.match_bytes("range", &[0x7F]).is_err());
+
+        let err = g.match_bytes("multibase", b"zQm\xFFc").unwrap_err();
+        assert_eq!(err.offset(), Some(3));
+    }
+
+    #[test]
+    fn test_bytes_and_text_agree_on_ascii() {
+        let g = grammar(
+            "nsid = segment 1*( \".\" segment )\nsegment = ALPHA *( ALPHA / DIGIT / %x2D )\n",
+        );
+        for input in [
+            "com.example.foo",
+            "Com.Ex-ample",
+            "com",
+            "com.9",
+            "com..x",
+            "",
+        ] {
+            assert_eq!(
+                g.match_rule("nsid", input).map_err(|e| e.to_string()),
+                g.match_bytes("nsid", input.as_bytes())
+                    .map_err(|e| e.to_string()),
+                "{input:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_incremental_alternatives() {
+        let g = grammar("ab = \"a\"\nAB =/ \"b\"\n");
+        assert!(g.match_rule("ab", "a").is_ok());
+        assert!(g.match_rule("ab", "b").is_ok());
+    }
+
+    #[test]
+    fn test_core_rules_can_be_referenced_and_overridden() {
+        let g = grammar("word = 1*ALPHA\n");
+        assert!(g.match_rule("word", "Hello").is_ok());
+        assert!(g.match_rule("word", "h3llo").is_err());
+        assert!(g.is_core_rule("ALPHA"));
+
+        let g = grammar("word = 1*ALPHA\nALPHA = %x61-7A\n");
+        assert!(g.match_rule("word", "hello").is_ok());
+        assert!(g.match_rule("word", "Hello").is_err());
+        assert!(!g.is_core_rule("ALPHA"));
+
+        let g = grammar("word = 1*ALPHA\nALPHA =/ \"_\"\n");
+        assert!(g.match_rule("word", "snake_case").is_ok());
+    }
+
+    #[test]
+    fn test_left_recursion() {
+        let g = grammar("list = list \",\" \"x\" / \"x\"\n");
+        assert!(g.match_rule("list", "x").is_ok());
+        assert!(g.match_rule("list", "x,x").is_ok());
+        assert!(g.match_rule("list", "x,x,x").is_ok());
+        assert!(g.match_rule("list", "x,").is_err());
+        assert!(g.match_rule("list", ",x").is_err());
+
+        // Through another rule
+        let g = grammar("sum = term\nterm = sum \"+\" DIGIT / DIGIT\n");
+        assert!(g.match_rule("sum", "1+2+3").is_ok());
+        assert!(g.match_rule("term", "1+2").is_ok());
+        assert!(g.match_rule("sum", "1++2").is_err());
+
+        // Recursing forever without consuming anything
+        let g = grammar("a = a / \"x\"\n");
+        assert!(g.match_rule("a", "x").is_ok());
+        assert!(g.match_rule("a", "").is_err());
+    }
+
+    #[test]
+    fn test_failure_reports_offset_and_rule_stack() {
+        let g =
+            grammar("nsid = segment 1*( \".\" segment )\nsegment = %x61-7A *( %x61-7A / \"-\" )\n");
+        assert!(g.match_rule("nsid", "com.example.foo").is_ok());
+
+        let err = g.match_rule("nsid", "com.ex_ample").unwrap_err();
+        assert_eq!(err.offset(), Some(6));
+        assert_eq!(
+            err.rule_stack().unwrap(),
+            &["nsid".to_owned(), "segment".to_owned()]
+        );
+
+        // Trailing input that nothing tried to match
+        let g = grammar("a = \"a\"\n");
+        let err = g.match_rule("a", "ab").unwrap_err();
+        assert_eq!(err.offset(), Some(1));
+        assert_eq!(
+            err.to_string(),
+            "input doesn't match rule `a`: matching failed at byte offset 1 (in a)"
+        );
+    }
+}
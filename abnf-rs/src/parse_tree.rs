@@ -0,0 +1,139 @@
+use crate::ast::Span;
+use crate::{Error, ErrorRepr};
+
+/// The rules that matched a piece of input, and what they matched.
+///
+/// The root is the rule that input was matched against. Each node's children are the rules that
+/// matched directly inside it, in input order. Core rules (`ALPHA`, `DIGIT`, etc.) that the
+/// grammar didn't define itself aren't included.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseTree<'i> {
+    /// The name of the rule, as it was written in the grammar
+    pub rule: String,
+    /// The byte offsets in the input that the rule matched
+    pub span: Span,
+    /// The input that the rule matched
+    pub text: &'i str,
+    pub children: Vec<ParseTree<'i>>,
+}
+
+impl<'i> ParseTree<'i> {
+    /// The first match of the rule named `rule` below this node, searching depth-first. Rule
+    /// names are case-insensitive.
+    pub fn get(&self, rule: &str) -> Option<&ParseTree<'i>> {
+        self.children.iter().find_map(|child| {
+            if child.rule.eq_ignore_ascii_case(rule) {
+                Some(child)
+            } else {
+                child.get(rule)
+            }
+        })
+    }
+
+    /// Like [`ParseTree::get`], but returns an error if there's no match for `rule`
+    pub fn expect(&self, rule: &str) -> Result<&ParseTree<'i>, Error> {
+        self.get(rule)
+            .ok_or_else(|| Error(ErrorRepr::MissingRule(rule.to_owned())))
+    }
+
+    /// Every match of the rule named `rule` below this node, in input order
+    pub fn get_all(&self, rule: &str) -> Vec<&ParseTree<'i>> {
+        let mut matches = Vec::new();
+        for child in &self.children {
+            if child.rule.eq_ignore_ascii_case(rule) {
+                matches.push(child);
+            }
+            matches.extend(child.get_all(rule));
+        }
+
+        matches
+    }
+}
+
+/// Build a value out of input that matched a grammar, see [`crate::Abnf::parse`]
+pub trait FromParseTree: Sized {
+    fn from_parse_tree(tree: &ParseTree<'_>) -> Result<Self, Error>;
+}
+
+impl FromParseTree for String {
+    fn from_parse_tree(tree: &ParseTree<'_>) -> Result<Self, Error> {
+        Ok(tree.text.to_owned())
+    }
+}
+
+impl FromParseTree for () {
+    fn from_parse_tree(_tree: &ParseTree<'_>) -> Result<Self, Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::Span;
+    use crate::Grammar;
+
+    const NSID_ABNF: &str = r#"nsid      = authority "." name
+authority = segment *( "." segment )
+name      = segment
+segment   = ALPHA *( ALPHA / DIGIT / "-" )
+"#;
+
+    #[test]
+    fn test_parse_tree() {
+        let grammar = Grammar::from_metasyntax(NSID_ABNF).unwrap();
+        let tree = grammar.parse_rule("nsid", "com.example.status").unwrap();
+
+        assert_eq!(tree.rule, "nsid");
+        assert_eq!(tree.span, Span::new(0, 18));
+        assert_eq!(tree.text, "com.example.status");
+
+        let authority = tree.get("authority").unwrap();
+        assert_eq!(authority.text, "com.example");
+        assert_eq!(authority.span, Span::new(0, 11));
+        assert_eq!(tree.get("NAME").unwrap().text, "status");
+
+        let segments: Vec<_> = tree.get_all("segment").iter().map(|s| s.text).collect();
+        assert_eq!(segments, vec!["com", "example", "status"]);
+        let segments: Vec<_> = authority
+            .get_all("segment")
+            .iter()
+            .map(|s| s.text)
+            .collect();
+        assert_eq!(segments, vec!["com", "example"]);
+
+        // Core rules aren't included
+        assert!(tree.get("ALPHA").is_none());
+        assert_eq!(
+            tree.expect("ALPHA").unwrap_err().to_string(),
+            "parse tree doesn't contain a match for rule `ALPHA`"
+        );
+    }
+
+    #[test]
+    fn test_parse_tree_backtracks() {
+        let grammar = Grammar::from_metasyntax("as = *a last\na = \"a\"\nlast = \"a\"\n").unwrap();
+        let tree = grammar.parse_rule("as", "aaa").unwrap();
+        let rules: Vec<_> = tree
+            .children
+            .iter()
+            .map(|c| (c.rule.as_str(), c.span.start))
+            .collect();
+        assert_eq!(rules, vec![("a", 0), ("a", 1), ("last", 2)]);
+    }
+
+    #[test]
+    fn test_parse_tree_with_left_recursion() {
+        let grammar =
+            Grammar::from_metasyntax("list = list \",\" item / item\nitem = \"x\"\n").unwrap();
+        let tree = grammar.parse_rule("list", "x").unwrap();
+        assert_eq!(tree.get("item").unwrap().text, "x");
+
+        let tree = grammar.parse_rule("list", "x,x,x").unwrap();
+        let children: Vec<_> = tree
+            .children
+            .iter()
+            .map(|c| (c.rule.as_str(), c.text))
+            .collect();
+        assert_eq!(children, vec![("list", "x,x"), ("item", "x")]);
+    }
+}
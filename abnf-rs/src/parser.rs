@@ -1,10 +1,13 @@
-// TODO remove this once this stuff starts getting used
-#![allow(dead_code)]
-
+use crate::ast::{
+    Alternation, Base, CharVal, Comment, Concatenation, DefinedAs, Element, NumVal, NumValue,
+    Repetition, Rule, RuleList, Span,
+};
 use nom::branch::alt;
-use nom::character::complete::{char, digit0, digit1, hex_digit1, line_ending, space1};
-use nom::combinator::opt;
-use nom::multi::{many0, many1};
+use nom::character::complete::{char, digit0, digit1, line_ending, one_of, space1};
+use nom::combinator::{all_consuming, consumed, eof, map, map_res, opt, value};
+use nom::error::ErrorKind;
+use nom::multi::{many0, many1, separated_list1};
+use nom::sequence::{delimited, preceded, separated_pair, terminated};
 use nom::{
     bytes::complete::{tag, take_while, take_while1},
     character::complete::alpha1,
@@ -12,9 +15,15 @@ use nom::{
     sequence::{pair, tuple},
     IResult,
 };
+use nom_locate::LocatedSpan;
 use std::fmt;
 use tracing::{span, trace, Level};
 
+mod diagnose;
+
+/// Grammar source annotated with its location, so that parsed nodes can carry a [`Span`]
+type Input<'a> = LocatedSpan<&'a str>;
+
 // The ABNF for ABNFs!
 // https://www.rfc-editor.org/rfc/rfc5234
 //
@@ -43,6 +52,19 @@ use tracing::{span, trace, Level};
 //  char-val       =  DQUOTE *(%x20-21 / %x23-7E) DQUOTE
 //                         ; quoted string of SP and VCHAR
 //                         ;  without DQUOTE
+//
+// RFC 7405 replaces char-val with
+// https://www.rfc-editor.org/rfc/rfc7405#section-2.2
+//
+//  char-val       =  case-insensitive-string /
+//                    case-sensitive-string
+//  case-insensitive-string =
+//                    [ "%i" ] quoted-string
+//  case-sensitive-string =
+//                    "%s" quoted-string
+//  quoted-string  =  DQUOTE *(%x20-21 / %x23-7E) DQUOTE
+//                         ; quoted string of SP and VCHAR
+//                         ;  without DQUOTE
 //  num-val        =  "%" (bin-val / dec-val / hex-val)
 //  bin-val        =  "b" 1*BIT
 //                    [ 1*("." 1*BIT) / ("-" 1*BIT) ]
@@ -58,17 +80,146 @@ use tracing::{span, trace, Level};
 //                         ; prose description, to be used as
 //                         ;  last resort
 
-fn rule_list(i: &str) -> IResult<&str, &str> {
-    let span = span!(Level::TRACE, "rule_list", input = i);
+/// Parse an ABNF rule list into its AST. The entire source must be consumed.
+pub fn parse(source: &str) -> Result<RuleList, Error> {
+    match all_consuming(rule_list)(Input::new(source)) {
+        Ok((_, rule_list)) => {
+            for rule in &rule_list.rules {
+                validate_num_vals(source, &rule.elements)?;
+            }
+
+            Ok(rule_list)
+        }
+        Err(nom::Err::Failure(e)) if e.code == ErrorKind::TooLarge => Err(Error::OutOfRange {
+            location: Location::new(source, e.input.location_offset())
+                .expect("the value is within the source"),
+            value: e.input.fragment().to_string(),
+        }),
+        // nom only knows which combinator failed, so go back and work out what went wrong
+        Err(nom::Err::Error(_) | nom::Err::Failure(_)) => Err(diagnose::diagnose(source)),
+        Err(nom::Err::Incomplete(_)) => Err(Error::Unexpected("grammar source was incomplete")),
+    }
+}
+
+/// The largest Unicode code point
+const MAX_CODE_POINT: u32 = 0x10FFFF;
+
+/// Check that num-val ranges aren't backwards
+fn validate_num_vals(source: &str, alternation: &Alternation) -> Result<(), Error> {
+    for repetition in alternation
+        .concatenations
+        .iter()
+        .flat_map(|c| &c.repetitions)
+    {
+        let location =
+            || Location::new(source, repetition.span.start).expect("spans are within the source");
+        match &repetition.element {
+            Element::Group(alternation) | Element::Option(alternation) => {
+                validate_num_vals(source, alternation)?
+            }
+            Element::NumVal(num_val) => {
+                if let NumValue::Range(low, high) = &num_val.value {
+                    if low > high {
+                        return Err(Error::BackwardsRange {
+                            location: location(),
+                        });
+                    }
+                }
+            }
+            Element::RuleName(_) | Element::CharVal(_) | Element::ProseVal(_) => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn to_span(consumed: &Input) -> Span {
+    let start = consumed.location_offset();
+    Span::new(start, start + consumed.fragment().len())
+}
+
+/// Every comment in some grammar source, skipping over quoted strings and prose values that
+/// might contain a `;`
+fn comments_in(source: &Input) -> Vec<Comment> {
+    let text = source.fragment();
+    let start = source.location_offset();
+    let mut comments = Vec::new();
+    let mut closing_quote = None;
+    let mut i = 0;
+    while let Some(c) = text[i..].chars().next() {
+        match (closing_quote, c) {
+            (Some(close), c) if c == close => closing_quote = None,
+            (Some(_), _) => {}
+            (None, '"') => closing_quote = Some('"'),
+            (None, '<') => closing_quote = Some('>'),
+            (None, ';') => {
+                let end = text[i..]
+                    .find(['\r', '\n'])
+                    .map_or(text.len(), |end| i + end);
+                comments.push(Comment {
+                    text: text[i + 1..end].trim_end().to_owned(),
+                    span: Span::new(start + i, start + end),
+                });
+                i = end;
+                continue;
+            }
+            (None, _) => {}
+        }
+        i += c.len_utf8();
+    }
+
+    comments
+}
+
+/// A line (or more, for rules that continue) of a rule list
+enum Line {
+    Rule(Rule),
+    /// A blank line, or one with only a comment
+    Blank(Vec<Comment>),
+}
+
+fn rule_list(i: Input) -> IResult<Input, RuleList> {
+    let span = span!(Level::TRACE, "rule_list", input = i.fragment());
     let _enter = span.enter();
-    recognize(many1(alt((rule, recognize(pair(many0(c_wsp), c_nl))))))(i)
+    map(
+        many1(alt((
+            map(rule, Line::Rule),
+            map(recognize(pair(many0(c_wsp), c_nl)), |blank| {
+                Line::Blank(comments_in(&blank))
+            }),
+        ))),
+        |lines| {
+            let mut rule_list = RuleList {
+                rules: Vec::new(),
+                comments: Vec::new(),
+            };
+            for line in lines {
+                match line {
+                    Line::Rule(rule) => rule_list.rules.push(rule),
+                    Line::Blank(comments) => rule_list.comments.extend(comments),
+                }
+            }
+
+            rule_list
+        },
+    )(i)
 }
 
-fn rule(i: &str) -> IResult<&str, &str> {
-    let span = span!(Level::TRACE, "rule", input = i);
+fn rule(i: Input) -> IResult<Input, Rule> {
+    let span = span!(Level::TRACE, "rule", input = i.fragment());
     let _enter = span.enter();
 
-    let res = recognize(tuple((rule_name, defined_as, elements, c_nl)))(i);
+    // The final rule in a grammar is allowed to end without a newline
+    let res = map(
+        consumed(tuple((rule_name, defined_as, elements, alt((c_nl, eof))))),
+        |(consumed, (name, defined_as, elements, _))| Rule {
+            span: Span::new(name.location_offset(), elements.span.end),
+            name: name.fragment().to_string(),
+            defined_as,
+            elements,
+            comments: comments_in(&consumed),
+        },
+    )(i);
 
     if res.is_ok() {
         trace!("success")
@@ -80,8 +231,8 @@ fn rule(i: &str) -> IResult<&str, &str> {
 }
 
 // https://www.rfc-editor.org/rfc/rfc5234#section-2.1
-fn rule_name(i: &str) -> IResult<&str, &str> {
-    let span = span!(Level::TRACE, "rule_name", input = i);
+fn rule_name(i: Input) -> IResult<Input, Input> {
+    let span = span!(Level::TRACE, "rule_name", input = i.fragment());
     let _enter = span.enter();
     let res = recognize(pair(
         alpha1,
@@ -97,14 +248,18 @@ fn rule_name(i: &str) -> IResult<&str, &str> {
     res
 }
 
-fn defined_as(i: &str) -> IResult<&str, &str> {
-    let span = span!(Level::TRACE, "defined_as", input = i);
+fn defined_as(i: Input) -> IResult<Input, DefinedAs> {
+    let span = span!(Level::TRACE, "defined_as", input = i.fragment());
     let _enter = span.enter();
-    let res = recognize(tuple((
+    let res = delimited(
         many0(c_wsp),
-        alt((tag("="), tag("=/"))),
+        // Check for incremental alternatives first, otherwise "=" will always win out.
+        alt((
+            value(DefinedAs::Incremental, tag("=/")),
+            value(DefinedAs::Basic, tag("=")),
+        )),
         many0(c_wsp),
-    )))(i);
+    )(i);
 
     if res.is_ok() {
         trace!("success")
@@ -115,10 +270,10 @@ fn defined_as(i: &str) -> IResult<&str, &str> {
     res
 }
 
-fn elements(i: &str) -> IResult<&str, &str> {
-    let span = span!(Level::TRACE, "elements", input = i);
+fn elements(i: Input) -> IResult<Input, Alternation> {
+    let span = span!(Level::TRACE, "elements", input = i.fragment());
     let _enter = span.enter();
-    let res = recognize(pair(alternation, many0(c_wsp)))(i);
+    let res = terminated(alternation, many0(c_wsp))(i);
 
     if res.is_ok() {
         trace!("success")
@@ -129,10 +284,10 @@ fn elements(i: &str) -> IResult<&str, &str> {
     res
 }
 
-fn c_wsp(i: &str) -> IResult<&str, &str> {
-    let span = span!(Level::TRACE, "c_wsp", input = i);
+fn c_wsp(i: Input) -> IResult<Input, Input> {
+    let span = span!(Level::TRACE, "c_wsp", input = i.fragment());
     let _enter = span.enter();
-    let res = alt((tag(" "), recognize(pair(c_nl, char(' ')))))(i);
+    let res = alt((recognize(wsp), recognize(pair(c_nl, wsp))))(i);
 
     if res.is_ok() {
         trace!("success")
@@ -143,8 +298,12 @@ fn c_wsp(i: &str) -> IResult<&str, &str> {
     res
 }
 
-fn c_nl(i: &str) -> IResult<&str, &str> {
-    let span = span!(Level::TRACE, "c_nl", input = i);
+fn wsp(i: Input) -> IResult<Input, char> {
+    one_of(" \t")(i)
+}
+
+fn c_nl(i: Input) -> IResult<Input, Input> {
+    let span = span!(Level::TRACE, "c_nl", input = i.fragment());
     let _enter = span.enter();
     let res = alt((comment, line_ending))(i);
 
@@ -157,8 +316,8 @@ fn c_nl(i: &str) -> IResult<&str, &str> {
     res
 }
 
-fn comment(i: &str) -> IResult<&str, &str> {
-    let span = span!(Level::TRACE, "comment", input = i);
+fn comment(i: Input) -> IResult<Input, Input> {
+    let span = span!(Level::TRACE, "comment", input = i.fragment());
     let _enter = span.enter();
     let res = recognize(tuple((
         char(';'),
@@ -166,7 +325,8 @@ fn comment(i: &str) -> IResult<&str, &str> {
             space1,
             take_while1(|c: char| c.is_ascii() && (0x21..=0x7E).contains(&(c as u8))),
         ))),
-        line_ending,
+        // As with rules, a comment on the final line may end without a newline
+        alt((line_ending, eof)),
     )))(i);
 
     if res.is_ok() {
@@ -179,18 +339,22 @@ fn comment(i: &str) -> IResult<&str, &str> {
 }
 
 // https://www.rfc-editor.org/rfc/rfc5234#section-3.2
-fn alternation(i: &str) -> IResult<&str, &str> {
-    let span = span!(Level::TRACE, "alternation", input = i);
+fn alternation(i: Input) -> IResult<Input, Alternation> {
+    let span = span!(Level::TRACE, "alternation", input = i.fragment());
     let _enter = span.enter();
-    let res = recognize(pair(
-        concatenation,
-        many0(tuple((
-            many0(c_wsp),
-            char('/'),
-            many0(c_wsp),
+    let res = map(
+        consumed(pair(
             concatenation,
-        ))),
-    ))(i);
+            many0(preceded(
+                tuple((many0(c_wsp), char('/'), many0(c_wsp))),
+                concatenation,
+            )),
+        )),
+        |(consumed, (first, rest))| Alternation {
+            concatenations: std::iter::once(first).chain(rest).collect(),
+            span: to_span(&consumed),
+        },
+    )(i);
 
     if res.is_ok() {
         trace!("success")
@@ -202,10 +366,16 @@ fn alternation(i: &str) -> IResult<&str, &str> {
 }
 
 // https://www.rfc-editor.org/rfc/rfc5234#section-3.1
-fn concatenation(i: &str) -> IResult<&str, &str> {
-    let span = span!(Level::TRACE, "concatenation", input = i);
+fn concatenation(i: Input) -> IResult<Input, Concatenation> {
+    let span = span!(Level::TRACE, "concatenation", input = i.fragment());
     let _enter = span.enter();
-    let res = recognize(pair(repetition, many0(pair(many1(c_wsp), repetition))))(i);
+    let res = map(
+        consumed(pair(repetition, many0(preceded(many1(c_wsp), repetition)))),
+        |(consumed, (first, rest))| Concatenation {
+            repetitions: std::iter::once(first).chain(rest).collect(),
+            span: to_span(&consumed),
+        },
+    )(i);
 
     if res.is_ok() {
         trace!("success")
@@ -218,10 +388,22 @@ fn concatenation(i: &str) -> IResult<&str, &str> {
 
 // https://www.rfc-editor.org/rfc/rfc5234#section-3.6
 // https://www.rfc-editor.org/rfc/rfc5234#section-3.7
-fn repetition(i: &str) -> IResult<&str, &str> {
-    let span = span!(Level::TRACE, "repetition", input = i);
+fn repetition(i: Input) -> IResult<Input, Repetition> {
+    let span = span!(Level::TRACE, "repetition", input = i.fragment());
     let _enter = span.enter();
-    let res = recognize(pair(opt(repeat), element))(i);
+    let res = map(
+        consumed(pair(opt(repeat), element)),
+        |(consumed, (repeat, element))| {
+            // No repeat is the same as exactly one repetition
+            let (min, max) = repeat.unwrap_or((1, Some(1)));
+            Repetition {
+                min,
+                max,
+                element,
+                span: to_span(&consumed),
+            }
+        },
+    )(i);
 
     if res.is_ok() {
         trace!("success")
@@ -232,14 +414,25 @@ fn repetition(i: &str) -> IResult<&str, &str> {
     res
 }
 
-fn repeat(i: &str) -> IResult<&str, &str> {
-    let span = span!(Level::TRACE, "repeat", input = i);
+fn repeat(i: Input) -> IResult<Input, (usize, Option<usize>)> {
+    let span = span!(Level::TRACE, "repeat", input = i.fragment());
     let _enter = span.enter();
     let res = alt((
         // Check the more specific case first, otherwise the less specific case will
         // always win out.
-        recognize(tuple((digit0, char('*'), digit0))),
-        digit1,
+        map_res(
+            tuple((digit0, char('*'), digit0)),
+            |(min, _, max): (Input, _, Input)| -> Result<_, std::num::ParseIntError> {
+                let min = if min.is_empty() { 0 } else { min.parse()? };
+                let max = if max.is_empty() {
+                    None
+                } else {
+                    Some(max.parse()?)
+                };
+                Ok((min, max))
+            },
+        ),
+        map_res(digit1, |n: Input| n.parse::<usize>().map(|n| (n, Some(n)))),
     ))(i);
 
     if res.is_ok() {
@@ -251,10 +444,19 @@ fn repeat(i: &str) -> IResult<&str, &str> {
     res
 }
 
-fn element(i: &str) -> IResult<&str, &str> {
-    let span = span!(Level::TRACE, "element", input = i);
+fn element(i: Input) -> IResult<Input, Element> {
+    let span = span!(Level::TRACE, "element", input = i.fragment());
     let _enter = span.enter();
-    let res = alt((rule_name, group, option, char_val, num_val, prose_val))(i);
+    let res = alt((
+        map(rule_name, |name| {
+            Element::RuleName(name.fragment().to_string())
+        }),
+        map(group, Element::Group),
+        map(option, Element::Option),
+        map(char_val, Element::CharVal),
+        map(num_val, Element::NumVal),
+        map(prose_val, Element::ProseVal),
+    ))(i);
 
     if res.is_ok() {
         trace!("success")
@@ -266,17 +468,14 @@ fn element(i: &str) -> IResult<&str, &str> {
 }
 
 // https://www.rfc-editor.org/rfc/rfc5234#section-3.5
-fn group(i: &str) -> IResult<&str, &str> {
-    let span = span!(Level::TRACE, "group", input = i);
+fn group(i: Input) -> IResult<Input, Alternation> {
+    let span = span!(Level::TRACE, "group", input = i.fragment());
     let _enter = span.enter();
-    // TODO: split out all the elements in the group
-    let res = recognize(tuple((
-        char('('),
-        many0(c_wsp),
+    let res = delimited(
+        pair(char('('), many0(c_wsp)),
         alternation,
-        many0(c_wsp),
-        char(')'),
-    )))(i);
+        pair(many0(c_wsp), char(')')),
+    )(i);
 
     if res.is_ok() {
         trace!("success")
@@ -288,17 +487,14 @@ fn group(i: &str) -> IResult<&str, &str> {
 }
 
 // https://www.rfc-editor.org/rfc/rfc5234#section-3.8
-fn option(i: &str) -> IResult<&str, &str> {
-    let span = span!(Level::TRACE, "option", input = i);
+fn option(i: Input) -> IResult<Input, Alternation> {
+    let span = span!(Level::TRACE, "option", input = i.fragment());
     let _enter = span.enter();
-    // TODO: split out all the elements in the option
-    let res = recognize(tuple((
-        char('['),
-        many0(c_wsp),
+    let res = delimited(
+        pair(char('['), many0(c_wsp)),
         alternation,
-        many0(c_wsp),
-        char(']'),
-    )))(i);
+        pair(many0(c_wsp), char(']')),
+    )(i);
 
     if res.is_ok() {
         trace!("success")
@@ -309,20 +505,47 @@ fn option(i: &str) -> IResult<&str, &str> {
     res
 }
 
-fn char_val(i: &str) -> IResult<&str, &str> {
-    let span = span!(Level::TRACE, "char_val", input = i);
+fn char_val(i: Input) -> IResult<Input, CharVal> {
+    let span = span!(Level::TRACE, "char_val", input = i.fragment());
     let _enter = span.enter();
-    let res = recognize(tuple((
-        char('"'),
-        take_while(|c: char| {
-            // take while `c` is ASCII and is in the range (inclusive) of
-            // 0x20 (space) to 0x21 (!) or 0x23 (#) to 0x7E (~)
-            // _(This range excludes double quotes)_
-            c.is_ascii()
-                && ((0x20..=0x21).contains(&(c as u8)) || (0x23..=0x7E).contains(&(c as u8)))
-        }),
-        char('"'),
-    )))(i);
+    // Like everything else in ABNF, the `s` and `i` are case-insensitive
+    let res = alt((
+        map(
+            preceded(pair(char('%'), one_of("sS")), quoted_string),
+            CharVal::sensitive,
+        ),
+        map(
+            preceded(opt(pair(char('%'), one_of("iI"))), quoted_string),
+            CharVal::insensitive,
+        ),
+    ))(i);
+
+    if res.is_ok() {
+        trace!("success")
+    } else {
+        trace!("failure")
+    };
+
+    res
+}
+
+fn quoted_string(i: Input) -> IResult<Input, String> {
+    let span = span!(Level::TRACE, "quoted_string", input = i.fragment());
+    let _enter = span.enter();
+    let res = map(
+        delimited(
+            char('"'),
+            take_while(|c: char| {
+                // take while `c` is ASCII and is in the range (inclusive) of
+                // 0x20 (space) to 0x21 (!) or 0x23 (#) to 0x7E (~)
+                // _(This range excludes double quotes)_
+                c.is_ascii()
+                    && ((0x20..=0x21).contains(&(c as u8)) || (0x23..=0x7E).contains(&(c as u8)))
+            }),
+            char('"'),
+        ),
+        |s: Input| s.fragment().to_string(),
+    )(i);
 
     if res.is_ok() {
         trace!("success")
@@ -334,10 +557,10 @@ fn char_val(i: &str) -> IResult<&str, &str> {
 }
 
 // https://www.rfc-editor.org/rfc/rfc5234#section-2.3
-fn num_val(i: &str) -> IResult<&str, &str> {
-    let span = span!(Level::TRACE, "num_val", input = i);
+fn num_val(i: Input) -> IResult<Input, NumVal> {
+    let span = span!(Level::TRACE, "num_val", input = i.fragment());
     let _enter = span.enter();
-    let res = recognize(pair(char('%'), alt((bin_val, dec_val, hex_val))))(i);
+    let res = preceded(char('%'), alt((bin_val, dec_val, hex_val)))(i);
 
     if res.is_ok() {
         trace!("success")
@@ -348,13 +571,10 @@ fn num_val(i: &str) -> IResult<&str, &str> {
     res
 }
 
-fn bin_val(i: &str) -> IResult<&str, &str> {
-    let span = span!(Level::TRACE, "bin_val", input = i);
+fn bin_val(i: Input) -> IResult<Input, NumVal> {
+    let span = span!(Level::TRACE, "bin_val", input = i.fragment());
     let _enter = span.enter();
-    // TODO convert this output to a number or something
-    // TODO handle the weird concatenated version of these
-    //    https://www.rfc-editor.org/rfc/rfc5234#section-2.3
-    let res = recognize(pair(char('b'), take_while1(|c| c == '0' || c == '1')))(i);
+    let res = preceded(one_of("bB"), num_value(Base::Binary))(i);
 
     if res.is_ok() {
         trace!("success")
@@ -365,13 +585,10 @@ fn bin_val(i: &str) -> IResult<&str, &str> {
     res
 }
 
-fn dec_val(i: &str) -> IResult<&str, &str> {
-    let span = span!(Level::TRACE, "dec_val", input = i);
+fn dec_val(i: Input) -> IResult<Input, NumVal> {
+    let span = span!(Level::TRACE, "dec_val", input = i.fragment());
     let _enter = span.enter();
-    // TODO convert this output to a number or something
-    // TODO handle the weird concatenated version of these
-    //    https://www.rfc-editor.org/rfc/rfc5234#section-2.3
-    let res = recognize(pair(char('d'), digit1))(i);
+    let res = preceded(one_of("dD"), num_value(Base::Decimal))(i);
 
     if res.is_ok() {
         trace!("success")
@@ -382,13 +599,10 @@ fn dec_val(i: &str) -> IResult<&str, &str> {
     res
 }
 
-fn hex_val(i: &str) -> IResult<&str, &str> {
-    let span = span!(Level::TRACE, "hex_val", input = i);
+fn hex_val(i: Input) -> IResult<Input, NumVal> {
+    let span = span!(Level::TRACE, "hex_val", input = i.fragment());
     let _enter = span.enter();
-    // TODO convert this output to a number or something
-    // TODO handle the weird concatenated version of these
-    //    https://www.rfc-editor.org/rfc/rfc5234#section-2.3
-    let res = recognize(pair(char('x'), hex_digit1))(i);
+    let res = preceded(one_of("xX"), num_value(Base::Hexadecimal))(i);
 
     if res.is_ok() {
         trace!("success")
@@ -399,18 +613,52 @@ fn hex_val(i: &str) -> IResult<&str, &str> {
     res
 }
 
+/// A `-` separated range of values or a `.` separated sequence of values, written in the given
+/// base
+fn num_value<'a>(base: Base) -> impl FnMut(Input<'a>) -> IResult<Input<'a>, NumVal> {
+    map(
+        alt((
+            map(
+                separated_pair(digits(base), char('-'), digits(base)),
+                |(low, high)| NumValue::Range(low, high),
+            ),
+            map(separated_list1(char('.'), digits(base)), NumValue::Sequence),
+        )),
+        move |value| NumVal { base, value },
+    )
+}
+
+/// A num-val value, which must be a Unicode code point. Anything larger fails with
+/// [`ErrorKind::TooLarge`] so that [`parse`] can report the value rather than a syntax error.
+fn digits<'a>(base: Base) -> impl FnMut(Input<'a>) -> IResult<Input<'a>, u32> {
+    let radix = base.radix();
+    move |i| {
+        let (rest, d) = take_while1(move |c: char| c.is_digit(radix))(i)?;
+        match u32::from_str_radix(d.fragment(), radix) {
+            Ok(value) if value <= MAX_CODE_POINT => Ok((rest, value)),
+            _ => Err(nom::Err::Failure(nom::error::Error::new(
+                d,
+                ErrorKind::TooLarge,
+            ))),
+        }
+    }
+}
+
 // https://www.rfc-editor.org/rfc/rfc5234#section-2.3
-fn prose_val(i: &str) -> IResult<&str, &str> {
-    let span = span!(Level::TRACE, "prose_val", input = i);
+fn prose_val(i: Input) -> IResult<Input, String> {
+    let span = span!(Level::TRACE, "prose_val", input = i.fragment());
     let _enter = span.enter();
-    let res = recognize(tuple((
-        char('<'),
-        take_while(|c: char| {
-            c.is_ascii()
-                && ((0x20..=0x3D).contains(&(c as u8)) || (0x3F..=0x7E).contains(&(c as u8)))
-        }),
-        char('>'),
-    )))(i);
+    let res = map(
+        delimited(
+            char('<'),
+            take_while(|c: char| {
+                c.is_ascii()
+                    && ((0x20..=0x3D).contains(&(c as u8)) || (0x3F..=0x7E).contains(&(c as u8)))
+            }),
+            char('>'),
+        ),
+        |s: Input| s.fragment().to_string(),
+    )(i);
 
     if res.is_ok() {
         trace!("success")
@@ -421,15 +669,126 @@ fn prose_val(i: &str) -> IResult<&str, &str> {
     res
 }
 
+/// Where in the grammar source an error was found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    /// Byte offset into the source
+    pub offset: usize,
+    /// 1-based line number
+    pub line: usize,
+    /// 1-based column, counted in characters
+    pub column: usize,
+    /// The line of source containing the error, without its line ending
+    pub source_line: String,
+}
+
+impl Location {
+    /// Where `offset` is in `source`, or `None` if it isn't the offset of a character in `source`
+    /// or of its end
+    pub(crate) fn new(source: &str, offset: usize) -> Option<Self> {
+        if !source.is_char_boundary(offset) {
+            return None;
+        }
+        let line_start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[offset..]
+            .find(['\r', '\n'])
+            .map_or(source.len(), |i| offset + i);
+
+        Some(Self {
+            offset,
+            line: source[..offset].matches('\n').count() + 1,
+            column: source[line_start..offset].chars().count() + 1,
+            source_line: source[line_start..line_end].to_owned(),
+        })
+    }
+
+    /// The source line with a caret under the error, e.g.
+    ///
+    /// ```text
+    /// 3 | segment   - ALPHA
+    ///   |           ^
+    /// ```
+    pub fn snippet(&self) -> String {
+        let gutter = self.line.to_string();
+        // Keep tabs so that the caret lines up with the source line
+        let padding: String = self
+            .source_line
+            .chars()
+            .take(self.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+
+        format!(
+            "{gutter} | {}\n{:width$} | {padding}^",
+            self.source_line,
+            "",
+            width = gutter.len()
+        )
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     Unexpected(&'static str),
+    /// The grammar isn't valid ABNF. `message` says what was expected at `location`.
+    Syntax {
+        location: Location,
+        message: String,
+    },
+    /// A num-val range whose low value is greater than its high value e.g. `%x5A-41`
+    BackwardsRange {
+        location: Location,
+    },
+    /// A num-val value that isn't a Unicode code point e.g. `%x110000`
+    OutOfRange {
+        location: Location,
+        /// The value's digits, as written in the grammar source
+        value: String,
+    },
+}
+
+impl Error {
+    /// Where in the grammar source the error was found
+    pub fn location(&self) -> Option<&Location> {
+        match self {
+            Error::Unexpected(_) => None,
+            Error::Syntax { location, .. }
+            | Error::BackwardsRange { location }
+            | Error::OutOfRange { location, .. } => Some(location),
+        }
+    }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Error::Unexpected(message) => write!(f, "unexpected error: {}", message),
+            Error::Syntax { location, message } => write!(
+                f,
+                "invalid ABNF at {}: {}\n{}",
+                location,
+                message,
+                location.snippet()
+            ),
+            Error::BackwardsRange { location } => write!(
+                f,
+                "num-val range at {} has a low value greater than its high value\n{}",
+                location,
+                location.snippet()
+            ),
+            Error::OutOfRange { location, value } => write!(
+                f,
+                "num-val value `{}` at {} is outside the Unicode range\n{}",
+                value,
+                location,
+                location.snippet()
+            ),
         }
     }
 }
@@ -437,6 +796,7 @@ impl fmt::Display for Error {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::Grammar;
     use pretty_assertions::assert_eq;
     use std::sync::Once;
 
@@ -447,13 +807,106 @@ mod tests {
         });
     }
 
+    fn rule_name_element(name: &str) -> Element {
+        Element::RuleName(name.to_owned())
+    }
+
+    /// Collect the top-level elements of each alternative, ignoring spans
+    fn alternatives(alternation: &Alternation) -> Vec<Vec<Element>> {
+        alternation
+            .concatenations
+            .iter()
+            .map(|c| c.repetitions.iter().map(|r| r.element.clone()).collect())
+            .collect()
+    }
+
     #[test]
     fn test_parse_the_abnf_abnf() {
         init_logger();
         let input = include_str!("../abnf.abnf");
-        let (remaining, s) = rule_list(input).unwrap();
-        assert_eq!(remaining, "");
-        assert_eq!(input, s);
+        let rule_list = parse(input).unwrap();
+        let names: Vec<_> = rule_list.rules.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "rulelist",
+                "rule",
+                "rulename",
+                "defined-as",
+                "elements",
+                "c-wsp",
+                "c-nl",
+                "comment",
+                "alternation",
+                "concatenation",
+                "repetition",
+                "repeat",
+                "element",
+                "group",
+                "option",
+                "char-val",
+                "num-val",
+                "bin-val",
+                "dec-val",
+                "hex-val",
+                "prose-val",
+            ]
+        );
+
+        // Spans point back into the grammar source
+        let rulelist = &rule_list.rules[0];
+        assert_eq!(
+            &input[rulelist.span.start..rulelist.span.end],
+            "rulelist       =  1*( rule / (*c-wsp c-nl) )"
+        );
+        let prose_val = rule_list.rules.last().unwrap();
+        assert_eq!(
+            &input[prose_val.elements.span.start..prose_val.elements.span.end],
+            r#""<" *(%x20-3D / %x3F-7E) ">""#
+        );
+
+        // Printing the rules and parsing them again gives back the same rules and comments
+        let summary = |rule_list: &RuleList| -> Vec<(String, String, Vec<String>)> {
+            rule_list
+                .rules
+                .iter()
+                .map(|r| {
+                    let comments = r.comments.iter().map(|c| c.text.clone()).collect();
+                    (r.name.clone(), r.elements.to_string(), comments)
+                })
+                .collect()
+        };
+        let reparsed = parse(&crate::printer::print(&rule_list)).unwrap();
+        assert_eq!(summary(&reparsed), summary(&rule_list));
+        assert_eq!(
+            rule_list.rules[1].comments[1].text, "  with white space",
+            "comments keep their indentation"
+        );
+
+        // The ABNF for ABNFs only relies on the core rules, so it can describe itself. It's
+        // strict about newlines being CRLF though.
+        let grammar = Grammar::from_rule_list(rule_list).unwrap();
+        let mut crlf_input = input.replace('\n', "\r\n");
+        if !crlf_input.ends_with("\r\n") {
+            crlf_input.push_str("\r\n");
+        }
+        grammar.match_rule("rulelist", &crlf_input).unwrap();
+        assert!(grammar.match_rule("rulelist", input).is_err());
+    }
+
+    #[test]
+    fn test_parse_rejects_trailing_garbage() {
+        init_logger();
+
+        let input = "a = b\n1nvalid\n";
+        let err = parse(input).expect_err("rule names can't start with a digit");
+        assert_eq!(err.location().unwrap().offset, 6);
+        assert_eq!(
+            err.to_string(),
+            "invalid ABNF at line 2, column 1: rule names must start with a letter\n\
+             2 | 1nvalid\n\
+             \x20 | ^"
+        );
     }
 
     #[test]
@@ -461,14 +914,40 @@ mod tests {
         init_logger();
 
         let input = "elements       =  alternation *c-wsp\r\n";
-        let (remaining, s) = rule(input).unwrap();
-        assert_eq!(remaining, "");
-        assert_eq!(input, s);
+        let (remaining, r) = rule(Input::new(input)).unwrap();
+        assert_eq!(*remaining.fragment(), "");
+        assert_eq!(r.name, "elements");
+        assert_eq!(r.defined_as, DefinedAs::Basic);
+        assert_eq!(r.span, Span::new(0, 36));
+        assert_eq!(
+            alternatives(&r.elements),
+            vec![vec![
+                rule_name_element("alternation"),
+                rule_name_element("c-wsp"),
+            ]]
+        );
+        assert_eq!(r.elements.concatenations[0].repetitions[1].min, 0);
+        assert_eq!(r.elements.concatenations[0].repetitions[1].max, None);
 
         let input = "element        =  rulename / group / option \r\n                  char-val / num-val / prose-val\r\n";
-        let (remaining, s) = rule(input).unwrap();
-        assert_eq!(remaining, "");
-        assert_eq!(input, s);
+        let (remaining, r) = rule(Input::new(input)).unwrap();
+        assert_eq!(*remaining.fragment(), "");
+        assert_eq!(r.name, "element");
+        assert_eq!(
+            alternatives(&r.elements),
+            vec![
+                vec![rule_name_element("rulename")],
+                vec![rule_name_element("group")],
+                vec![rule_name_element("option"), rule_name_element("char-val")],
+                vec![rule_name_element("num-val")],
+                vec![rule_name_element("prose-val")],
+            ]
+        );
+
+        let input = "ruleset =/ alt3 / alt4\n";
+        let (remaining, r) = rule(Input::new(input)).unwrap();
+        assert_eq!(*remaining.fragment(), "");
+        assert_eq!(r.defined_as, DefinedAs::Incremental);
     }
 
     #[test]
@@ -476,19 +955,19 @@ mod tests {
         init_logger();
 
         let input = "rule-name";
-        let (remaining, s) = rule_name(input).unwrap();
-        assert_eq!(remaining, "");
-        assert_eq!(input, s);
+        let (remaining, s) = rule_name(Input::new(input)).unwrap();
+        assert_eq!(*remaining.fragment(), "");
+        assert_eq!(input, *s.fragment());
 
         let input = "x100910912";
-        let (remaining, s) = rule_name(input).unwrap();
-        assert_eq!(remaining, "");
-        assert_eq!(input, s);
+        let (remaining, s) = rule_name(Input::new(input)).unwrap();
+        assert_eq!(*remaining.fragment(), "");
+        assert_eq!(input, *s.fragment());
 
         let input = "x----";
-        let (remaining, s) = rule_name(input).unwrap();
-        assert_eq!(remaining, "");
-        assert_eq!(input, s);
+        let (remaining, s) = rule_name(Input::new(input)).unwrap();
+        assert_eq!(*remaining.fragment(), "");
+        assert_eq!(input, *s.fragment());
     }
 
     #[test]
@@ -496,17 +975,17 @@ mod tests {
         init_logger();
 
         let input = "1-rule-name";
-        let err = rule_name(input).expect_err("rule name is invalid");
+        let err = rule_name(Input::new(input)).expect_err("rule name is invalid");
         assert_eq!(
             err.to_string(),
-            "Parsing Error: Error { input: \"1-rule-name\", code: Alpha }"
+            "Parsing Error: Error { input: LocatedSpan { offset: 0, line: 1, fragment: \"1-rule-name\", extra: () }, code: Alpha }"
         );
 
         let input = "-rule-name";
-        let err = rule_name(input).expect_err("rule name is invalid");
+        let err = rule_name(Input::new(input)).expect_err("rule name is invalid");
         assert_eq!(
             err.to_string(),
-            "Parsing Error: Error { input: \"-rule-name\", code: Alpha }"
+            "Parsing Error: Error { input: LocatedSpan { offset: 0, line: 1, fragment: \"-rule-name\", extra: () }, code: Alpha }"
         );
     }
 
@@ -515,20 +994,20 @@ mod tests {
         init_logger();
 
         let input = "\r\n";
-        let (remaining, s) = c_nl(input).unwrap();
-        assert_eq!(remaining, "");
-        assert_eq!(input, s);
+        let (remaining, s) = c_nl(Input::new(input)).unwrap();
+        assert_eq!(*remaining.fragment(), "");
+        assert_eq!(input, *s.fragment());
 
         let input = "\n";
-        let (remaining, s) = c_nl(input).unwrap();
-        assert_eq!(remaining, "");
-        assert_eq!(input, s);
+        let (remaining, s) = c_nl(Input::new(input)).unwrap();
+        assert_eq!(*remaining.fragment(), "");
+        assert_eq!(input, *s.fragment());
 
         // Comments count as valid line endings too
         let input = "; some comment\r\n";
-        let (remaining, s) = c_nl(input).unwrap();
-        assert_eq!(remaining, "");
-        assert_eq!(input, s);
+        let (remaining, s) = c_nl(Input::new(input)).unwrap();
+        assert_eq!(*remaining.fragment(), "");
+        assert_eq!(input, *s.fragment());
     }
 
     #[test]
@@ -536,9 +1015,9 @@ mod tests {
         init_logger();
 
         let input = "; this is a comment\r\n";
-        let (remaining, s) = comment(input).unwrap();
-        assert_eq!(remaining, "");
-        assert_eq!(input, s);
+        let (remaining, s) = comment(Input::new(input)).unwrap();
+        assert_eq!(*remaining.fragment(), "");
+        assert_eq!(input, *s.fragment());
     }
 
     #[test]
@@ -546,9 +1025,36 @@ mod tests {
         init_logger();
 
         let input = "a / b / c";
-        let (remaining, s) = alternation(input).unwrap();
-        assert_eq!(remaining, "");
-        assert_eq!(input, s);
+        let (remaining, a) = alternation(Input::new(input)).unwrap();
+        assert_eq!(*remaining.fragment(), "");
+        assert_eq!(
+            alternatives(&a),
+            vec![
+                vec![rule_name_element("a")],
+                vec![rule_name_element("b")],
+                vec![rule_name_element("c")],
+            ]
+        );
+        assert_eq!(a.span, Span::new(0, 9));
+        assert_eq!(a.concatenations[1].span, Span::new(4, 5));
+    }
+
+    #[test]
+    fn test_concatenation() {
+        init_logger();
+
+        let input = "a\tb\r\n c";
+        let (remaining, c) = concatenation(Input::new(input)).unwrap();
+        assert_eq!(*remaining.fragment(), "");
+        let elements: Vec<_> = c.repetitions.into_iter().map(|r| r.element).collect();
+        assert_eq!(
+            elements,
+            vec![
+                rule_name_element("a"),
+                rule_name_element("b"),
+                rule_name_element("c"),
+            ]
+        );
     }
 
     #[test]
@@ -557,27 +1063,30 @@ mod tests {
 
         // Repetitions may not even have a repeat count
         let input = "\"word\"";
-        let (remaining, s) = repetition(input).unwrap();
-        assert_eq!(remaining, "");
-        assert_eq!(input, s);
+        let (remaining, r) = repetition(Input::new(input)).unwrap();
+        assert_eq!(*remaining.fragment(), "");
+        assert_eq!((r.min, r.max), (1, Some(1)));
+        assert_eq!(r.element, Element::CharVal(CharVal::insensitive("word")));
+        assert_eq!(r.span, Span::new(0, 6));
 
         // 0 or more repetitions of the string "word"
         let input = "*\"word\"";
-        let (remaining, s) = repetition(input).unwrap();
-        assert_eq!(remaining, "");
-        assert_eq!(input, s);
+        let (remaining, r) = repetition(Input::new(input)).unwrap();
+        assert_eq!(*remaining.fragment(), "");
+        assert_eq!((r.min, r.max), (0, None));
 
         // 1 or more repetitions of the string "word"
         let input = "1*\"word\"";
-        let (remaining, s) = repetition(input).unwrap();
-        assert_eq!(remaining, "");
-        assert_eq!(input, s);
+        let (remaining, r) = repetition(Input::new(input)).unwrap();
+        assert_eq!(*remaining.fragment(), "");
+        assert_eq!((r.min, r.max), (1, None));
 
         // 1 to 5 repetitions of the string "word"
         let input = "1*5\"word\"";
-        let (remaining, s) = repetition(input).unwrap();
-        assert_eq!(remaining, "");
-        assert_eq!(input, s);
+        let (remaining, r) = repetition(Input::new(input)).unwrap();
+        assert_eq!(*remaining.fragment(), "");
+        assert_eq!((r.min, r.max), (1, Some(5)));
+        assert_eq!(r.span, Span::new(0, 9));
     }
 
     #[test]
@@ -586,27 +1095,33 @@ mod tests {
 
         // 0 or more repeats
         let input = "*";
-        let (remaining, s) = repeat(input).unwrap();
-        assert_eq!(remaining, "");
-        assert_eq!(input, s);
+        let (remaining, r) = repeat(Input::new(input)).unwrap();
+        assert_eq!(*remaining.fragment(), "");
+        assert_eq!(r, (0, None));
 
         // 0 or 1 repeats
         let input = "*1";
-        let (remaining, s) = repeat(input).unwrap();
-        assert_eq!(remaining, "");
-        assert_eq!(input, s);
+        let (remaining, r) = repeat(Input::new(input)).unwrap();
+        assert_eq!(*remaining.fragment(), "");
+        assert_eq!(r, (0, Some(1)));
 
         // 1 or more repeats
         let input = "1*";
-        let (remaining, s) = repeat(input).unwrap();
-        assert_eq!(remaining, "");
-        assert_eq!(input, s);
+        let (remaining, r) = repeat(Input::new(input)).unwrap();
+        assert_eq!(*remaining.fragment(), "");
+        assert_eq!(r, (1, None));
 
         // 1 to 5 repeats
         let input = "1*5";
-        let (remaining, s) = repeat(input).unwrap();
-        assert_eq!(remaining, "");
-        assert_eq!(input, s);
+        let (remaining, r) = repeat(Input::new(input)).unwrap();
+        assert_eq!(*remaining.fragment(), "");
+        assert_eq!(r, (1, Some(5)));
+
+        // exactly 3 repeats
+        let input = "3";
+        let (remaining, r) = repeat(Input::new(input)).unwrap();
+        assert_eq!(*remaining.fragment(), "");
+        assert_eq!(r, (3, Some(3)));
     }
 
     #[test]
@@ -615,39 +1130,56 @@ mod tests {
 
         // rulename
         let input = "a-rule-name-ending-with-123";
-        let (remaining, s) = element(input).unwrap();
-        assert_eq!(remaining, "");
-        assert_eq!(input, s);
+        let (remaining, e) = element(Input::new(input)).unwrap();
+        assert_eq!(*remaining.fragment(), "");
+        assert_eq!(e, rule_name_element(input));
 
         // group
         let input = "(a group)";
-        let (remaining, s) = element(input).unwrap();
-        assert_eq!(remaining, "");
-        assert_eq!(input, s);
+        let (remaining, e) = element(Input::new(input)).unwrap();
+        assert_eq!(*remaining.fragment(), "");
+        match e {
+            Element::Group(a) => assert_eq!(
+                alternatives(&a),
+                vec![vec![rule_name_element("a"), rule_name_element("group")]]
+            ),
+            e => panic!("expected a group, got {e:?}"),
+        }
 
         // option
         let input = "[repeat]";
-        let (remaining, s) = element(input).unwrap();
-        assert_eq!(remaining, "");
-        assert_eq!(input, s);
+        let (remaining, e) = element(Input::new(input)).unwrap();
+        assert_eq!(*remaining.fragment(), "");
+        match e {
+            Element::Option(a) => {
+                assert_eq!(alternatives(&a), vec![vec![rule_name_element("repeat")]])
+            }
+            e => panic!("expected an option, got {e:?}"),
+        }
 
         // char-val
         let input = "\"some string\"";
-        let (remaining, s) = element(input).unwrap();
-        assert_eq!(remaining, "");
-        assert_eq!(input, s);
+        let (remaining, e) = element(Input::new(input)).unwrap();
+        assert_eq!(*remaining.fragment(), "");
+        assert_eq!(e, Element::CharVal(CharVal::insensitive("some string")));
 
         // num-val
         let input = "%x1F";
-        let (remaining, s) = element(input).unwrap();
-        assert_eq!(remaining, "");
-        assert_eq!(input, s);
+        let (remaining, e) = element(Input::new(input)).unwrap();
+        assert_eq!(*remaining.fragment(), "");
+        assert_eq!(
+            e,
+            Element::NumVal(NumVal {
+                base: Base::Hexadecimal,
+                value: NumValue::Sequence(vec![0x1F]),
+            })
+        );
 
         // prose-val
         let input = "<prose-val>";
-        let (remaining, s) = element(input).unwrap();
-        assert_eq!(remaining, "");
-        assert_eq!(input, s);
+        let (remaining, e) = element(Input::new(input)).unwrap();
+        assert_eq!(*remaining.fragment(), "");
+        assert_eq!(e, Element::ProseVal("prose-val".to_owned()));
     }
 
     #[test]
@@ -655,9 +1187,27 @@ mod tests {
         init_logger();
 
         let input = r#""hello""#;
-        let (remaining, s) = char_val(input).unwrap();
-        assert_eq!(remaining, "");
-        assert_eq!(input, s);
+        let (remaining, s) = char_val(Input::new(input)).unwrap();
+        assert_eq!(*remaining.fragment(), "");
+        assert_eq!(s, CharVal::insensitive("hello"));
+
+        let input = r#"%i"hello""#;
+        let (remaining, s) = char_val(Input::new(input)).unwrap();
+        assert_eq!(*remaining.fragment(), "");
+        assert_eq!(s, CharVal::insensitive("hello"));
+
+        let input = r#"%s"hello""#;
+        let (remaining, s) = char_val(Input::new(input)).unwrap();
+        assert_eq!(*remaining.fragment(), "");
+        assert_eq!(s, CharVal::sensitive("hello"));
+
+        let input = r#"%S"Hello""#;
+        let (remaining, s) = char_val(Input::new(input)).unwrap();
+        assert_eq!(*remaining.fragment(), "");
+        assert_eq!(s, CharVal::sensitive("Hello"));
+
+        assert!(char_val(Input::new(r#"%x"hello""#)).is_err());
+        assert!(char_val(Input::new(r#"% s"hello""#)).is_err());
     }
 
     #[test]
@@ -665,9 +1215,27 @@ mod tests {
         init_logger();
 
         let input = r#"%b1010"#;
-        let (remaining, s) = num_val(input).unwrap();
-        assert_eq!(remaining, "");
-        assert_eq!(input, s);
+        let (remaining, n) = num_val(Input::new(input)).unwrap();
+        assert_eq!(*remaining.fragment(), "");
+        assert_eq!(n.base, Base::Binary);
+        assert_eq!(n.value, NumValue::Sequence(vec![0b1010]));
+
+        let input = r#"%b1101.1010"#;
+        let (remaining, n) = num_val(Input::new(input)).unwrap();
+        assert_eq!(*remaining.fragment(), "");
+        assert_eq!(n.value, NumValue::Sequence(vec![0b1101, 0b1010]));
+
+        let input = r#"%b110000-111001"#;
+        let (remaining, n) = num_val(Input::new(input)).unwrap();
+        assert_eq!(*remaining.fragment(), "");
+        assert_eq!(n.value, NumValue::Range(0b110000, 0b111001));
+
+        // ABNF is case-insensitive, including the base
+        let (_, n) = num_val(Input::new("%B1")).unwrap();
+        assert_eq!(n.base, Base::Binary);
+        assert_eq!(n.value, NumValue::Sequence(vec![1]));
+
+        assert!(num_val(Input::new("%b2")).is_err());
     }
 
     #[test]
@@ -675,9 +1243,26 @@ mod tests {
         init_logger();
 
         let input = r#"%d10"#;
-        let (remaining, s) = num_val(input).unwrap();
-        assert_eq!(remaining, "");
-        assert_eq!(input, s);
+        let (remaining, n) = num_val(Input::new(input)).unwrap();
+        assert_eq!(*remaining.fragment(), "");
+        assert_eq!(n.base, Base::Decimal);
+        assert_eq!(n.value, NumValue::Sequence(vec![10]));
+
+        let input = r#"%d13.10"#;
+        let (remaining, n) = num_val(Input::new(input)).unwrap();
+        assert_eq!(*remaining.fragment(), "");
+        assert_eq!(n.value, NumValue::Sequence(vec![13, 10]));
+
+        let input = r#"%d48-57"#;
+        let (remaining, n) = num_val(Input::new(input)).unwrap();
+        assert_eq!(*remaining.fragment(), "");
+        assert_eq!(n.value, NumValue::Range(48, 57));
+
+        let (_, n) = num_val(Input::new("%D65")).unwrap();
+        assert_eq!(n.base, Base::Decimal);
+        assert_eq!(n.value, NumValue::Sequence(vec![65]));
+
+        assert!(num_val(Input::new("%dA")).is_err());
     }
 
     #[test]
@@ -685,9 +1270,67 @@ mod tests {
         init_logger();
 
         let input = r#"%x10"#;
-        let (remaining, s) = num_val(input).unwrap();
-        assert_eq!(remaining, "");
-        assert_eq!(input, s);
+        let (remaining, n) = num_val(Input::new(input)).unwrap();
+        assert_eq!(*remaining.fragment(), "");
+        assert_eq!(n.base, Base::Hexadecimal);
+        assert_eq!(n.value, NumValue::Sequence(vec![0x10]));
+
+        let input = r#"%x61.62.63"#;
+        let (remaining, n) = num_val(Input::new(input)).unwrap();
+        assert_eq!(*remaining.fragment(), "");
+        assert_eq!(n.value, NumValue::Sequence(vec![0x61, 0x62, 0x63]));
+
+        let input = r#"%x41-5A"#;
+        let (remaining, n) = num_val(Input::new(input)).unwrap();
+        assert_eq!(*remaining.fragment(), "");
+        assert_eq!(n.value, NumValue::Range(0x41, 0x5A));
+
+        // A trailing `.` isn't part of the num-val
+        let input = r#"%x0D."#;
+        let (remaining, n) = num_val(Input::new(input)).unwrap();
+        assert_eq!(*remaining.fragment(), ".");
+        assert_eq!(n.value, NumValue::Sequence(vec![0x0D]));
+
+        // Ranges and sequences can't be mixed
+        let input = r#"%x30-39.41"#;
+        let (remaining, _) = num_val(Input::new(input)).unwrap();
+        assert_eq!(*remaining.fragment(), ".41");
+
+        let (_, n) = num_val(Input::new("%X41")).unwrap();
+        assert_eq!(n.base, Base::Hexadecimal);
+        assert_eq!(n.value, NumValue::Sequence(vec![0x41]));
+    }
+
+    #[test]
+    fn test_invalid_num_vals() {
+        init_logger();
+
+        let err = parse("a = \"a\" / %x5A-41\n").unwrap_err();
+        assert!(matches!(err, Error::BackwardsRange { .. }));
+        assert_eq!(
+            err.to_string(),
+            "num-val range at line 1, column 11 has a low value greater than its high value\n\
+             1 | a = \"a\" / %x5A-41\n\
+             \x20 |           ^"
+        );
+
+        let err = parse("a = 1*( %x110000 )\n").unwrap_err();
+        assert!(matches!(&err, Error::OutOfRange { value, .. } if value == "110000"));
+        assert_eq!(err.location().unwrap().offset, 10);
+
+        let err = parse("a = %d13.1114112\n").unwrap_err();
+        assert!(matches!(&err, Error::OutOfRange { value, .. } if value == "1114112"));
+
+        // Too large for any integer type, not just for Unicode
+        let err = parse("a = \"a\" / %x41-100000000\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "num-val value `100000000` at line 1, column 16 is outside the Unicode range\n\
+             1 | a = \"a\" / %x41-100000000\n\
+             \x20 |                ^"
+        );
+
+        assert!(parse("a = %x0-10FFFF\n").is_ok());
     }
 
     #[test]
@@ -695,9 +1338,9 @@ mod tests {
         init_logger();
 
         let input = r#"<hello>"#;
-        let (remaining, s) = prose_val(input).unwrap();
-        assert_eq!(remaining, "");
-        assert_eq!(input, s);
+        let (remaining, s) = prose_val(Input::new(input)).unwrap();
+        assert_eq!(*remaining.fragment(), "");
+        assert_eq!(s, "hello");
     }
 }
 
@@ -0,0 +1,409 @@
+//! Working out why a grammar didn't parse.
+//!
+//! The parser's combinators only report that they failed, not why. Once parsing has failed, this
+//! walks the source again, following the structure of the ABNF for ABNF, to find the first thing
+//! that's wrong and describe what was expected there.
+
+use super::{c_nl, c_wsp, defined_as, element, repeat, rule, rule_name, Error, Input, Location};
+use nom::character::complete::char;
+use nom::combinator::{eof, opt};
+use nom::multi::{many0, many1};
+use nom::sequence::{pair, tuple};
+use nom::{branch::alt, Slice};
+
+/// Where something went wrong, and what was expected there
+type Problem<'a> = (Input<'a>, String);
+
+pub(super) fn diagnose(source: &str) -> Error {
+    let (at, message) = find_problem(Input::new(source));
+
+    Error::Syntax {
+        location: Location::new(source, at.location_offset())
+            .expect("the problem is within the source"),
+        message,
+    }
+}
+
+fn find_problem(mut i: Input) -> Problem {
+    loop {
+        if i.fragment().is_empty() {
+            let message = if i.location_offset() == 0 {
+                "expected at least one rule"
+            } else {
+                // The parser failed but nothing here did. This shouldn't happen.
+                "invalid ABNF"
+            };
+            return (i, message.to_owned());
+        }
+
+        // Blank lines and comments
+        if let Ok((rest, _)) = pair(many0(c_wsp), c_nl)(i) {
+            i = rest;
+            continue;
+        }
+        if let Ok((rest, _)) = rule(i) {
+            i = rest;
+            continue;
+        }
+
+        return match check_rule(i) {
+            Err(problem) => problem,
+            Ok(_) => (i, "invalid rule".to_owned()),
+        };
+    }
+}
+
+/// Skip over any whitespace, comments, and line continuations
+fn skip_c_wsp(i: Input) -> Input {
+    many0(c_wsp)(i).map_or(i, |(rest, _)| rest)
+}
+
+fn first_char(i: Input) -> Option<char> {
+    i.fragment().chars().next()
+}
+
+/// Whether an element (or the repeat before one) could start here
+fn starts_repetition(i: Input) -> bool {
+    matches!(
+        first_char(i),
+        Some(c) if c.is_ascii_alphanumeric() || "*([\"%<".contains(c)
+    )
+}
+
+fn check_rule(i: Input) -> Result<Input, Problem> {
+    let (i, name) = rule_name(i).map_err(|_| {
+        let message = match first_char(i) {
+            Some(' ' | '\t') => "rules can't be indented, expected a rule name",
+            Some(c) if c.is_ascii_digit() || c == '-' => "rule names must start with a letter",
+            _ => "expected a rule name",
+        };
+        (i, message.to_owned())
+    })?;
+    let name = *name.fragment();
+
+    let (i, _) = defined_as(i).map_err(|_| {
+        (
+            skip_c_wsp(i),
+            format!("expected `=` or `=/` after rule name `{name}`"),
+        )
+    })?;
+    if !starts_repetition(i) {
+        return Err((i, format!("expected elements for rule `{name}`")));
+    }
+
+    let i = skip_c_wsp(check_alternation(i)?);
+    let (i, _) = alt((c_nl, eof))(i).map_err(|_| {
+        let message = match first_char(i) {
+            Some(c @ (')' | ']')) => format!("unexpected `{c}` in rule `{name}`"),
+            _ => format!(
+                "expected `/`, another element, a comment, or the end of the line in rule `{name}`"
+            ),
+        };
+        (i, message)
+    })?;
+
+    Ok(i)
+}
+
+fn check_alternation(i: Input) -> Result<Input, Problem> {
+    let mut i = check_concatenation(i)?;
+    loop {
+        let Ok((rest, _)) = tuple((many0(c_wsp), char('/'), many0(c_wsp)))(i) else {
+            return Ok(i);
+        };
+        if !starts_repetition(rest) {
+            return Err((rest, "expected an element after `/`".to_owned()));
+        }
+        i = check_concatenation(rest)?;
+    }
+}
+
+fn check_concatenation(i: Input) -> Result<Input, Problem> {
+    let mut i = check_repetition(i)?;
+    loop {
+        match many1(c_wsp)(i) {
+            Ok((rest, _)) if starts_repetition(rest) => i = check_repetition(rest)?,
+            _ => return Ok(i),
+        }
+    }
+}
+
+fn check_repetition(i: Input) -> Result<Input, Problem> {
+    let (rest, _) = opt(repeat)(i).map_err(|_| (i, "invalid repeat".to_owned()))?;
+    if rest.location_offset() != i.location_offset() && !starts_repetition(rest) {
+        return Err((rest, "expected an element after the repeat".to_owned()));
+    }
+
+    check_element(rest)
+}
+
+fn check_element(i: Input) -> Result<Input, Problem> {
+    if let Ok((rest, _)) = element(i) {
+        // A num-val that stops short of a range or sequence e.g. `%x30-`
+        if first_char(i) == Some('%') {
+            if let Some(c @ ('-' | '.')) = first_char(rest) {
+                return Err((
+                    rest.slice(1..),
+                    format!("expected {} after `{c}`", digits_for(i.slice(1..))),
+                ));
+            }
+        }
+        return Ok(rest);
+    }
+
+    match first_char(i) {
+        Some('(') => check_enclosed(i, ')', "group"),
+        Some('[') => check_enclosed(i, ']', "option"),
+        Some('"') => Err(check_quoted(i, '"', "quoted string")),
+        Some('<') => Err(check_quoted(i, '>', "prose value")),
+        Some('%') => Err(check_percent(i)),
+        _ => Err((
+            i,
+            "expected an element: a rule name, `(`, `[`, a quoted string, `%`, or `<`".to_owned(),
+        )),
+    }
+}
+
+/// A group or option, opened by the character at the start of `i`
+fn check_enclosed<'a>(i: Input<'a>, close: char, kind: &str) -> Result<Input<'a>, Problem<'a>> {
+    let inner = skip_c_wsp(i.slice(1..));
+    if !starts_repetition(inner) {
+        return Err((inner, format!("expected an element inside the {kind}")));
+    }
+
+    let rest = skip_c_wsp(check_alternation(inner)?);
+    if first_char(rest) == Some(close) {
+        return Ok(rest.slice(1..));
+    }
+
+    Err((
+        rest,
+        format!(
+            "expected `{close}` to close the {kind} opened at line {}, column {}",
+            i.location_line(),
+            i.get_utf8_column()
+        ),
+    ))
+}
+
+/// A quoted string or prose value, opened by the character at the start of `i`
+fn check_quoted<'a>(i: Input<'a>, close: char, kind: &str) -> Problem<'a> {
+    for (offset, c) in i.fragment().char_indices().skip(1) {
+        let at = i.slice(offset..);
+        match c {
+            '\r' | '\n' => return (at, format!("unterminated {kind}, expected `{close}`")),
+            // The parser should have accepted this
+            c if c == close => return (i, format!("invalid {kind}")),
+            ' '..='~' => {}
+            _ => {
+                return (
+                    at,
+                    format!("a {kind} can only contain printable ASCII characters and spaces"),
+                )
+            }
+        }
+    }
+
+    let end = i.slice(i.fragment().len()..);
+    (end, format!("unterminated {kind}, expected `{close}`"))
+}
+
+fn check_percent(i: Input) -> Problem {
+    let after_percent = i.slice(1..);
+    match first_char(after_percent) {
+        Some(c @ ('s' | 'S' | 'i' | 'I')) => {
+            let string = after_percent.slice(1..);
+            if first_char(string) == Some('"') {
+                check_quoted(string, '"', "quoted string")
+            } else {
+                (string, format!("expected a quoted string after `%{c}`"))
+            }
+        }
+        Some(c @ ('b' | 'B' | 'd' | 'D' | 'x' | 'X')) => (
+            after_percent.slice(1..),
+            format!("expected {} after `%{c}`", digits_for(after_percent)),
+        ),
+        _ => (
+            after_percent,
+            "expected `b`, `d`, `x`, `s`, or `i` after `%`".to_owned(),
+        ),
+    }
+}
+
+/// The kind of digits that a num-val takes, given the input starting at its `b`, `d`, or `x`
+fn digits_for(i: Input) -> &'static str {
+    match first_char(i) {
+        Some('b' | 'B') => "binary digits",
+        Some('d' | 'D') => "decimal digits",
+        _ => "hexadecimal digits",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::parse;
+    use pretty_assertions::assert_eq;
+
+    /// The message, line, and column of the error from parsing `source`
+    fn problem(source: &str) -> (String, usize, usize) {
+        let err = parse(source).expect_err("grammar is invalid");
+        let location = err.location().unwrap();
+        let message = err.to_string();
+        let message = message
+            .lines()
+            .next()
+            .unwrap()
+            .split_once(": ")
+            .unwrap()
+            .1
+            .to_owned();
+
+        (message, location.line, location.column)
+    }
+
+    #[test]
+    fn test_rule_problems() {
+        assert_eq!(
+            problem("nsid = authority\nsegment   - ALPHA\n"),
+            (
+                "expected `=` or `=/` after rule name `segment`".to_owned(),
+                2,
+                11
+            )
+        );
+        assert_eq!(
+            problem("  a = b\n"),
+            (
+                "rules can't be indented, expected a rule name".to_owned(),
+                1,
+                1
+            )
+        );
+        // Indented lines continue the rule above them
+        assert_eq!(
+            problem("a = b\n  c = d\n"),
+            (
+                "expected `/`, another element, a comment, or the end of the line in rule `a`"
+                    .to_owned(),
+                2,
+                5
+            )
+        );
+        assert_eq!(
+            problem("-a = b\n"),
+            ("rule names must start with a letter".to_owned(), 1, 1)
+        );
+        assert_eq!(
+            problem("a =\n"),
+            ("expected elements for rule `a`".to_owned(), 1, 4)
+        );
+        assert_eq!(problem(""), ("expected at least one rule".to_owned(), 1, 1));
+        assert_eq!(
+            problem("a = b = c\n"),
+            (
+                "expected `/`, another element, a comment, or the end of the line in rule `a`"
+                    .to_owned(),
+                1,
+                7
+            )
+        );
+    }
+
+    #[test]
+    fn test_element_problems() {
+        assert_eq!(
+            problem("a = b /\n"),
+            ("expected an element after `/`".to_owned(), 1, 8)
+        );
+        assert_eq!(
+            problem("a = b / = c\n"),
+            ("expected an element after `/`".to_owned(), 1, 9)
+        );
+        assert_eq!(
+            problem("a = 2*\n"),
+            ("expected an element after the repeat".to_owned(), 1, 7)
+        );
+        assert_eq!(
+            problem("a = b )\n"),
+            ("unexpected `)` in rule `a`".to_owned(), 1, 7)
+        );
+        assert_eq!(
+            problem("a = ( b / c\nd = e\n"),
+            (
+                "expected `)` to close the group opened at line 1, column 5".to_owned(),
+                1,
+                12
+            )
+        );
+        assert_eq!(
+            problem("a = [ ]\n"),
+            ("expected an element inside the option".to_owned(), 1, 7)
+        );
+        assert_eq!(
+            problem("a = b \"c\n"),
+            ("unterminated quoted string, expected `\"`".to_owned(), 1, 9)
+        );
+        assert_eq!(
+            problem("a = \"caf\u{e9}\"\n"),
+            (
+                "a quoted string can only contain printable ASCII characters and spaces".to_owned(),
+                1,
+                9
+            )
+        );
+        assert_eq!(
+            problem("a = <some prose\n"),
+            ("unterminated prose value, expected `>`".to_owned(), 1, 16)
+        );
+    }
+
+    #[test]
+    fn test_num_val_problems() {
+        assert_eq!(
+            problem("a = %q20\n"),
+            (
+                "expected `b`, `d`, `x`, `s`, or `i` after `%`".to_owned(),
+                1,
+                6
+            )
+        );
+        assert_eq!(
+            problem("a = %xZZ\n"),
+            ("expected hexadecimal digits after `%x`".to_owned(), 1, 7)
+        );
+        assert_eq!(
+            problem("a = %D\n"),
+            ("expected decimal digits after `%D`".to_owned(), 1, 7)
+        );
+        assert_eq!(
+            problem("a = %d13.\n"),
+            ("expected decimal digits after `.`".to_owned(), 1, 10)
+        );
+        assert_eq!(
+            problem("a = %b0-\n"),
+            ("expected binary digits after `-`".to_owned(), 1, 9)
+        );
+        assert_eq!(
+            problem("a = %s'abc'\n"),
+            ("expected a quoted string after `%s`".to_owned(), 1, 7)
+        );
+    }
+
+    #[test]
+    fn test_snippet() {
+        let err = parse("a = b\n\tc = d\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid ABNF at line 2, column 4: expected `/`, another element, a comment, or the \
+             end of the line in rule `a`\n\
+             2 | \tc = d\n\
+             \x20 | \t  ^"
+        );
+
+        let err = parse("a = ( b\n").unwrap_err();
+        assert_eq!(
+            err.location().unwrap().snippet(),
+            "1 | a = ( b\n  |        ^"
+        );
+    }
+}
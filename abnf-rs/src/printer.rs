@@ -0,0 +1,114 @@
+//! Writing rule lists back out as ABNF.
+
+use crate::ast::{DefinedAs, Rule, RuleList};
+
+/// Fold incremental alternatives (`=/`) into the rule they extend, along with their comments.
+///
+/// Alternatives for rules that the rule list doesn't define, like `ALPHA =/ "_"` extending a core
+/// rule, are left as they are.
+pub fn normalize(rule_list: RuleList) -> RuleList {
+    let mut rules: Vec<Rule> = Vec::with_capacity(rule_list.rules.len());
+    for rule in rule_list.rules {
+        let base = rules.iter_mut().find(|base| {
+            base.defined_as == DefinedAs::Basic && base.name.eq_ignore_ascii_case(&rule.name)
+        });
+        match (rule.defined_as, base) {
+            (DefinedAs::Incremental, Some(base)) => {
+                base.elements
+                    .concatenations
+                    .extend(rule.elements.concatenations);
+                base.comments.extend(rule.comments);
+            }
+            _ => rules.push(rule),
+        }
+    }
+
+    RuleList {
+        rules,
+        comments: rule_list.comments,
+    }
+}
+
+/// Write a rule list out in a canonical form: one line per rule with the `=`s lined up, a single
+/// space between elements, and each comment on a line of its own.
+///
+/// Comments within a rule follow it, indented to line up with its elements. Comments between
+/// rules stay where they were.
+pub fn print(rule_list: &RuleList) -> String {
+    let width = rule_list
+        .rules
+        .iter()
+        .map(|rule| rule.name.len())
+        .max()
+        .unwrap_or(0);
+    let indent = " ".repeat(width + 3);
+
+    let mut out = String::new();
+    let mut comments = rule_list.comments.iter().peekable();
+    for rule in &rule_list.rules {
+        while let Some(comment) = comments.next_if(|c| c.span.start < rule.span.start) {
+            out.push_str(&format!(";{}\n", comment.text));
+        }
+
+        out.push_str(&format!(
+            "{:width$} {} {}\n",
+            rule.name, rule.defined_as, rule.elements
+        ));
+        for comment in &rule.comments {
+            out.push_str(&format!("{indent};{}\n", comment.text));
+        }
+    }
+    for comment in comments {
+        out.push_str(&format!(";{}\n", comment.text));
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalize, print};
+    use crate::parser::parse;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_print() {
+        let source = r#"; NSIDs
+nsid = authority   "." name ; e.g. com.example.status
+segment = ALPHA *( ALPHA/DIGIT / "-" )
+        ; a ";" in a string isn't a comment
+authority = segment *("." segment)
+; names
+name = segment
+nsid =/ authority "." %s"*"  ; a namespace
+num = %x0D.0A / 1*3%d48-57 / 2%b1 / *2<prose; text> [ %i"X" ]
+"#;
+
+        assert_eq!(
+            print(&normalize(parse(source).unwrap())),
+            r#"; NSIDs
+nsid      = authority "." name / authority "." %s"*"
+            ; e.g. com.example.status
+            ; a namespace
+segment   = ALPHA *(ALPHA / DIGIT / "-")
+            ; a ";" in a string isn't a comment
+authority = segment *("." segment)
+; names
+name      = segment
+num       = %x0D.0A / 1*3%d48-57 / 2%b1 / *2<prose; text> ["X"]
+"#
+        );
+    }
+
+    #[test]
+    fn test_normalize_keeps_alternatives_without_a_base() {
+        let rule_list = normalize(parse("word = 1*ALPHA\nALPHA =/ \"_\"\n").unwrap());
+        assert_eq!(print(&rule_list), "word  = 1*ALPHA\nALPHA =/ \"_\"\n");
+    }
+
+    #[test]
+    fn test_print_is_stable() {
+        let printed = print(&normalize(parse(include_str!("../abnf.abnf")).unwrap()));
+        assert_eq!(print(&parse(&printed).unwrap()), printed);
+    }
+}
@@ -0,0 +1,343 @@
+//! Convert rules to regular expressions.
+//!
+//! A rule can be converted if, once every rule it references has been inlined, it doesn't refer
+//! back to itself. The output uses the syntax of the `regex` crate.
+
+use crate::ast::{Alternation, Element, NumValue};
+use crate::grammar::Grammar;
+use crate::{Error, ErrorRepr};
+use std::fmt::Write;
+
+/// A regular expression, before it's written out
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Regex {
+    /// Any one character in the inclusive ranges
+    Class(Vec<(u32, u32)>),
+    Concat(Vec<Regex>),
+    Alt(Vec<Regex>),
+    Repeat {
+        regex: Box<Regex>,
+        min: usize,
+        max: Option<usize>,
+    },
+}
+
+/// The anchored regex equivalent to the rule at `rule_index`
+pub(crate) fn rule_to_regex(grammar: &Grammar, rule_index: usize) -> Result<String, Error> {
+    let mut converter = Converter {
+        grammar,
+        stack: Vec::new(),
+    };
+    let regex = converter.rule(rule_index)?;
+
+    let mut out = "^".to_owned();
+    // Alternatives need a group, or the anchors would only apply to the first and last
+    write_regex(&mut out, &regex, Precedence::Concat);
+    out.push('$');
+
+    Ok(out)
+}
+
+struct Converter<'g> {
+    grammar: &'g Grammar,
+    // Rules being inlined, to catch recursion
+    stack: Vec<usize>,
+}
+
+impl<'g> Converter<'g> {
+    fn rule(&mut self, rule_index: usize) -> Result<Regex, Error> {
+        let rule = self.grammar.rule_at(rule_index);
+        if self.stack.contains(&rule_index) {
+            return Err(Error(ErrorRepr::NotRegular(rule.name.clone())));
+        }
+
+        self.stack.push(rule_index);
+        let regex = self.alternation(&rule.name, &rule.elements);
+        self.stack.pop();
+
+        regex
+    }
+
+    fn alternation(&mut self, rule: &str, alternation: &Alternation) -> Result<Regex, Error> {
+        let mut alternatives = Vec::new();
+        for concatenation in &alternation.concatenations {
+            let mut parts = Vec::new();
+            for repetition in &concatenation.repetitions {
+                let regex = self.element(rule, &repetition.element)?;
+                parts.push(match (repetition.min, repetition.max) {
+                    (1, Some(1)) => regex,
+                    (min, max) => Regex::Repeat {
+                        regex: Box::new(regex),
+                        min,
+                        max,
+                    },
+                });
+            }
+            alternatives.push(concat(parts));
+        }
+
+        Ok(alt(alternatives))
+    }
+
+    fn element(&mut self, rule: &str, element: &Element) -> Result<Regex, Error> {
+        match element {
+            Element::RuleName(name) => {
+                let index = self.grammar.index_of(name).ok_or_else(|| {
+                    Error(ErrorRepr::UndefinedRule {
+                        rule: rule.to_owned(),
+                        reference: name.clone(),
+                    })
+                })?;
+                self.rule(index)
+            }
+            Element::Group(alternation) => self.alternation(rule, alternation),
+            Element::Option(alternation) => Ok(Regex::Repeat {
+                regex: Box::new(self.alternation(rule, alternation)?),
+                min: 0,
+                max: Some(1),
+            }),
+            Element::CharVal(char_val) => Ok(concat(
+                char_val
+                    .value
+                    .chars()
+                    .map(|c| {
+                        let c = c as u32;
+                        let upper = (c as u8).to_ascii_uppercase() as u32;
+                        let lower = (c as u8).to_ascii_lowercase() as u32;
+                        if char_val.case_sensitive || upper == lower {
+                            Regex::Class(vec![(c, c)])
+                        } else {
+                            Regex::Class(vec![(upper, upper), (lower, lower)])
+                        }
+                    })
+                    .collect(),
+            )),
+            Element::NumVal(num_val) => Ok(match &num_val.value {
+                NumValue::Sequence(values) => {
+                    concat(values.iter().map(|&v| Regex::Class(vec![(v, v)])).collect())
+                }
+                NumValue::Range(low, high) => Regex::Class(vec![(*low, *high)]),
+            }),
+            Element::ProseVal(_) => Err(Error(ErrorRepr::Prose(rule.to_owned()))),
+        }
+    }
+}
+
+fn concat(mut parts: Vec<Regex>) -> Regex {
+    if parts.len() == 1 {
+        parts.remove(0)
+    } else {
+        Regex::Concat(parts)
+    }
+}
+
+/// Combine alternatives, merging any that match a single character into one class
+fn alt(alternatives: Vec<Regex>) -> Regex {
+    let mut merged: Vec<Regex> = Vec::new();
+    for alternative in alternatives {
+        match (merged.last_mut(), alternative) {
+            (Some(Regex::Class(ranges)), Regex::Class(more)) => ranges.extend(more),
+            (_, alternative) => merged.push(alternative),
+        }
+    }
+
+    if merged.len() == 1 {
+        merged.remove(0)
+    } else {
+        Regex::Alt(merged)
+    }
+}
+
+/// How tightly an expression binds, for deciding when it needs to be wrapped in a group
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Precedence {
+    Alt,
+    Concat,
+    Repeat,
+}
+
+fn write_regex(out: &mut String, regex: &Regex, context: Precedence) {
+    let precedence = match regex {
+        Regex::Alt(_) => Precedence::Alt,
+        Regex::Concat(_) => Precedence::Concat,
+        Regex::Class(_) | Regex::Repeat { .. } => Precedence::Repeat,
+    };
+    // A repeated repeat e.g. `(?:a*)?` also needs a group, `a*?` would be a lazy `*`
+    let needs_group = precedence < context
+        || (context == Precedence::Repeat && matches!(regex, Regex::Repeat { .. }));
+    if needs_group {
+        out.push_str("(?:");
+    }
+
+    match regex {
+        Regex::Class(ranges) => write_class(out, ranges),
+        Regex::Concat(parts) => {
+            for part in parts {
+                write_regex(out, part, Precedence::Concat);
+            }
+        }
+        Regex::Alt(alternatives) => {
+            for (i, alternative) in alternatives.iter().enumerate() {
+                if i > 0 {
+                    out.push('|');
+                }
+                write_regex(out, alternative, Precedence::Alt);
+            }
+        }
+        Regex::Repeat { regex, min, max } => {
+            write_regex(out, regex, Precedence::Repeat);
+            let _ = match (min, max) {
+                (0, None) => write!(out, "*"),
+                (1, None) => write!(out, "+"),
+                (0, Some(1)) => write!(out, "?"),
+                (min, None) => write!(out, "{{{min},}}"),
+                (min, Some(max)) if min == max => write!(out, "{{{min}}}"),
+                (min, Some(max)) => write!(out, "{{{min},{max}}}"),
+            };
+        }
+    }
+
+    if needs_group {
+        out.push(')');
+    }
+}
+
+fn write_class(out: &mut String, ranges: &[(u32, u32)]) {
+    let mut ranges = ranges.to_vec();
+    ranges.sort_unstable();
+    let mut merged: Vec<(u32, u32)> = Vec::new();
+    for (low, high) in ranges {
+        match merged.last_mut() {
+            Some((_, last_high)) if low <= last_high.saturating_add(1) => {
+                *last_high = (*last_high).max(high)
+            }
+            _ => merged.push((low, high)),
+        }
+    }
+
+    if let [(low, high)] = merged.as_slice() {
+        if low == high {
+            write_char(out, *low, false);
+            return;
+        }
+    }
+
+    out.push('[');
+    for (low, high) in merged {
+        write_char(out, low, true);
+        if high != low {
+            if high > low + 1 {
+                out.push('-');
+            }
+            write_char(out, high, true);
+        }
+    }
+    out.push(']');
+}
+
+fn write_char(out: &mut String, c: u32, in_class: bool) {
+    let meta = if in_class {
+        "\\[]^-&~"
+    } else {
+        "\\.+*?()|[]{}^$"
+    };
+    match char::from_u32(c) {
+        Some(c) if meta.contains(c) => {
+            out.push('\\');
+            out.push(c);
+        }
+        Some(c) if c.is_ascii_graphic() || c == ' ' => out.push(c),
+        _ => {
+            let _ = write!(out, "\\x{{{c:X}}}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Grammar;
+
+    fn to_regex(source: &str, rule: &str) -> String {
+        Grammar::from_metasyntax(source)
+            .unwrap()
+            .to_regex(rule)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_to_regex() {
+        assert_eq!(to_regex("a = \"a\" / \"b\"\n", "a"), "^[ABab]$");
+        assert_eq!(to_regex("a = %s\"a.b\"\n", "a"), r"^a\.b$");
+        assert_eq!(to_regex("a = \"at://\"\n", "a"), "^[Aa][Tt]://$");
+        assert_eq!(to_regex("a = 1*DIGIT [\"-\"]\n", "a"), "^[0-9]+-?$");
+        assert_eq!(to_regex("a = 2*3( %x41 %x42 )\n", "a"), "^(?:AB){2,3}$");
+        assert_eq!(
+            to_regex("a = 2%d10 / *( [\"x\"] )\n", "a"),
+            r"^(?:\x{A}{2}|(?:[Xx]?)*)$"
+        );
+        assert_eq!(to_regex("a = %x5D / %x2D / %x5E\n", "a"), r"^[\-\]\^]$");
+        assert_eq!(
+            to_regex("a = %x10000-10FFFF\n", "a"),
+            r"^[\x{10000}-\x{10FFFF}]$"
+        );
+    }
+
+    #[test]
+    fn test_to_regex_matches_like_the_grammar() {
+        // https://atproto.com/specs/nsid
+        let source = r#"nsid      = authority "." name
+authority = segment *( "." segment )
+name      = segment
+segment   = ALPHA *( ALPHA / DIGIT / "-" )
+"#;
+        let grammar = Grammar::from_metasyntax(source).unwrap();
+        let pattern = grammar.to_regex("nsid").unwrap();
+        assert_eq!(
+            pattern,
+            r"^[A-Za-z][\-0-9A-Za-z]*(?:\.[A-Za-z][\-0-9A-Za-z]*)*\.[A-Za-z][\-0-9A-Za-z]*$"
+        );
+
+        let regex = regex::Regex::new(&pattern).unwrap();
+        for input in [
+            "com.example.status",
+            "io.social.getFeed",
+            "net.users.bob.ping",
+            "com.example",
+            "com.example.*",
+            "com..example",
+            "9com.example",
+            "com.example.status\n",
+            "",
+        ] {
+            assert_eq!(
+                regex.is_match(input),
+                grammar.match_rule("nsid", input).is_ok(),
+                "{input:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_to_regex_errors() {
+        let grammar = Grammar::from_metasyntax("a = \"(\" [a] \")\"\nb = <prose>\n").unwrap();
+        assert_eq!(
+            grammar.to_regex("a").unwrap_err().to_string(),
+            "rule `a` is recursive, so it can't be converted to a regex"
+        );
+        assert_eq!(
+            grammar.to_regex("b").unwrap_err().to_string(),
+            "rule `b` contains prose, which can't be converted to a regex"
+        );
+        assert_eq!(
+            grammar.to_regex("c").unwrap_err().to_string(),
+            "rule `c` isn't defined in the grammar"
+        );
+
+        // Indirect recursion names the rule that recurses
+        let grammar = Grammar::from_metasyntax("a = \"x\" b\nb = \"y\" [c]\nc = b\n").unwrap();
+        assert_eq!(
+            grammar.to_regex("a").unwrap_err().to_string(),
+            "rule `b` is recursive, so it can't be converted to a regex"
+        );
+    }
+}
@@ -0,0 +1,270 @@
+// Generated by abnf-rs. Do not edit this by hand.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/// A rule that matched part of the input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capture {
+    pub rule: &'static str,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Every offset that a match could end at, along with the rules that matched on the way
+type Matches = Vec<(usize, Vec<Capture>)>;
+
+/// Matches an element at the given offset
+type Element<'a> = &'a dyn Fn(usize) -> Matches;
+
+/// The matches of each rule that's been tried, by its name and starting offset
+type Memo = RefCell<HashMap<(&'static str, usize), Matches>>;
+
+#[allow(dead_code)]
+fn memoize(memo: &Memo, rule: &'static str, pos: usize, f: impl FnOnce() -> Matches) -> Matches {
+    let memoized = memo.borrow().get(&(rule, pos)).cloned();
+    if let Some(matches) = memoized {
+        return matches;
+    }
+
+    let matches = f();
+    memo.borrow_mut().insert((rule, pos), matches.clone());
+    matches
+}
+
+#[allow(dead_code)]
+fn add(matches: &mut Matches, end: usize, captures: Vec<Capture>) {
+    if !matches.iter().any(|(e, _)| *e == end) {
+        matches.push((end, captures));
+    }
+}
+
+#[allow(dead_code)]
+fn complete(matches: Matches, input: &str) -> Option<Vec<Capture>> {
+    matches
+        .into_iter()
+        .find(|(end, _)| *end == input.len())
+        .map(|(_, captures)| captures)
+}
+
+#[allow(dead_code)]
+fn capture(rule: &'static str, start: usize, matches: Matches) -> Matches {
+    matches
+        .into_iter()
+        .map(|(end, mut captures)| {
+            captures.insert(0, Capture { rule, start, end });
+            (end, captures)
+        })
+        .collect()
+}
+
+#[allow(dead_code)]
+fn alternation(pos: usize, alternatives: &[Element]) -> Matches {
+    let mut matches = Matches::new();
+    for alternative in alternatives {
+        for (end, captures) in alternative(pos) {
+            add(&mut matches, end, captures);
+        }
+    }
+
+    matches
+}
+
+#[allow(dead_code)]
+fn concatenation(pos: usize, repetitions: &[Element]) -> Matches {
+    let mut matches = vec![(pos, Vec::new())];
+    for repetition in repetitions {
+        let mut next = Matches::new();
+        for (start, captures) in &matches {
+            for (end, more) in repetition(*start) {
+                let mut captures = captures.clone();
+                captures.extend(more);
+                add(&mut next, end, captures);
+            }
+        }
+
+        if next.is_empty() {
+            return next;
+        }
+        matches = next;
+    }
+
+    matches
+}
+
+#[allow(dead_code)]
+fn repetition(pos: usize, min: usize, max: Option<usize>, element: Element) -> Matches {
+    let mut matches = Matches::new();
+    let mut frontier = vec![(pos, Vec::new())];
+    // Once the minimum count is reached, offsets that were already reached can't lead anywhere new
+    let mut seen = Vec::new();
+    let mut count = 0;
+
+    loop {
+        if count >= min {
+            for (end, captures) in &frontier {
+                add(&mut matches, *end, captures.clone());
+                seen.push(*end);
+            }
+        }
+        if max == Some(count) {
+            break;
+        }
+
+        let mut next = Matches::new();
+        for (start, captures) in &frontier {
+            for (end, more) in element(*start) {
+                let mut captures = captures.clone();
+                captures.extend(more);
+                add(&mut next, end, captures);
+            }
+        }
+        if count + 1 >= min {
+            next.retain(|(end, _)| !seen.contains(end));
+        }
+        if next.is_empty() {
+            break;
+        }
+
+        frontier = next;
+        count += 1;
+    }
+
+    matches
+}
+
+#[allow(dead_code)]
+fn char_val(input: &str, pos: usize, value: &str, case_sensitive: bool) -> Matches {
+    let end = pos + value.len();
+    match input.as_bytes().get(pos..end) {
+        Some(candidate) if case_sensitive && candidate == value.as_bytes() => {
+            vec![(end, Vec::new())]
+        }
+        Some(candidate) if !case_sensitive && candidate.eq_ignore_ascii_case(value.as_bytes()) => {
+            vec![(end, Vec::new())]
+        }
+        _ => Matches::new(),
+    }
+}
+
+#[allow(dead_code)]
+fn num_sequence(input: &str, pos: usize, values: &[u32]) -> Matches {
+    let mut chars = input[pos..].chars();
+    let mut end = pos;
+    for &value in values {
+        match chars.next() {
+            Some(c) if c as u32 == value => end += c.len_utf8(),
+            _ => return Matches::new(),
+        }
+    }
+
+    vec![(end, Vec::new())]
+}
+
+#[allow(dead_code)]
+fn num_range(input: &str, pos: usize, low: u32, high: u32) -> Matches {
+    match input[pos..].chars().next() {
+        Some(c) if (low..=high).contains(&(c as u32)) => vec![(pos + c.len_utf8(), Vec::new())],
+        _ => Matches::new(),
+    }
+}
+
+/// Whether all of `input` matches the `nsid` rule
+pub fn is_valid_nsid(input: &str) -> bool {
+    parse_nsid(input).is_some()
+}
+
+/// Match all of `input` against the `nsid` rule, returning the span of every rule that matched,
+/// outermost first
+pub fn parse_nsid(input: &str) -> Option<Vec<Capture>> {
+    complete(match_nsid(input, &Memo::default(), 0), input)
+}
+
+fn match_nsid(input: &str, memo: &Memo, pos: usize) -> Matches {
+    memoize(memo, "nsid", pos, || {
+        capture("nsid", pos, concatenation(pos, &[
+            &|pos| match_authority(input, memo, pos),
+            &|pos| char_val(input, pos, ".", false),
+            &|pos| match_name(input, memo, pos),
+        ]))
+    })
+}
+
+/// Whether all of `input` matches the `authority` rule
+pub fn is_valid_authority(input: &str) -> bool {
+    parse_authority(input).is_some()
+}
+
+/// Match all of `input` against the `authority` rule, returning the span of every rule that matched,
+/// outermost first
+pub fn parse_authority(input: &str) -> Option<Vec<Capture>> {
+    complete(match_authority(input, &Memo::default(), 0), input)
+}
+
+fn match_authority(input: &str, memo: &Memo, pos: usize) -> Matches {
+    memoize(memo, "authority", pos, || {
+        capture("authority", pos, concatenation(pos, &[
+            &|pos| match_segment(input, memo, pos),
+            &|pos| repetition(pos, 0, None, &|pos| concatenation(pos, &[
+                &|pos| char_val(input, pos, ".", false),
+                &|pos| match_segment(input, memo, pos),
+            ])),
+        ]))
+    })
+}
+
+/// Whether all of `input` matches the `name` rule
+pub fn is_valid_name(input: &str) -> bool {
+    parse_name(input).is_some()
+}
+
+/// Match all of `input` against the `name` rule, returning the span of every rule that matched,
+/// outermost first
+pub fn parse_name(input: &str) -> Option<Vec<Capture>> {
+    complete(match_name(input, &Memo::default(), 0), input)
+}
+
+fn match_name(input: &str, memo: &Memo, pos: usize) -> Matches {
+    memoize(memo, "name", pos, || {
+        capture("name", pos, match_segment(input, memo, pos))
+    })
+}
+
+/// Whether all of `input` matches the `segment` rule
+pub fn is_valid_segment(input: &str) -> bool {
+    parse_segment(input).is_some()
+}
+
+/// Match all of `input` against the `segment` rule, returning the span of every rule that matched,
+/// outermost first
+pub fn parse_segment(input: &str) -> Option<Vec<Capture>> {
+    complete(match_segment(input, &Memo::default(), 0), input)
+}
+
+fn match_segment(input: &str, memo: &Memo, pos: usize) -> Matches {
+    memoize(memo, "segment", pos, || {
+        capture("segment", pos, concatenation(pos, &[
+            &|pos| match_alpha(input, memo, pos),
+            &|pos| repetition(pos, 0, None, &|pos| alternation(pos, &[
+                &|pos| match_alpha(input, memo, pos),
+                &|pos| match_digit(input, memo, pos),
+                &|pos| char_val(input, pos, "-", false),
+            ])),
+        ]))
+    })
+}
+
+fn match_alpha(input: &str, memo: &Memo, pos: usize) -> Matches {
+    memoize(memo, "ALPHA", pos, || {
+        alternation(pos, &[
+            &|pos| num_range(input, pos, 0x41, 0x5A),
+            &|pos| num_range(input, pos, 0x61, 0x7A),
+        ])
+    })
+}
+
+fn match_digit(input: &str, memo: &Memo, pos: usize) -> Matches {
+    memoize(memo, "DIGIT", pos, || {
+        num_range(input, pos, 0x30, 0x39)
+    })
+}
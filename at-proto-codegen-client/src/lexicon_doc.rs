@@ -1,5 +1,6 @@
 pub mod io;
 
+use serde::Deserialize;
 use serde_json::Value;
 use thiserror::Error;
 use tracing::{debug, error, warn};
@@ -38,10 +39,9 @@ impl From<u64> for LexiconVersion {
     }
 }
 
-#[derive(Default)]
 struct Builder {
     lexicon: Option<LexiconVersion>,
-    id: Option<String>,
+    id: Nsid,
     r#type: Option<LexiconDocType>,
     revision: Option<u64>,
     description: Option<String>,
@@ -51,8 +51,18 @@ struct Builder {
 }
 
 impl Builder {
-    fn new() -> Self {
-        Self::default()
+    /// `from_json` has already checked the ID, since it's needed for logging
+    fn new(id: Nsid) -> Self {
+        Self {
+            lexicon: None,
+            id,
+            r#type: None,
+            revision: None,
+            description: None,
+            input: None,
+            output: None,
+            error: None,
+        }
     }
 
     fn lexicon(mut self, lexicon: Option<LexiconVersion>) -> Self {
@@ -60,11 +70,6 @@ impl Builder {
         self
     }
 
-    fn id(mut self, id: Option<String>) -> Self {
-        self.id = id;
-        self
-    }
-
     fn r#type(mut self, r#type: Option<LexiconDocType>) -> Self {
         self.r#type = r#type;
         self
@@ -99,18 +104,11 @@ impl Builder {
         let lexicon = self
             .lexicon
             .ok_or(LexiconDocError::MissingField("lexicon"))?;
-        let id = self.id.ok_or(LexiconDocError::MissingField("id"))?;
-        let id = id
-            .parse::<Nsid>()
-            .map_err(|_| LexiconDocError::InvalidField {
-                field: "id",
-                value: id,
-            })?;
         let r#type = self.r#type.ok_or(LexiconDocError::MissingField("type"))?;
 
         Ok(LexiconDoc {
             lexicon,
-            id,
+            id: self.id,
             r#type,
             revision: self.revision,
             description: self.description,
@@ -170,11 +168,14 @@ impl LexiconDoc {
     pub fn from_json(json: &serde_json::Value) -> Result<LexiconDoc, LexiconDocError> {
         let lexicon: Option<LexiconVersion> =
             json.get("lexicon").and_then(Value::as_u64).map(Into::into);
-        let id = json
-            .get("id")
-            .and_then(Value::as_str)
-            .map(Into::into)
-            .unwrap_or_default();
+        let id = json.get("id").ok_or(LexiconDocError::MissingField("id"))?;
+        let id = Nsid::deserialize(id).map_err(|_| LexiconDocError::InvalidField {
+            field: "id",
+            // Strings are given without their JSON quotes
+            value: id
+                .as_str()
+                .map_or_else(|| id.to_string(), ToOwned::to_owned),
+        })?;
 
         debug!("creating lexicon doc for {}", id);
 
@@ -222,9 +223,8 @@ impl LexiconDoc {
             }
         });
 
-        Builder::new()
+        Builder::new(id)
             .lexicon(lexicon)
-            .id(Some(id))
             .r#type(r#type)
             .revision(revision)
             .description(description)
@@ -242,3 +242,36 @@ pub enum LexiconDocError {
     #[error("The Lexicon document has an invalid {field} field with value {value}")]
     InvalidField { field: &'static str, value: String },
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{LexiconDoc, LexiconDocError};
+    use serde_json::json;
+
+    #[test]
+    fn test_invalid_id() {
+        let err = LexiconDoc::from_json(&json!({ "lexicon": 1, "id": "not-an-nsid" }))
+            .err()
+            .unwrap();
+        assert_eq!(
+            err,
+            LexiconDocError::InvalidField {
+                field: "id",
+                value: "not-an-nsid".to_owned()
+            }
+        );
+
+        let err = LexiconDoc::from_json(&json!({ "lexicon": 1, "id": 7 }))
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "The Lexicon document has an invalid id field with value 7"
+        );
+
+        let err = LexiconDoc::from_json(&json!({ "lexicon": 1 }))
+            .err()
+            .unwrap();
+        assert_eq!(err, LexiconDocError::MissingField("id"));
+    }
+}
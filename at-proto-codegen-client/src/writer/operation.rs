@@ -20,6 +20,26 @@ pub const OUTPUT_MODULE: Module = Module {
     visibility: Visibility::Public,
 };
 
+/// The module within `parent` for the lexicons in `doc`'s namespace, e.g. `input::com_atproto_repo`
+/// for `com.atproto.repo.getRecord`. Keeping namespaces apart stops operations with the same name
+/// from clashing.
+fn namespace_module(parent: Module, doc: &LexiconDoc) -> Module {
+    let namespace = doc.id().namespace();
+    // Authorities are case-insensitive, so differently cased ones share a module
+    let name = namespace
+        .authority_segments()
+        .to_ascii_lowercase()
+        .replace(['.', '-'], "_");
+
+    Module {
+        name: Cow::Owned(name),
+        documentation: Cow::Owned(format!("{} for `{namespace}`", parent.documentation)),
+        parent: Parent::Module(Box::new(parent)),
+        dependencies: vec![],
+        visibility: Visibility::Public,
+    }
+}
+
 pub fn write_to_module(c: &mut Crate, doc: &LexiconDoc) -> anyhow::Result<()> {
     write_input_struct(c, doc)?;
     write_input_struct_builder(c, doc)?;
@@ -33,7 +53,7 @@ pub fn write_to_module(c: &mut Crate, doc: &LexiconDoc) -> anyhow::Result<()> {
 fn write_input_struct(c: &mut Crate, doc: &LexiconDoc) -> anyhow::Result<()> {
     debug!("writing input struct for {}", doc.id());
 
-    let mut writer = c.writer(INPUT_MODULE);
+    let mut writer = c.writer(namespace_module(INPUT_MODULE, doc));
     let struct_name = doc.id().as_struct_name();
 
     // Write docs for the struct
@@ -81,7 +101,7 @@ fn write_input_struct_builder(_c: &mut Crate, doc: &LexiconDoc) -> anyhow::Resul
 
 fn write_output_struct(c: &mut Crate, doc: &LexiconDoc) -> anyhow::Result<()> {
     debug!("writing output struct for {}", doc.id());
-    let mut writer = c.writer(OUTPUT_MODULE);
+    let mut writer = c.writer(namespace_module(OUTPUT_MODULE, doc));
     let struct_name = doc.id().as_struct_name();
 
     // Write docs for the struct
@@ -126,3 +146,29 @@ fn write_output_struct_builder(_c: &mut Crate, doc: &LexiconDoc) -> anyhow::Resu
     debug!("writing output struct builder for {}", doc.id());
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{namespace_module, INPUT_MODULE, OUTPUT_MODULE};
+    use crate::lexicon_doc::LexiconDoc;
+    use serde_json::json;
+
+    fn doc(id: &str) -> LexiconDoc {
+        LexiconDoc::from_json(&json!({
+            "lexicon": 1,
+            "id": id,
+            "type": "query"
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_namespace_module() {
+        let module = namespace_module(INPUT_MODULE, &doc("com.atproto.repo.getRecord"));
+        assert_eq!(module.to_module_path(), "crate::input::com_atproto_repo");
+        assert_eq!(module.documentation, "XRPC inputs for `com.atproto.repo.*`");
+
+        let module = namespace_module(OUTPUT_MODULE, &doc("COM.atproto-lab.getRecord"));
+        assert_eq!(module.to_module_path(), "crate::output::com_atproto_lab");
+    }
+}
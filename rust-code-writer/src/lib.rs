@@ -68,7 +68,7 @@ impl Crate {
 
     fn merge_pending_writes(&mut self) {
         self.pending_writes
-            .sort_by(|a, b| a.0.to_module_path().cmp(&b.0.to_module_path()));
+            .sort_by_key(|(module, _)| module.to_module_path());
         let unmerged_writes = std::mem::take(&mut self.pending_writes);
 
         self.pending_writes =
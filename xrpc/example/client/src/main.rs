@@ -1,3 +1,18 @@
-fn main() {
-    println!("Hello, world!");
+use xrpc::{response::Body, Nsid, XrpcClient};
+
+/// Send a query to an XRPC server running locally, like the one in `example/server`
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let client = XrpcClient::new("http://localhost:2583");
+    let request = client
+        .query(Nsid::new("io.example.ping")?)
+        .parameter("message", "hello")
+        .build()?;
+
+    match client.send(request).await?.into_body() {
+        Body::Text(text) => println!("{text}"),
+        body => println!("{body:?}"),
+    }
+
+    Ok(())
 }
@@ -1,3 +1,30 @@
-fn main() {
-    println!("Hello, world!");
+use axum::{extract::Query, Router};
+use std::{collections::HashMap, net::SocketAddr};
+use xrpc::{request::Type, response::ResponseType, server::ErrorResponse, Nsid, XrpcRouter};
+
+/// Serve an XRPC method locally, for `example/client` to call
+#[tokio::main]
+async fn main() {
+    tracing_subscriber::fmt::init();
+
+    let app: Router = XrpcRouter::new()
+        .route(Nsid::new("io.example.ping").unwrap(), Type::Query, ping)
+        .into();
+
+    let addr = SocketAddr::from(([127, 0, 0, 1], 2583));
+    tracing::debug!("listening on {}", addr);
+    axum::Server::bind(&addr)
+        .serve(app.into_make_service())
+        .await
+        .unwrap();
+}
+
+/// Respond with the `message` query parameter
+async fn ping(Query(params): Query<HashMap<String, String>>) -> Result<String, ErrorResponse> {
+    params.get("message").cloned().ok_or_else(|| {
+        ErrorResponse::standard(
+            ResponseType::InvalidRequest,
+            "missing the `message` parameter",
+        )
+    })
 }
@@ -0,0 +1,299 @@
+//! Checks of a single value against the limits in its schema, shared by query parameters and
+//! JSON values.
+
+use crate::parameter::{Default, Format};
+use crate::{Nsid, Parameter};
+use std::cmp::Ordering;
+
+/// A limit in a schema that a value is outside of
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Limit<'p> {
+    MinLength(u64),
+    MaxLength(u64),
+    Minimum(i64),
+    Maximum(i64),
+    /// The value isn't the schema's `const`
+    Const(&'p Default),
+    /// The value isn't one of the schema's `enum` values
+    Enum,
+}
+
+/// Check the length of a string, the number of items in an array, or the size of some bytes
+pub(crate) fn length(parameter: &Parameter, length: usize) -> Option<Limit<'_>> {
+    let length = length as u64;
+    match (parameter.min_length, parameter.max_length) {
+        (Some(min_length), _) if length < min_length => Some(Limit::MinLength(min_length)),
+        (_, Some(max_length)) if length > max_length => Some(Limit::MaxLength(max_length)),
+        _ => None,
+    }
+}
+
+/// Check a number against the minimum and maximum, given how it compares to a bound
+pub(crate) fn range(
+    parameter: &Parameter,
+    compare: impl Fn(i64) -> Option<Ordering>,
+) -> Option<Limit<'_>> {
+    match (parameter.minimum, parameter.maximum) {
+        (Some(minimum), _) if compare(minimum) == Some(Ordering::Less) => {
+            Some(Limit::Minimum(minimum))
+        }
+        (_, Some(maximum)) if compare(maximum) == Some(Ordering::Greater) => {
+            Some(Limit::Maximum(maximum))
+        }
+        _ => None,
+    }
+}
+
+/// Check a value against the `const` and `enum`, given whether it equals an allowed value
+pub(crate) fn allowed(parameter: &Parameter, equals: impl Fn(&Default) -> bool) -> Vec<Limit<'_>> {
+    let mut limits = Vec::new();
+    match &parameter.r#const {
+        Some(expected) if !equals(expected) => limits.push(Limit::Const(expected)),
+        _ => (),
+    }
+    match &parameter.r#enum {
+        Some(allowed) if !allowed.iter().any(&equals) => limits.push(Limit::Enum),
+        _ => (),
+    }
+
+    limits
+}
+
+/// https://atproto.com/specs/lexicon#string-formats
+pub(crate) fn is_valid(format: Format, s: &str) -> bool {
+    match format {
+        Format::AtIdentifier => is_did(s) || is_handle(s),
+        Format::AtUri => is_at_uri(s),
+        Format::Cid => {
+            (8..=256).contains(&s.len())
+                && s.bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'+' || b == b'=')
+        }
+        Format::Datetime => is_datetime(s),
+        Format::Did => is_did(s),
+        Format::Handle => is_handle(s),
+        Format::Language => is_language(s),
+        Format::Nsid => Nsid::new(s).is_ok(),
+        Format::RecordKey => is_record_key(s),
+        Format::Tid => {
+            const CHARS: &[u8] = b"234567abcdefghijklmnopqrstuvwxyz";
+            s.len() == 13
+                && CHARS[..16].contains(&s.as_bytes()[0])
+                && s.bytes().all(|b| CHARS.contains(&b))
+        }
+        Format::Uri => is_uri(s),
+    }
+}
+
+/// e.g. `did:plc:7iza6de2dwap2sbkpav7c6c6`
+fn is_did(s: &str) -> bool {
+    let Some((method, id)) = s.strip_prefix("did:").and_then(|rest| rest.split_once(':')) else {
+        return false;
+    };
+
+    s.len() <= 2048
+        && !method.is_empty()
+        && method.bytes().all(|b| b.is_ascii_lowercase())
+        && !id.is_empty()
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"._:%-".contains(&b))
+        && !id.ends_with([':', '%'])
+}
+
+/// A domain name with at least two labels, e.g. `alice.bsky.social`
+fn is_handle(s: &str) -> bool {
+    let labels: Vec<_> = s.split('.').collect();
+
+    s.len() <= 253
+        && labels.len() >= 2
+        && labels.iter().all(|label| {
+            (1..=63).contains(&label.len())
+                && label
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+        })
+        && !labels[labels.len() - 1].starts_with(|c: char| c.is_ascii_digit())
+}
+
+/// e.g. `at://alice.bsky.social/app.bsky.feed.post/3jui7kd54zh2y`
+fn is_at_uri(s: &str) -> bool {
+    let Some(rest) = s.strip_prefix("at://") else {
+        return false;
+    };
+    let mut parts = rest.splitn(3, '/');
+    let authority = parts.next().unwrap_or_default();
+
+    (is_did(authority) || is_handle(authority))
+        && parts
+            .next()
+            .is_none_or(|collection| Nsid::new(collection).is_ok())
+        && parts.next().is_none_or(is_record_key)
+}
+
+/// An RFC 3339 timestamp, e.g. `2022-11-17T01:05:26.123Z`
+fn is_datetime(s: &str) -> bool {
+    let b = s.as_bytes();
+    if b.len() < 20 || !s.is_ascii() {
+        return false;
+    }
+    let number = |start: usize, end: usize| -> Option<u32> {
+        b[start..end]
+            .iter()
+            .all(u8::is_ascii_digit)
+            .then(|| s[start..end].parse().ok())
+            .flatten()
+    };
+    let separators = b[4] == b'-'
+        && b[7] == b'-'
+        && matches!(b[10], b'T' | b't')
+        && b[13] == b':'
+        && b[16] == b':';
+    let fields = (
+        number(0, 4),
+        number(5, 7),
+        number(8, 10),
+        number(11, 13),
+        number(14, 16),
+        number(17, 19),
+    );
+    let (Some(_), Some(month), Some(day), Some(hour), Some(minute), Some(second)) = fields else {
+        return false;
+    };
+    if !separators
+        || !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return false;
+    }
+
+    let mut rest = &s[19..];
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let digits = fraction.bytes().take_while(u8::is_ascii_digit).count();
+        if digits == 0 {
+            return false;
+        }
+        rest = &fraction[digits..];
+    }
+    match rest.as_bytes() {
+        [b'Z' | b'z'] => true,
+        [b'+' | b'-', h1, h2, b':', m1, m2] => [h1, h2, m1, m2].iter().all(|b| b.is_ascii_digit()),
+        _ => false,
+    }
+}
+
+/// A BCP 47 language tag, e.g. `en` or `pt-BR`
+fn is_language(s: &str) -> bool {
+    let mut subtags = s.split('-');
+    let primary = subtags.next().unwrap_or_default();
+    let primary_is_valid = matches!(primary, "i" | "x")
+        || ((2..=8).contains(&primary.len()) && primary.bytes().all(|b| b.is_ascii_alphabetic()));
+
+    primary_is_valid
+        && subtags.all(|subtag| {
+            (1..=8).contains(&subtag.len()) && subtag.bytes().all(|b| b.is_ascii_alphanumeric())
+        })
+}
+
+fn is_record_key(s: &str) -> bool {
+    (1..=512).contains(&s.len())
+        && s != "."
+        && s != ".."
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"._:~-".contains(&b))
+}
+
+/// An absolute URI, e.g. `https://bsky.app/profile/alice.bsky.social`
+fn is_uri(s: &str) -> bool {
+    let Some((scheme, rest)) = s.split_once(':') else {
+        return false;
+    };
+
+    s.len() <= 8192
+        && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"+.-".contains(&b))
+        && !rest.is_empty()
+        && !s.contains(char::is_whitespace)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_valid;
+    use crate::parameter::Format;
+
+    #[test]
+    fn test_formats() {
+        for (format, valid, invalid) in [
+            (
+                Format::AtIdentifier,
+                &["did:web:example.com", "alice.test"][..],
+                &["alice", "did:web:"][..],
+            ),
+            (
+                Format::AtUri,
+                &[
+                    "at://alice.test",
+                    "at://did:plc:1234abcd/app.bsky.feed.post/3jui7kd54zh2y",
+                ],
+                &["https://alice.test", "at://alice.test/not-an-nsid"],
+            ),
+            (
+                Format::Datetime,
+                &["1985-04-12T23:20:50.52Z", "1996-12-19T16:39:57-08:00"],
+                &["1985-04-12", "1985-13-12T23:20:50Z", "1985-04-12T23:20:50"],
+            ),
+            (
+                Format::Did,
+                &[
+                    "did:plc:7iza6de2dwap2sbkpav7c6c6",
+                    "did:web:example.com%3A8080",
+                ],
+                &["did:PLC:1234", "did:plc:", "did:plc:1234:"],
+            ),
+            (
+                Format::Handle,
+                &["alice.bsky.social", "x.y-z.io"],
+                &["alice", "-alice.test", "alice.123"],
+            ),
+            (
+                Format::Language,
+                &["en", "zh-Hant-TW", "i-klingon"],
+                &["e", "en_US"],
+            ),
+            (
+                Format::Nsid,
+                &["app.bsky.feed.post"],
+                &["app.bsky", "app.bsky.feed.*"],
+            ),
+            (
+                Format::RecordKey,
+                &["self", "3jui7kd54zh2y"],
+                &["", "..", "a/b"],
+            ),
+            (
+                Format::Tid,
+                &["3jui7kd54zh2y"],
+                &["3jui7kd54zh2", "zjui7kd54zh2y"],
+            ),
+            (
+                Format::Uri,
+                &["https://example.com/path?q=1", "mailto:alice@example.com"],
+                &["example.com", "https://example.com/a b"],
+            ),
+        ] {
+            for s in valid {
+                assert!(is_valid(format, s), "{s} should be a valid {format}");
+            }
+            for s in invalid {
+                assert!(!is_valid(format, s), "{s} shouldn't be a valid {format}");
+            }
+        }
+    }
+}
@@ -0,0 +1,244 @@
+//! Sending XRPC requests over HTTP.
+
+use crate::request::{self, Type};
+use crate::response;
+use crate::{Nsid, XrpcRequest, XrpcResponse};
+use bytes::Bytes;
+use std::future::Future;
+use std::pin::Pin;
+
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Something that can send an HTTP request and wait for the response, like a hyper client or a
+/// fake server for tests.
+pub trait Connector {
+    fn call(
+        &self,
+        request: http::Request<Bytes>,
+    ) -> BoxFuture<'static, Result<http::Response<Bytes>, BoxError>>;
+}
+
+impl<C> Connector for hyper::Client<C, hyper::Body>
+where
+    C: hyper::client::connect::Connect + Clone + Send + Sync + 'static,
+{
+    fn call(
+        &self,
+        request: http::Request<Bytes>,
+    ) -> BoxFuture<'static, Result<http::Response<Bytes>, BoxError>> {
+        let client = self.clone();
+        Box::pin(async move {
+            let response = client.request(request.map(hyper::Body::from)).await?;
+            let (parts, body) = response.into_parts();
+            let body = hyper::body::to_bytes(body).await?;
+
+            Ok(http::Response::from_parts(parts, body))
+        })
+    }
+}
+
+/// A client for the XRPC methods of a single server.
+///
+/// Requests are sent with a [`Connector`], which is a plain-HTTP hyper client unless one is given
+/// to [`XrpcClient::with_connector`]. A hyper client with an HTTPS connector can be given to talk
+/// to servers over TLS.
+pub struct XrpcClient<C = hyper::Client<hyper::client::HttpConnector>> {
+    base_url: String,
+    connector: C,
+}
+
+impl XrpcClient {
+    /// Create a client for the server at `base_url`, e.g. `http://localhost:2583`
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self::with_connector(base_url, hyper::Client::new())
+    }
+}
+
+impl<C: Connector> XrpcClient<C> {
+    pub fn with_connector(base_url: impl Into<String>, connector: C) -> Self {
+        Self {
+            base_url: base_url.into(),
+            connector,
+        }
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Start building a query to send to this client's server
+    pub fn query(&self, nsid: Nsid) -> request::Builder {
+        XrpcRequest::builder()
+            .r#type(Type::Query)
+            .nsid(nsid)
+            .host(&self.base_url)
+    }
+
+    /// Start building a procedure to send to this client's server
+    pub fn procedure(&self, nsid: Nsid) -> request::Builder {
+        XrpcRequest::builder()
+            .r#type(Type::Procedure)
+            .nsid(nsid)
+            .host(&self.base_url)
+    }
+
+    /// Send `request` to this client's server and wait for the response.
+    ///
+    /// Whatever host the request was built with, it's replaced with this client's base URL.
+    pub async fn send(&self, mut request: XrpcRequest) -> Result<XrpcResponse, Error> {
+        request.set_host(&self.base_url);
+        let request = http::Request::try_from(request)?;
+        let response = self
+            .connector
+            .call(request)
+            .await
+            .map_err(response::Error::Transport)?;
+
+        Ok(XrpcResponse::try_from(response)?)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Request(#[from] request::Error),
+    #[error(transparent)]
+    Response(#[from] response::Error),
+}
+
+impl Error {
+    /// Whether the same request might succeed if it's sent again later
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Request(_) => false,
+            Self::Response(e) => e.is_retryable(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BoxError, BoxFuture, Connector, Error, XrpcClient};
+    use crate::request::Type;
+    use crate::response::{self, Body};
+    use crate::{Nsid, XrpcRequest};
+    use bytes::Bytes;
+    use std::sync::{Arc, Mutex};
+
+    /// Responds to every request with the same response, and remembers the requests it was sent
+    #[derive(Clone, Default)]
+    struct FakeConnector {
+        response: Option<(u16, &'static str, &'static str)>,
+        requests: Arc<Mutex<Vec<http::Request<Bytes>>>>,
+    }
+
+    impl Connector for FakeConnector {
+        fn call(
+            &self,
+            request: http::Request<Bytes>,
+        ) -> BoxFuture<'static, Result<http::Response<Bytes>, BoxError>> {
+            self.requests.lock().unwrap().push(request);
+            let response = self.response;
+            Box::pin(async move {
+                let (status, encoding, body) = response.ok_or("connection refused")?;
+                Ok(http::Response::builder()
+                    .status(status)
+                    .header(http::header::CONTENT_TYPE, encoding)
+                    .body(Bytes::from(body))?)
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_query() {
+        let connector = FakeConnector {
+            response: Some((200, "text/plain", "alice.test")),
+            ..Default::default()
+        };
+        let client = XrpcClient::with_connector("http://localhost:2583", connector.clone());
+
+        let request = client
+            .query(Nsid::new("app.bsky.getProfile").unwrap())
+            .parameter("user", "alice.test")
+            .build()
+            .unwrap();
+        let response = client.send(request).await.unwrap();
+        assert_eq!(response.body(), &Body::Text("alice.test".to_owned()));
+
+        let requests = connector.requests.lock().unwrap();
+        assert_eq!(requests[0].method(), http::Method::GET);
+        assert_eq!(
+            requests[0].uri(),
+            "http://localhost:2583/xrpc/app.bsky.getProfile?user=alice.test"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_procedure() {
+        let connector = FakeConnector {
+            response: Some((
+                400,
+                "application/json",
+                r#"{"error":"InvalidRequest","message":"missing username"}"#,
+            )),
+            ..Default::default()
+        };
+        let client = XrpcClient::with_connector("http://localhost:2583", connector.clone());
+
+        let request = client
+            .procedure(Nsid::new("com.atproto.createAccount").unwrap())
+            .body("{}")
+            .encoding("application/json")
+            .build()
+            .unwrap();
+        let err = client.send(request).await.unwrap_err();
+        assert!(!err.is_retryable());
+        assert!(matches!(
+            err,
+            Error::Response(response::Error::Http { error: Some(_), .. })
+        ));
+
+        let requests = connector.requests.lock().unwrap();
+        assert_eq!(requests[0].method(), http::Method::POST);
+        assert_eq!(requests[0].body().as_ref(), b"{}");
+    }
+
+    #[tokio::test]
+    async fn test_send_uses_base_url() {
+        let connector = FakeConnector {
+            response: Some((200, "text/plain", "alice.test")),
+            ..Default::default()
+        };
+        let client = XrpcClient::with_connector("http://localhost:2583/", connector.clone());
+
+        let request = XrpcRequest::builder()
+            .r#type(Type::Query)
+            .nsid(Nsid::new("app.bsky.getProfile").unwrap())
+            .host("https://bsky.social")
+            .build()
+            .unwrap();
+        client.send(request).await.unwrap();
+
+        let requests = connector.requests.lock().unwrap();
+        assert_eq!(
+            requests[0].uri(),
+            "http://localhost:2583/xrpc/app.bsky.getProfile"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_transport_error() {
+        let client = XrpcClient::with_connector("http://localhost:2583", FakeConnector::default());
+
+        let request = client
+            .query(Nsid::new("app.bsky.getProfile").unwrap())
+            .build()
+            .unwrap();
+        let err = client.send(request).await.unwrap_err();
+        assert!(err.is_retryable());
+        assert_eq!(err.to_string(), "couldn't send the request");
+        let source = std::error::Error::source(&err).unwrap();
+        assert_eq!(source.to_string(), "connection refused");
+    }
+}
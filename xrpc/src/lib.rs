@@ -1,10 +1,26 @@
+mod check;
+#[cfg(feature = "client")]
+pub mod client;
 pub mod nsid;
 pub mod parameter;
+pub mod query;
 pub mod request;
 pub mod response;
+#[cfg(feature = "server")]
+pub mod server;
+#[cfg(feature = "serde_json")]
+pub mod validate;
 
-pub use nsid::Nsid;
+#[cfg(feature = "client")]
+pub use client::XrpcClient;
+pub use nsid::{Nsid, NsidNamespace};
 pub use parameter::Parameter;
+pub use request::XrpcRequest;
+pub use response::XrpcResponse;
+#[cfg(feature = "server")]
+pub use server::XrpcRouter;
+#[cfg(feature = "serde_json")]
+pub use validate::ValidationErrors;
 
 pub enum XrpcBodyEncoding {
     String(String),
@@ -17,6 +33,9 @@ pub struct XrpcBody {
     pub description: Option<String>,
 }
 
+/// An error returned by an XRPC server in an `{ "error": ..., "message": ... }` envelope
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{name}{}", .description.as_ref().map(|d| format!(": {d}")).unwrap_or_default())]
 pub struct XrpcError {
     pub name: String,
     pub description: Option<String>,
@@ -1,12 +1,15 @@
 use convert_case::{Case, Casing};
-use once_cell::sync::Lazy;
-use regex::Regex;
+use std::cmp::Ordering;
 use std::{fmt, str::FromStr};
 
-// I wrote tests for this regex and I'm still not confident it's correct. 😅
-static NSID_REGEX: Lazy<Regex> = Lazy::new(|| {
-    Regex::new(r"^([[[:alnum:]]\-]+)\.([[[:alnum:]]\-]+)\.([[[:alnum:]]\-]+\.?)+$").unwrap()
-});
+/// The most characters an NSID can have
+const MAX_LENGTH: usize = 317;
+/// The most characters the authority of an NSID can have, the same as a domain name
+const MAX_AUTHORITY_LENGTH: usize = 253;
+/// The most characters a segment can have, the same as a domain name label
+const MAX_SEGMENT_LENGTH: usize = 63;
+/// The fewest segments an NSID can have, an authority of at least two plus the name
+const MIN_SEGMENTS: usize = 3;
 
 /// https://atproto.com/specs/nsid
 ///
@@ -20,7 +23,13 @@ static NSID_REGEX: Lazy<Regex> = Lazy::new(|| {
 /// name      = segment
 /// nsid      = authority delim name
 /// nsid-ns   = authority delim "*"
-#[derive(Debug, Clone, PartialEq, Eq)]
+///
+/// ## Limits
+///
+/// - An NSID has at least 3 segments, so the authority has at least 2
+/// - Each segment is at most 63 characters long
+/// - The authority is at most 253 characters long, and the whole NSID at most 317
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Nsid {
     inner: String,
 }
@@ -29,46 +38,120 @@ impl Nsid {
     pub fn new(inner: impl ToString) -> Result<Self, Error> {
         let inner = inner.to_string();
         if inner.is_empty() {
-            return Err(Error(ErrorRepr::Invalid));
+            return Err(Error(ErrorRepr::Empty));
         }
-
-        if !NSID_REGEX.is_match(&inner) {
-            return Err(Error(ErrorRepr::Invalid));
+        if inner.len() > MAX_LENGTH {
+            return Err(Error(ErrorRepr::TooLong {
+                length: inner.len(),
+            }));
         }
 
+        let (authority, name) = inner.rsplit_once('.').unwrap_or(("", &inner));
+        validate_authority(authority, inner.split('.').count())?;
+        validate_segment(authority.split('.').count(), name)?;
+
         Ok(Self { inner })
     }
 
+    /// Every segment of the NSID, in order e.g. `com`, `atproto`, and `test` for
+    /// `com.atproto.test`
+    pub fn segments(&self) -> impl DoubleEndedIterator<Item = &str> {
+        self.inner.split('.')
+    }
+
+    /// The segments before the name, in NSID order e.g. `com.atproto` for `com.atproto.test`
+    pub fn authority_segments(&self) -> &str {
+        self.inner
+            .rsplit_once('.')
+            .map_or(&self.inner, |(authority, _name)| authority)
+    }
+
+    /// The authority as the domain name it's derived from e.g. `atproto.com` for
+    /// `com.atproto.test`
+    pub fn domain_authority(&self) -> String {
+        self.authority_segments()
+            .rsplit('.')
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+
+    /// The same as [`Nsid::domain_authority`]
     pub fn authority(&self) -> String {
-        // split the nsid into segments
-        let mut parts = self
-            .inner
-            .split('.')
-            // reverse the order of the segments
-            .rev();
-        // remove the last segment
-        let _last = parts.next();
-        // rejoin the segments, separated by a dot
-        let mut authority = parts.fold(String::new(), |mut acc, part| {
-            acc.push_str(part);
-            acc.push('.');
-            acc
-        });
-        // remove trailing dot
-        let _ = authority.pop();
-
-        authority
+        self.domain_authority()
+    }
+
+    /// The final segment e.g. `test` for `com.atproto.test`
+    pub fn name(&self) -> &str {
+        self.inner
+            .rsplit_once('.')
+            .map_or(&self.inner, |(_authority, name)| name)
     }
 
     pub fn as_crate_name(&self) -> String {
-        self.authority().replace('.', "-")
+        self.domain_authority().replace('.', "-")
     }
 
     pub fn as_struct_name(&self) -> String {
-        self.inner.split('.').last().unwrap().to_case(Case::Pascal)
+        self.name().to_case(Case::Pascal)
+    }
+
+    /// The namespace that this NSID belongs to e.g. `com.atproto.*` for `com.atproto.test`
+    pub fn namespace(&self) -> NsidNamespace {
+        NsidNamespace {
+            authority: self.authority_segments().to_owned(),
+        }
     }
 }
 
+/// Check the authority of an NSID or namespace, given the number of segments in the whole thing
+fn validate_authority(authority: &str, segment_count: usize) -> Result<(), Error> {
+    if segment_count < MIN_SEGMENTS {
+        return Err(Error(ErrorRepr::TooFewSegments {
+            count: segment_count,
+        }));
+    }
+    if authority.len() > MAX_AUTHORITY_LENGTH {
+        return Err(Error(ErrorRepr::AuthorityTooLong {
+            length: authority.len(),
+        }));
+    }
+
+    authority
+        .split('.')
+        .enumerate()
+        .try_for_each(|(index, segment)| validate_segment(index, segment))
+}
+
+/// `segment = alpha *( alpha / number / "-" )`
+fn validate_segment(index: usize, segment: &str) -> Result<(), Error> {
+    let mut chars = segment.chars();
+    match chars.next() {
+        None => return Err(Error(ErrorRepr::EmptySegment { index })),
+        Some(c) if !c.is_ascii_alphabetic() => {
+            return Err(Error(ErrorRepr::SegmentStart {
+                index,
+                segment: segment.to_owned(),
+            }))
+        }
+        Some(_) => {}
+    }
+    if let Some(character) = chars.find(|&c| !c.is_ascii_alphanumeric() && c != '-') {
+        return Err(Error(ErrorRepr::InvalidCharacter {
+            index,
+            segment: segment.to_owned(),
+            character,
+        }));
+    }
+    if segment.len() > MAX_SEGMENT_LENGTH {
+        return Err(Error(ErrorRepr::SegmentTooLong {
+            index,
+            segment: segment.to_owned(),
+        }));
+    }
+
+    Ok(())
+}
+
 impl FromStr for Nsid {
     type Err = Error;
 
@@ -77,12 +160,148 @@ impl FromStr for Nsid {
     }
 }
 
+impl TryFrom<String> for Nsid {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::new(s)
+    }
+}
+
+impl AsRef<str> for Nsid {
+    fn as_ref(&self) -> &str {
+        &self.inner
+    }
+}
+
 impl fmt::Display for Nsid {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.inner)
     }
 }
 
+/// NSIDs are ordered segment by segment through their authority, then by name. That keeps NSIDs
+/// in the same namespace together, with those nested more deeply after them, e.g.
+/// `com.atproto.test` comes before `com.atproto.sync.getRepo`.
+impl Ord for Nsid {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.authority_segments()
+            .split('.')
+            .cmp(other.authority_segments().split('.'))
+            .then_with(|| self.name().cmp(other.name()))
+    }
+}
+
+impl PartialOrd for Nsid {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Nsid {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.inner)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Nsid {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let inner = String::deserialize(deserializer)?;
+        Self::new(inner).map_err(serde::de::Error::custom)
+    }
+}
+
+/// A wildcard that stands for every NSID under an authority, like `com.atproto.*`
+///
+/// ## Grammar
+///
+/// nsid-ns   = authority delim "*"
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct NsidNamespace {
+    authority: String,
+}
+
+impl NsidNamespace {
+    pub fn new(inner: impl ToString) -> Result<Self, Error> {
+        let inner = inner.to_string();
+        if inner.is_empty() {
+            return Err(Error(ErrorRepr::Empty));
+        }
+        let authority = inner
+            .strip_suffix(".*")
+            .ok_or(Error(ErrorRepr::MissingWildcard))?;
+
+        // The authority has to be one that an NSID could have, so count the `*` as the name
+        validate_authority(authority, inner.split('.').count())?;
+
+        Ok(Self {
+            authority: authority.to_owned(),
+        })
+    }
+
+    /// The namespace without the trailing `.*`, in NSID order e.g. `com.atproto` for
+    /// `com.atproto.*`
+    pub fn authority_segments(&self) -> &str {
+        &self.authority
+    }
+
+    /// Whether `nsid` is under this namespace. That includes NSIDs nested more deeply, so
+    /// `com.atproto.*` matches both `com.atproto.getProfile` and `com.atproto.sync.getRepo`.
+    ///
+    /// Authorities are domain names, so they're compared case-insensitively.
+    pub fn matches(&self, nsid: &Nsid) -> bool {
+        nsid.inner
+            .split_at_checked(self.authority.len())
+            .is_some_and(|(authority, rest)| {
+                authority.eq_ignore_ascii_case(&self.authority) && rest.starts_with('.')
+            })
+    }
+}
+
+impl FromStr for NsidNamespace {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::new(s)
+    }
+}
+
+impl fmt::Display for NsidNamespace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.*", self.authority)
+    }
+}
+
+/// Namespaces are ordered segment by segment, like the authorities of [`Nsid`]s
+impl Ord for NsidNamespace {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.authority.split('.').cmp(other.authority.split('.'))
+    }
+}
+
+impl PartialOrd for NsidNamespace {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for NsidNamespace {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for NsidNamespace {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let inner = String::deserialize(deserializer)?;
+        Self::new(inner).map_err(serde::de::Error::custom)
+    }
+}
+
 #[derive(thiserror::Error, Debug)]
 #[error(transparent)]
 pub struct Error(#[from] ErrorRepr);
@@ -92,33 +311,106 @@ impl Error {
     pub fn invalid() -> Self {
         Self(ErrorRepr::Invalid)
     }
+
+    pub fn kind(&self) -> ErrorKind {
+        match &self.0 {
+            ErrorRepr::Invalid => ErrorKind::Invalid,
+            ErrorRepr::Empty => ErrorKind::Empty,
+            ErrorRepr::TooLong { .. } => ErrorKind::TooLong,
+            ErrorRepr::TooFewSegments { .. } => ErrorKind::TooFewSegments,
+            ErrorRepr::AuthorityTooLong { .. } => ErrorKind::AuthorityTooLong,
+            ErrorRepr::EmptySegment { .. } => ErrorKind::EmptySegment,
+            ErrorRepr::SegmentStart { .. } => ErrorKind::SegmentStart,
+            ErrorRepr::InvalidCharacter { .. } => ErrorKind::InvalidCharacter,
+            ErrorRepr::SegmentTooLong { .. } => ErrorKind::SegmentTooLong,
+            ErrorRepr::MissingWildcard => ErrorKind::MissingWildcard,
+        }
+    }
+
+    /// For errors in a single segment, which one it was, counting from 0
+    pub fn segment(&self) -> Option<usize> {
+        match &self.0 {
+            ErrorRepr::EmptySegment { index }
+            | ErrorRepr::SegmentStart { index, .. }
+            | ErrorRepr::InvalidCharacter { index, .. }
+            | ErrorRepr::SegmentTooLong { index, .. } => Some(*index),
+            _ => None,
+        }
+    }
 }
 
-#[derive(thiserror::Error, Debug)]
-enum ErrorRepr {
+/// Why an NSID or namespace was invalid, see [`Error::kind`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// Made with [`Error::invalid`], without saying why
     Invalid,
+    Empty,
+    TooLong,
+    TooFewSegments,
+    AuthorityTooLong,
+    /// Two dots in a row, or a dot at the start or end
+    EmptySegment,
+    /// A segment started with something other than a letter
+    SegmentStart,
+    /// A segment contained something other than a letter, digit, or hyphen
+    InvalidCharacter,
+    SegmentTooLong,
+    /// A namespace didn't end with `.*`
+    MissingWildcard,
 }
 
-impl fmt::Display for ErrorRepr {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            ErrorRepr::Invalid => write!(f, "invalid nsid"),
-        }
-    }
+#[derive(thiserror::Error, Debug)]
+enum ErrorRepr {
+    #[error("invalid nsid")]
+    Invalid,
+    #[error("nsid is empty")]
+    Empty,
+    #[error("nsid is {length} characters long, but can be at most {MAX_LENGTH}")]
+    TooLong { length: usize },
+    #[error("nsid has {count} segments, but needs at least {MIN_SEGMENTS}")]
+    TooFewSegments { count: usize },
+    #[error(
+        "nsid authority is {length} characters long, but can be at most {MAX_AUTHORITY_LENGTH}"
+    )]
+    AuthorityTooLong { length: usize },
+    #[error("nsid segment {index} is empty")]
+    EmptySegment { index: usize },
+    #[error("nsid segment {index} `{segment}` must start with a letter")]
+    SegmentStart { index: usize, segment: String },
+    #[error(
+        "nsid segment {index} `{segment}` contains `{character}`, but segments can only contain \
+         letters, digits, and hyphens"
+    )]
+    InvalidCharacter {
+        index: usize,
+        segment: String,
+        character: char,
+    },
+    #[error(
+        "nsid segment {index} `{segment}` is {} characters long, but can be at most \
+         {MAX_SEGMENT_LENGTH}",
+        segment.len()
+    )]
+    SegmentTooLong { index: usize, segment: String },
+    #[error("nsid namespace must end with `.*`")]
+    MissingWildcard,
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Error, Nsid, NSID_REGEX};
+    use super::{Error, ErrorKind, Nsid, NsidNamespace};
+    use abnf_rs::{Generator, Grammar};
     use std::str::FromStr;
 
     #[test]
     fn test_empty() {
-        let expected = Error::invalid().to_string();
-        let actual = Nsid::new("")
-            .expect_err("empty strings are invalid")
-            .to_string();
-        assert_eq!(expected, actual);
+        let err = Nsid::new("").expect_err("empty strings are invalid");
+        assert_eq!(err.kind(), ErrorKind::Empty);
+        assert_eq!(err.to_string(), "nsid is empty");
+
+        assert_eq!(Error::invalid().kind(), ErrorKind::Invalid);
+        assert_eq!(Error::invalid().to_string(), "invalid nsid");
     }
 
     #[test]
@@ -127,6 +419,18 @@ mod tests {
         assert_eq!(nsid.authority(), "atproto.com");
     }
 
+    #[test]
+    fn test_segments() {
+        let nsid = Nsid::new("com.atproto.sync.getRepo").unwrap();
+        assert_eq!(
+            nsid.segments().collect::<Vec<_>>(),
+            vec!["com", "atproto", "sync", "getRepo"]
+        );
+        assert_eq!(nsid.name(), "getRepo");
+        assert_eq!(nsid.authority_segments(), "com.atproto.sync");
+        assert_eq!(nsid.domain_authority(), "sync.atproto.com");
+    }
+
     #[test]
     fn test_struct_name() {
         let nsid = Nsid::new("com.atproto.test".to_string()).unwrap();
@@ -147,15 +451,234 @@ mod tests {
     }
 
     #[test]
-    fn test_regex() {
-        assert!(NSID_REGEX.is_match("com.atproto.test"));
-        assert!(NSID_REGEX.is_match("dashes.are.a-ok"));
-        assert!(NSID_REGEX.is_match("com.atproto.testCamelCase"));
-        assert!(NSID_REGEX.is_match("com.atproto.test.long.name.with.many.segments"));
-        assert!(NSID_REGEX.is_match("99.numbers.are.ok.2"));
-        assert!(!NSID_REGEX.is_match("symbols.are.not.ok.$#{!^["));
-        assert!(!NSID_REGEX.is_match("spaces are not ok.at.all"));
-        assert!(!NSID_REGEX.is_match("underscorse_are_not_ok.at.all"));
-        assert!(!NSID_REGEX.is_match("multiple.dots.in.a.row...are.not.ok"));
+    fn test_ordering() {
+        let mut nsids: Vec<_> = [
+            "com.atproto.sync.getRepo",
+            "app.bsky.getProfile",
+            "com.atproto.test",
+            "com.atproto-lab.test",
+            "com.atproto.getProfile",
+        ]
+        .into_iter()
+        .map(|nsid| Nsid::new(nsid).unwrap())
+        .collect();
+        nsids.sort();
+
+        let nsids: Vec<_> = nsids.iter().map(AsRef::as_ref).collect();
+        assert_eq!(
+            nsids,
+            vec![
+                "app.bsky.getProfile",
+                "com.atproto.getProfile",
+                "com.atproto.test",
+                "com.atproto.sync.getRepo",
+                "com.atproto-lab.test",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_try_from_string() {
+        let nsid = Nsid::try_from("com.atproto.test".to_owned()).unwrap();
+        assert_eq!(nsid.as_ref(), "com.atproto.test");
+        assert!(Nsid::try_from("com.atproto.".to_owned()).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        use std::collections::BTreeMap;
+
+        let nsid: Nsid = serde_json::from_str(r#""com.atproto.test""#).unwrap();
+        assert_eq!(nsid.as_ref(), "com.atproto.test");
+        assert_eq!(
+            serde_json::to_string(&nsid).unwrap(),
+            r#""com.atproto.test""#
+        );
+
+        let err = serde_json::from_str::<Nsid>(r#""99.bottles.of""#).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "nsid segment 0 `99` must start with a letter"
+        );
+
+        let map: BTreeMap<Nsid, u32> =
+            serde_json::from_str(r#"{"com.atproto.test": 1, "app.bsky.getProfile": 2}"#).unwrap();
+        assert_eq!(map.keys().next().unwrap().as_ref(), "app.bsky.getProfile");
+    }
+
+    #[test]
+    fn test_namespace() {
+        let namespace = NsidNamespace::from_str("com.atproto.*").unwrap();
+        assert_eq!(namespace.authority_segments(), "com.atproto");
+        assert_eq!(namespace.to_string(), "com.atproto.*");
+
+        assert!(namespace.matches(&Nsid::new("com.atproto.test").unwrap()));
+        assert!(namespace.matches(&Nsid::new("com.atproto.sync.getRepo").unwrap()));
+        assert!(!namespace.matches(&Nsid::new("com.atprotocol.test").unwrap()));
+        assert!(!namespace.matches(&Nsid::new("app.bsky.getProfile").unwrap()));
+
+        let nsid = Nsid::new("com.atproto.sync.getRepo").unwrap();
+        assert_eq!(nsid.namespace().to_string(), "com.atproto.sync.*");
+        assert!(nsid.namespace().matches(&nsid));
+
+        // Authorities are case-insensitive, but names aren't part of the namespace
+        let namespace = NsidNamespace::new("COM.atproto.*").unwrap();
+        assert!(namespace.matches(&Nsid::new("com.atproto.sync").unwrap()));
+        assert!(namespace.matches(&Nsid::new("com.ATPROTO.sync.getRepo").unwrap()));
+        assert!(!namespace.matches(&Nsid::new("com.atprotocol.sync").unwrap()));
+    }
+
+    #[test]
+    fn test_namespace_order() {
+        let mut namespaces: Vec<_> = ["com.atproto.sync.*", "com.atproto-lab.*", "com.atproto.*"]
+            .into_iter()
+            .map(|namespace| NsidNamespace::new(namespace).unwrap())
+            .collect();
+        namespaces.sort();
+        let namespaces: Vec<_> = namespaces.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            namespaces,
+            ["com.atproto.*", "com.atproto.sync.*", "com.atproto-lab.*"]
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_namespace_serde() {
+        let namespace: NsidNamespace = serde_json::from_str(r#""com.atproto.*""#).unwrap();
+        assert_eq!(namespace.authority_segments(), "com.atproto");
+        assert_eq!(
+            serde_json::to_string(&namespace).unwrap(),
+            r#""com.atproto.*""#
+        );
+
+        let err = serde_json::from_str::<NsidNamespace>(r#""com.atproto""#).unwrap_err();
+        assert!(err.is_data());
+    }
+
+    #[test]
+    fn test_invalid_namespace() {
+        for (invalid, kind) in [
+            ("", ErrorKind::Empty),
+            ("*", ErrorKind::MissingWildcard),
+            (".*", ErrorKind::TooFewSegments),
+            ("com.*", ErrorKind::TooFewSegments),
+            ("com.atproto", ErrorKind::MissingWildcard),
+            ("com.atproto.", ErrorKind::MissingWildcard),
+            ("com..*", ErrorKind::EmptySegment),
+            ("com.at_proto.*", ErrorKind::InvalidCharacter),
+        ] {
+            assert_eq!(NsidNamespace::new(invalid).expect_err(invalid).kind(), kind);
+        }
+        // NSIDs don't accept wildcards
+        assert!(Nsid::new("com.atproto.*").is_err());
+    }
+
+    #[test]
+    fn test_valid() {
+        for valid in [
+            "com.atproto.test",
+            "dashes.are.a-ok",
+            "com.atproto.testCamelCase",
+            "com.atproto.test.long.name.with.many.segments",
+            "numbers.are.ok2.after.the.first9",
+        ] {
+            assert!(Nsid::new(valid).is_ok(), "{valid}");
+        }
+    }
+
+    #[test]
+    fn test_invalid() {
+        for (invalid, kind, segment) in [
+            ("99.numbers.are.ok.2", ErrorKind::SegmentStart, Some(0)),
+            (
+                "leading.digits.are.not.2ok",
+                ErrorKind::SegmentStart,
+                Some(4),
+            ),
+            ("dashes.are.-not-ok", ErrorKind::SegmentStart, Some(2)),
+            (
+                "symbols.are.not.ok.$#{!^[",
+                ErrorKind::SegmentStart,
+                Some(4),
+            ),
+            (
+                "spaces are not ok.at.all",
+                ErrorKind::InvalidCharacter,
+                Some(0),
+            ),
+            (
+                "underscores_are_not_ok.at.all",
+                ErrorKind::InvalidCharacter,
+                Some(0),
+            ),
+            (
+                "multiple.dots.in.a.row...are.not.ok",
+                ErrorKind::EmptySegment,
+                Some(5),
+            ),
+            ("no.trailing.dots.", ErrorKind::EmptySegment, Some(3)),
+            (".no.leading.dots", ErrorKind::EmptySegment, Some(0)),
+            ("too.short", ErrorKind::TooFewSegments, None),
+            ("short", ErrorKind::TooFewSegments, None),
+        ] {
+            let err = Nsid::new(invalid).expect_err(invalid);
+            assert_eq!((err.kind(), err.segment()), (kind, segment), "{invalid}");
+        }
+
+        let err = Nsid::new("com.example.under_score").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "nsid segment 2 `under_score` contains `_`, but segments can only contain letters, \
+             digits, and hyphens"
+        );
+    }
+
+    #[test]
+    fn test_lengths() {
+        let segment = "a".repeat(63);
+        assert!(Nsid::new(format!("com.{segment}.test")).is_ok());
+
+        let err = Nsid::new(format!("com.{segment}a.test")).unwrap_err();
+        assert_eq!(
+            (err.kind(), err.segment()),
+            (ErrorKind::SegmentTooLong, Some(1))
+        );
+
+        let authority = [segment.as_str(); 4].join(".");
+        let err = Nsid::new(format!("{authority}.test")).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AuthorityTooLong);
+        assert_eq!(
+            err.to_string(),
+            "nsid authority is 255 characters long, but can be at most 253"
+        );
+
+        let authority = [segment.as_str(); 3].join(".");
+        assert!(Nsid::new(format!("{authority}.{segment}")).is_ok());
+        let err = Nsid::new("a".repeat(318)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TooLong);
+    }
+
+    #[test]
+    fn test_agrees_with_grammar() {
+        // The grammar above, requiring at least two segments in the authority
+        const NSID_ABNF: &str = r#"alpha     = %x41-5A / %x61-7A
+number    = %x30-39
+delim     = "."
+segment   = alpha *( alpha / number / "-" )
+authority = segment 1*( delim segment )
+name      = segment
+nsid      = authority delim name
+"#;
+        let grammar = Grammar::from_metasyntax(NSID_ABNF).unwrap();
+        let mut generator = Generator::from_seed(&grammar, 317);
+
+        for _ in 0..500 {
+            let valid = generator.matching("nsid").unwrap();
+            assert!(Nsid::new(&valid).is_ok(), "{valid:?}");
+            let invalid = generator.near_miss("nsid").unwrap();
+            assert!(Nsid::new(&invalid).is_err(), "{invalid:?}");
+        }
     }
 }
@@ -1,47 +1,263 @@
+use std::collections::HashMap;
 use std::fmt;
 
 #[cfg(feature = "serde_json")]
 use serde_json::{Map, Value};
 
+/// https://atproto.com/specs/lexicon#field-types
+#[derive(Debug, Clone, PartialEq)]
 pub enum Type {
-    String,
+    /// A string, which may have to be in a specific format
+    String(Option<Format>),
     Number,
     Integer,
     Boolean,
+    Bytes,
+    /// A link to content by its CID
+    CidLink,
+    /// A reference to a file stored separately
+    Blob,
+    /// Any object
+    Unknown,
+    /// A list of values that each match `items`
+    Array(Box<Parameter>),
+    /// An object with known properties, of which those named in `required` must be present
+    Object {
+        properties: HashMap<String, Parameter>,
+        required: Vec<String>,
+    },
+    /// A reference to another definition, e.g. `com.atproto.repo.strongRef` or `#main`
+    Ref(String),
+    /// One of several definitions. Unless the union is `closed`, other definitions are allowed
+    /// too.
+    Union {
+        refs: Vec<String>,
+        closed: bool,
+    },
 }
 
-impl Type {}
+impl Type {
+    /// The name of the type in a lexicon
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Type::String(_) => "string",
+            Type::Number => "number",
+            Type::Integer => "integer",
+            Type::Boolean => "boolean",
+            Type::Bytes => "bytes",
+            Type::CidLink => "cid-link",
+            Type::Blob => "blob",
+            Type::Unknown => "unknown",
+            Type::Array(_) => "array",
+            Type::Object { .. } => "object",
+            Type::Ref(_) => "ref",
+            Type::Union { .. } => "union",
+        }
+    }
+}
 
 impl fmt::Display for Type {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Type::String => write!(f, "String"),
-            Type::Number => write!(f, "u64"),
+            Type::String(_) | Type::CidLink => write!(f, "String"),
+            Type::Number => write!(f, "f64"),
             Type::Integer => write!(f, "i64"),
             Type::Boolean => write!(f, "bool"),
+            Type::Bytes => write!(f, "Vec<u8>"),
+            Type::Array(items) => write!(f, "Vec<{}>", items.r#type),
+            Type::Blob
+            | Type::Unknown
+            | Type::Object { .. }
+            | Type::Ref(_)
+            | Type::Union { .. } => write!(f, "serde_json::Value"),
         }
     }
 }
 
+/// Parse the name of a type, like `"string"`. Types that need more of their schema, like `array`
+/// and its `items`, have to be parsed with [`Type::from_schema`].
 #[cfg(feature = "serde_json")]
 impl TryFrom<&Value> for Type {
     type Error = Error;
 
     fn try_from(value: &Value) -> Result<Self, Self::Error> {
-        match value.as_str() {
-            Some("string") => Ok(Self::String),
+        if !value.is_string() {
+            return Err(Error::InvalidField("type"));
+        }
+        Self::from_schema(&Map::from_iter([("type".to_owned(), value.clone())]))
+    }
+}
+
+#[cfg(feature = "serde_json")]
+impl Type {
+    /// Parse the type of a schema, like `{ "type": "array", "items": { "type": "string" } }`
+    pub fn from_schema(schema: &Map<String, Value>) -> Result<Self, Error> {
+        let r#type = schema.get("type").ok_or(Error::MissingField("type"))?;
+        match r#type.as_str() {
+            Some("string") => {
+                let format = schema.get("format").map(Format::try_from).transpose()?;
+                Ok(Self::String(format))
+            }
             Some("number") => Ok(Self::Number),
             Some("integer") => Ok(Self::Integer),
             Some("boolean") => Ok(Self::Boolean),
+            Some("bytes") => Ok(Self::Bytes),
+            Some("cid-link") => Ok(Self::CidLink),
+            Some("blob") => Ok(Self::Blob),
+            Some("unknown") => Ok(Self::Unknown),
+            Some("array") => {
+                let items = schema
+                    .get("items")
+                    .ok_or(Error::MissingField("items"))?
+                    .as_object()
+                    .ok_or(Error::InvalidField("items"))?;
+                Ok(Self::Array(Box::new(items.try_into()?)))
+            }
+            Some("object") => {
+                let properties = match schema.get("properties") {
+                    Some(properties) => properties
+                        .as_object()
+                        .ok_or(Error::InvalidField("properties"))?
+                        .iter()
+                        .map(|(name, property)| {
+                            let property = property
+                                .as_object()
+                                .ok_or(Error::InvalidField("properties"))?;
+                            Ok((name.clone(), property.try_into()?))
+                        })
+                        .collect::<Result<_, Error>>()?,
+                    None => HashMap::new(),
+                };
+                let required = match schema.get("required") {
+                    Some(required) => strings(required, "required")?,
+                    None => Vec::new(),
+                };
+
+                Ok(Self::Object {
+                    properties,
+                    required,
+                })
+            }
+            Some("ref") => {
+                let r#ref = schema
+                    .get("ref")
+                    .ok_or(Error::MissingField("ref"))?
+                    .as_str()
+                    .ok_or(Error::InvalidField("ref"))?;
+                Ok(Self::Ref(r#ref.to_owned()))
+            }
+            Some("union") => {
+                let refs = strings(
+                    schema.get("refs").ok_or(Error::MissingField("refs"))?,
+                    "refs",
+                )?;
+                let closed = match schema.get("closed") {
+                    Some(closed) => closed.as_bool().ok_or(Error::InvalidField("closed"))?,
+                    None => false,
+                };
+
+                Ok(Self::Union { refs, closed })
+            }
             _ => Err(Error::InvalidField("type")),
         }
     }
 }
 
+/// Parse an array of strings
+#[cfg(feature = "serde_json")]
+fn strings(value: &Value, field: &'static str) -> Result<Vec<String>, Error> {
+    value
+        .as_array()
+        .ok_or(Error::InvalidField(field))?
+        .iter()
+        .map(|s| {
+            s.as_str()
+                .map(ToOwned::to_owned)
+                .ok_or(Error::InvalidField(field))
+        })
+        .collect()
+}
+
+/// https://atproto.com/specs/lexicon#string-formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    AtIdentifier,
+    AtUri,
+    Cid,
+    Datetime,
+    Did,
+    Handle,
+    Language,
+    Nsid,
+    RecordKey,
+    Tid,
+    Uri,
+}
+
+impl Format {
+    /// The name of the format in a lexicon
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Format::AtIdentifier => "at-identifier",
+            Format::AtUri => "at-uri",
+            Format::Cid => "cid",
+            Format::Datetime => "datetime",
+            Format::Did => "did",
+            Format::Handle => "handle",
+            Format::Language => "language",
+            Format::Nsid => "nsid",
+            Format::RecordKey => "record-key",
+            Format::Tid => "tid",
+            Format::Uri => "uri",
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[cfg(feature = "serde_json")]
+impl TryFrom<&Value> for Format {
+    type Error = Error;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            Some("at-identifier") => Ok(Self::AtIdentifier),
+            Some("at-uri") => Ok(Self::AtUri),
+            Some("cid") => Ok(Self::Cid),
+            Some("datetime") => Ok(Self::Datetime),
+            Some("did") => Ok(Self::Did),
+            Some("handle") => Ok(Self::Handle),
+            Some("language") => Ok(Self::Language),
+            Some("nsid") => Ok(Self::Nsid),
+            Some("record-key") => Ok(Self::RecordKey),
+            Some("tid") => Ok(Self::Tid),
+            Some("uri") => Ok(Self::Uri),
+            _ => Err(Error::InvalidField("format")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Default {
     String(String),
     Boolean(bool),
-    Number(u64),
+    Integer(i64),
+    Number(f64),
+}
+
+impl fmt::Display for Default {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Default::String(s) => write!(f, "{s}"),
+            Default::Boolean(b) => write!(f, "{b}"),
+            Default::Integer(i) => write!(f, "{i}"),
+            Default::Number(n) => write!(f, "{n}"),
+        }
+    }
 }
 
 #[cfg(feature = "serde_json")]
@@ -52,18 +268,17 @@ impl TryFrom<&Value> for Default {
         match value {
             Value::String(s) => Ok(Self::String(s.to_string())),
             Value::Bool(b) => Ok(Self::Boolean(*b)),
-            Value::Number(n) => {
-                if let Some(i) = n.as_u64() {
-                    Ok(Self::Number(i))
-                } else {
-                    Err(Error::InvalidField("default"))
-                }
-            }
+            Value::Number(n) => match (n.as_i64(), n.as_f64()) {
+                (Some(i), _) => Ok(Self::Integer(i)),
+                (None, Some(n)) => Ok(Self::Number(n)),
+                (None, None) => Err(Error::InvalidField("default")),
+            },
             _ => Err(Error::InvalidField("default")),
         }
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct Parameter {
     pub r#type: Type,
     pub description: Option<String>,
@@ -71,8 +286,12 @@ pub struct Parameter {
     pub required: Option<bool>,
     pub min_length: Option<u64>,
     pub max_length: Option<u64>,
-    pub minimum: Option<u64>,
-    pub maximum: Option<u64>,
+    pub minimum: Option<i64>,
+    pub maximum: Option<i64>,
+    /// The only value allowed
+    pub r#const: Option<Default>,
+    /// The values allowed, if only some are
+    pub r#enum: Option<Vec<Default>>,
 }
 
 #[cfg(feature = "serde_json")]
@@ -80,32 +299,53 @@ impl TryFrom<&Map<String, Value>> for Parameter {
     type Error = Error;
 
     fn try_from(value: &Map<String, Value>) -> Result<Self, Self::Error> {
-        let r#type = value
-            .get("type")
-            .ok_or(Error::MissingField("type"))?
-            .try_into()?;
+        let r#type = Type::from_schema(value)?;
 
         let description = value
             .get("description")
             .and_then(Value::as_str)
             .map(ToOwned::to_owned);
 
-        // TODO write this in a more readable way
-        let default = if let Some(v) = value.get("default") {
-            Some(v.try_into()?)
-        } else {
-            None
-        };
+        let default = value.get("default").map(Default::try_from).transpose()?;
 
         let required = value.get("required").and_then(Value::as_bool);
 
-        let min_length = value.get("minLength").and_then(Value::as_u64);
+        // Limits that aren't whole numbers would otherwise be dropped without a word
+        let min_length = value
+            .get("minLength")
+            .map(|v| v.as_u64().ok_or(Error::InvalidField("minLength")))
+            .transpose()?;
 
-        let max_length = value.get("maxLength").and_then(Value::as_u64);
+        let max_length = value
+            .get("maxLength")
+            .map(|v| v.as_u64().ok_or(Error::InvalidField("maxLength")))
+            .transpose()?;
 
-        let minimum = value.get("minimum").and_then(Value::as_u64);
+        let minimum = value
+            .get("minimum")
+            .map(|v| v.as_i64().ok_or(Error::InvalidField("minimum")))
+            .transpose()?;
 
-        let maximum = value.get("maximum").and_then(Value::as_u64);
+        let maximum = value
+            .get("maximum")
+            .map(|v| v.as_i64().ok_or(Error::InvalidField("maximum")))
+            .transpose()?;
+
+        let r#const = value
+            .get("const")
+            .map(|v| Default::try_from(v).map_err(|_| Error::InvalidField("const")))
+            .transpose()?;
+
+        let r#enum = value
+            .get("enum")
+            .map(|v| {
+                v.as_array()
+                    .ok_or(Error::InvalidField("enum"))?
+                    .iter()
+                    .map(|v| Default::try_from(v).map_err(|_| Error::InvalidField("enum")))
+                    .collect::<Result<_, _>>()
+            })
+            .transpose()?;
 
         Ok(Self {
             r#type,
@@ -116,6 +356,8 @@ impl TryFrom<&Map<String, Value>> for Parameter {
             max_length,
             minimum,
             maximum,
+            r#const,
+            r#enum,
         })
     }
 }
@@ -127,3 +369,188 @@ pub enum Error {
     #[error("invalid field '{0}'")]
     InvalidField(&'static str),
 }
+
+#[cfg(all(test, feature = "serde_json"))]
+mod tests {
+    use super::{Default, Format, Parameter, Type};
+    use serde_json::{json, Value};
+    use std::collections::HashMap;
+
+    fn parameter(schema: Value) -> Parameter {
+        Parameter::try_from(schema.as_object().unwrap()).unwrap()
+    }
+
+    fn string() -> Parameter {
+        parameter(json!({ "type": "string" }))
+    }
+
+    #[test]
+    fn test_scalars() {
+        let did = parameter(json!({ "type": "string", "format": "did" }));
+        assert_eq!(did.r#type, Type::String(Some(Format::Did)));
+        assert_eq!(did.r#type.to_string(), "String");
+
+        let limit = parameter(json!({
+            "type": "integer",
+            "minimum": -10,
+            "maximum": 100,
+            "default": -1
+        }));
+        assert_eq!(limit.r#type, Type::Integer);
+        assert_eq!(limit.minimum, Some(-10));
+        assert_eq!(limit.default, Some(Default::Integer(-1)));
+
+        let visibility = parameter(json!({
+            "type": "string",
+            "enum": ["public", "private"],
+            "const": "public"
+        }));
+        assert_eq!(
+            visibility.r#enum,
+            Some(vec![
+                Default::String("public".to_owned()),
+                Default::String("private".to_owned())
+            ])
+        );
+        assert_eq!(
+            visibility.r#const,
+            Some(Default::String("public".to_owned()))
+        );
+
+        let ratio = parameter(json!({ "type": "number", "default": 0.5 }));
+        assert_eq!(ratio.r#type.to_string(), "f64");
+        assert_eq!(ratio.default, Some(Default::Number(0.5)));
+
+        for (name, r#type) in [
+            ("bytes", Type::Bytes),
+            ("cid-link", Type::CidLink),
+            ("blob", Type::Blob),
+            ("unknown", Type::Unknown),
+        ] {
+            assert_eq!(parameter(json!({ "type": name })).r#type, r#type);
+            assert_eq!(r#type.as_str(), name);
+        }
+    }
+
+    #[test]
+    fn test_type_names() {
+        assert_eq!(
+            Type::try_from(&json!("string")).unwrap(),
+            Type::String(None)
+        );
+        assert_eq!(Type::try_from(&json!("integer")).unwrap(), Type::Integer);
+        assert_eq!(
+            Type::try_from(&json!("object")).unwrap(),
+            Type::Object {
+                properties: HashMap::new(),
+                required: Vec::new(),
+            }
+        );
+        for (name, err) in [
+            (json!("array"), "missing field 'items'"),
+            (json!("float"), "invalid field 'type'"),
+            (json!({ "type": "string" }), "invalid field 'type'"),
+        ] {
+            assert_eq!(Type::try_from(&name).unwrap_err().to_string(), err);
+        }
+    }
+
+    #[test]
+    fn test_array() {
+        let tags = parameter(json!({
+            "type": "array",
+            "items": { "type": "string", "maxLength": 64 },
+            "maxLength": 8
+        }));
+        assert_eq!(tags.max_length, Some(8));
+        assert_eq!(tags.r#type.to_string(), "Vec<String>");
+        let Type::Array(items) = tags.r#type else {
+            panic!("expected an array");
+        };
+        assert_eq!(items.max_length, Some(64));
+    }
+
+    #[test]
+    fn test_object() {
+        let object = parameter(json!({
+            "type": "object",
+            "required": ["text"],
+            "properties": {
+                "text": { "type": "string" },
+                "reply": { "type": "ref", "ref": "#replyRef" },
+                "embed": {
+                    "type": "union",
+                    "refs": ["app.bsky.embed.images", "app.bsky.embed.external"]
+                }
+            }
+        }));
+        assert_eq!(
+            object.r#type,
+            Type::Object {
+                properties: HashMap::from([
+                    ("text".to_owned(), string()),
+                    (
+                        "reply".to_owned(),
+                        parameter(json!({ "type": "ref", "ref": "#replyRef" }))
+                    ),
+                    (
+                        "embed".to_owned(),
+                        Parameter {
+                            r#type: Type::Union {
+                                refs: vec![
+                                    "app.bsky.embed.images".to_owned(),
+                                    "app.bsky.embed.external".to_owned()
+                                ],
+                                closed: false,
+                            },
+                            ..string()
+                        }
+                    ),
+                ]),
+                required: vec!["text".to_owned()],
+            }
+        );
+        assert_eq!(object.r#type.to_string(), "serde_json::Value");
+    }
+
+    #[test]
+    fn test_invalid() {
+        for (schema, err) in [
+            (json!({}), "missing field 'type'"),
+            (json!({ "type": "float" }), "invalid field 'type'"),
+            (
+                json!({ "type": "string", "format": "email" }),
+                "invalid field 'format'",
+            ),
+            (json!({ "type": "array" }), "missing field 'items'"),
+            (json!({ "type": "ref" }), "missing field 'ref'"),
+            (
+                json!({ "type": "union", "refs": [1] }),
+                "invalid field 'refs'",
+            ),
+            (
+                json!({ "type": "boolean", "default": null }),
+                "invalid field 'default'",
+            ),
+            (
+                json!({ "type": "integer", "enum": [1, [2]] }),
+                "invalid field 'enum'",
+            ),
+            (
+                json!({ "type": "number", "maximum": 0.5 }),
+                "invalid field 'maximum'",
+            ),
+            (
+                json!({ "type": "integer", "minimum": "1" }),
+                "invalid field 'minimum'",
+            ),
+            (
+                json!({ "type": "string", "maxLength": -1 }),
+                "invalid field 'maxLength'",
+            ),
+        ] {
+            let result = Parameter::try_from(schema.as_object().unwrap());
+            assert_eq!(result.unwrap_err().to_string(), err, "{schema}");
+        }
+    }
+}
@@ -0,0 +1,620 @@
+//! Encoding query parameters for a request and decoding them on the server, checked against the
+//! parameters a method's lexicon declares.
+
+use crate::check::{self, Limit};
+use crate::parameter::{Default, Format, Type};
+use crate::Parameter;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// The value of a query parameter. Arrays are sent by repeating the parameter.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Number(f64),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    /// The name of the value's type in a lexicon
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::String(_) => "string",
+            Self::Number(_) => "number",
+            Self::Integer(_) => "integer",
+            Self::Boolean(_) => "boolean",
+            Self::Array(_) => "array",
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::String(s) => write!(f, "{s}"),
+            Self::Number(n) => write!(f, "{n}"),
+            Self::Integer(i) => write!(f, "{i}"),
+            Self::Boolean(b) => write!(f, "{b}"),
+            Self::Array(values) => {
+                let values: Vec<_> = values.iter().map(ToString::to_string).collect();
+                write!(f, "[{}]", values.join(", "))
+            }
+        }
+    }
+}
+
+/// Encode `values` as a query string, without the leading `?`, after checking them against the
+/// parameters in `schema`. Parameters are written in order of their names.
+pub fn encode(
+    schema: &HashMap<String, Parameter>,
+    values: &HashMap<String, Value>,
+) -> Result<String, Error> {
+    if let Some(name) = values.keys().find(|name| !schema.contains_key(*name)) {
+        return Err(Error::new(name, ErrorKind::Unknown));
+    }
+
+    let mut serializer = form_urlencoded::Serializer::new(String::new());
+    for (name, parameter) in schema.iter().collect::<BTreeMap<_, _>>() {
+        match (&parameter.r#type, values.get(name)) {
+            (Type::Array(items), Some(Value::Array(values))) => {
+                check_items(name, parameter, values.len())?;
+                for value in values {
+                    check(name, items, value)?;
+                    serializer.append_pair(name, &value.to_string());
+                }
+            }
+            (_, Some(value)) => {
+                check(name, parameter, value)?;
+                serializer.append_pair(name, &value.to_string());
+            }
+            (_, None) if is_required(parameter) => {
+                return Err(Error::new(name, ErrorKind::Missing))
+            }
+            (_, None) => (),
+        }
+    }
+
+    Ok(serializer.finish())
+}
+
+/// Decode a query string, without the leading `?`, into a value for each of the parameters in
+/// `schema` that it gives or has a default.
+///
+/// Parameters that aren't in `schema` are ignored.
+pub fn decode(
+    schema: &HashMap<String, Parameter>,
+    query: &str,
+) -> Result<HashMap<String, Value>, Error> {
+    let mut given: HashMap<String, Vec<String>> = HashMap::new();
+    for (name, value) in form_urlencoded::parse(query.as_bytes()) {
+        if schema.contains_key(name.as_ref()) {
+            given
+                .entry(name.into_owned())
+                .or_default()
+                .push(value.into_owned());
+        }
+    }
+
+    let mut values = HashMap::with_capacity(schema.len());
+    for (name, parameter) in schema.iter().collect::<BTreeMap<_, _>>() {
+        let raw = given.remove(name).unwrap_or_default();
+        let value = match (&parameter.r#type, raw.as_slice()) {
+            (_, []) if parameter.default.is_none() => {
+                if is_required(parameter) {
+                    return Err(Error::new(name, ErrorKind::Missing));
+                }
+                continue;
+            }
+            (Type::Array(items), raw) => {
+                check_items(name, parameter, raw.len())?;
+                let values = raw
+                    .iter()
+                    .map(|raw| parse(name, items, raw))
+                    .collect::<Result<_, _>>()?;
+                Value::Array(values)
+            }
+            (_, []) => {
+                let default = parameter.default.as_ref().map(ToString::to_string);
+                parse(name, parameter, &default.unwrap_or_default())?
+            }
+            (_, [raw]) => parse(name, parameter, raw)?,
+            (_, _) => return Err(Error::new(name, ErrorKind::Repeated)),
+        };
+        values.insert(name.clone(), value);
+    }
+
+    Ok(values)
+}
+
+fn is_required(parameter: &Parameter) -> bool {
+    parameter.required.unwrap_or(false)
+}
+
+/// Parse a single value for a parameter and check it against the parameter's limits
+fn parse(name: &str, parameter: &Parameter, raw: &str) -> Result<Value, Error> {
+    let value = match &parameter.r#type {
+        Type::String(_) => Some(Value::String(raw.to_owned())),
+        Type::Number => raw
+            .parse()
+            .ok()
+            .filter(|n: &f64| n.is_finite())
+            .map(Value::Number),
+        Type::Integer => raw.parse().ok().map(Value::Integer),
+        Type::Boolean => raw.parse().ok().map(Value::Boolean),
+        r#type => {
+            return Err(Error::new(
+                name,
+                ErrorKind::Unsupported {
+                    r#type: r#type.as_str(),
+                },
+            ))
+        }
+    };
+    let value = value.ok_or_else(|| {
+        Error::new(
+            name,
+            ErrorKind::Invalid {
+                expected: parameter.r#type.as_str(),
+                value: raw.to_owned(),
+            },
+        )
+    })?;
+
+    check(name, parameter, &value)?;
+    Ok(value)
+}
+
+/// Check that a single value is of its parameter's type and within its limits
+fn check(name: &str, parameter: &Parameter, value: &Value) -> Result<(), Error> {
+    let kind = match (&parameter.r#type, value) {
+        (Type::String(format), Value::String(s)) => {
+            let length = s.chars().count();
+            match (check::length(parameter, length), format) {
+                (Some(Limit::MinLength(min_length)), _) => Some(ErrorKind::TooShort {
+                    min_length,
+                    length: length as u64,
+                }),
+                (Some(Limit::MaxLength(max_length)), _) => Some(ErrorKind::TooLong {
+                    max_length,
+                    length: length as u64,
+                }),
+                (_, Some(format)) if !check::is_valid(*format, s) => {
+                    Some(ErrorKind::InvalidFormat {
+                        format: *format,
+                        value: s.clone(),
+                    })
+                }
+                _ => None,
+            }
+        }
+        (Type::Number, Value::Number(n)) => {
+            out_of_range(parameter, value, |bound| n.partial_cmp(&(bound as f64)))
+        }
+        (Type::Integer, Value::Integer(i)) => {
+            out_of_range(parameter, value, |bound| Some(i.cmp(&bound)))
+        }
+        (Type::Boolean, Value::Boolean(_)) => None,
+        (
+            Type::String(_) | Type::Number | Type::Integer | Type::Boolean | Type::Array(_),
+            value,
+        ) => Some(ErrorKind::WrongType {
+            expected: parameter.r#type.as_str(),
+            found: value.type_name(),
+        }),
+        (r#type, _) => Some(ErrorKind::Unsupported {
+            r#type: r#type.as_str(),
+        }),
+    };
+    if let Some(kind) = kind {
+        return Err(Error::new(name, kind));
+    }
+
+    match check::allowed(parameter, |expected| equals(expected, value)).first() {
+        Some(Limit::Const(expected)) => Err(Error::new(
+            name,
+            ErrorKind::NotConst {
+                expected: (*expected).clone(),
+                value: value.clone(),
+            },
+        )),
+        Some(_) => Err(Error::new(
+            name,
+            ErrorKind::NotInEnum {
+                value: value.clone(),
+            },
+        )),
+        None => Ok(()),
+    }
+}
+
+fn out_of_range(
+    parameter: &Parameter,
+    value: &Value,
+    compare: impl Fn(i64) -> Option<Ordering>,
+) -> Option<ErrorKind> {
+    match check::range(parameter, compare)? {
+        Limit::Minimum(minimum) => Some(ErrorKind::TooSmall {
+            minimum,
+            value: value.clone(),
+        }),
+        Limit::Maximum(maximum) => Some(ErrorKind::TooLarge {
+            maximum,
+            value: value.clone(),
+        }),
+        _ => None,
+    }
+}
+
+/// Whether a value is a `const` or `enum` value from its schema
+fn equals(expected: &Default, value: &Value) -> bool {
+    match (expected, value) {
+        (Default::String(expected), Value::String(s)) => expected == s,
+        (Default::Boolean(expected), Value::Boolean(b)) => expected == b,
+        (Default::Integer(expected), Value::Integer(i)) => expected == i,
+        (Default::Integer(expected), Value::Number(n)) => *expected as f64 == *n,
+        (Default::Number(expected), Value::Number(n)) => expected == n,
+        _ => false,
+    }
+}
+
+/// Check the number of items given for an array parameter
+fn check_items(name: &str, parameter: &Parameter, length: usize) -> Result<(), Error> {
+    let kind = match check::length(parameter, length) {
+        Some(Limit::MinLength(min_length)) => ErrorKind::TooFewItems {
+            min_length,
+            length: length as u64,
+        },
+        Some(Limit::MaxLength(max_length)) => ErrorKind::TooManyItems {
+            max_length,
+            length: length as u64,
+        },
+        _ => return Ok(()),
+    };
+
+    Err(Error::new(name, kind))
+}
+
+/// A query parameter that doesn't fit its schema
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[error("parameter `{parameter}` {kind}")]
+pub struct Error {
+    parameter: String,
+    kind: ErrorKind,
+}
+
+impl Error {
+    fn new(parameter: &str, kind: ErrorKind) -> Self {
+        Self {
+            parameter: parameter.to_owned(),
+            kind,
+        }
+    }
+
+    /// The name of the parameter
+    pub fn parameter(&self) -> &str {
+        &self.parameter
+    }
+
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    #[error("is required")]
+    Missing,
+    #[error("isn't one of the method's parameters")]
+    Unknown,
+    #[error("is given more than once")]
+    Repeated,
+    #[error("is of type {}, which can't be sent in a query", .r#type)]
+    Unsupported { r#type: &'static str },
+    #[error("must be of type {expected}, not {found}")]
+    WrongType {
+        expected: &'static str,
+        found: &'static str,
+    },
+    #[error("must be of type {expected}, but is `{value}`")]
+    Invalid {
+        expected: &'static str,
+        value: String,
+    },
+    #[error("must be at least {min_length} characters long, but is {length}")]
+    TooShort { min_length: u64, length: u64 },
+    #[error("must be at most {max_length} characters long, but is {length}")]
+    TooLong { max_length: u64, length: u64 },
+    #[error("must be given at least {min_length} times, but is given {length}")]
+    TooFewItems { min_length: u64, length: u64 },
+    #[error("must be given at most {max_length} times, but is given {length}")]
+    TooManyItems { max_length: u64, length: u64 },
+    #[error("must be at least {minimum}, but is {value}")]
+    TooSmall { minimum: i64, value: Value },
+    #[error("must be at most {maximum}, but is {value}")]
+    TooLarge { maximum: i64, value: Value },
+    #[error("must be {expected}, but is {value}")]
+    NotConst { expected: Default, value: Value },
+    #[error("must be one of the allowed values, but is {value}")]
+    NotInEnum { value: Value },
+    #[error("must be a valid {format}, but is {value:?}")]
+    InvalidFormat { format: Format, value: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, encode, ErrorKind, Value};
+    use crate::parameter::{self, Type};
+    use crate::Parameter;
+    use std::collections::HashMap;
+
+    fn parameter(r#type: Type) -> Parameter {
+        Parameter {
+            r#type,
+            description: None,
+            default: None,
+            required: None,
+            min_length: None,
+            max_length: None,
+            minimum: None,
+            maximum: None,
+            r#const: None,
+            r#enum: None,
+        }
+    }
+
+    fn schema() -> HashMap<String, Parameter> {
+        HashMap::from([
+            (
+                "user".to_owned(),
+                Parameter {
+                    required: Some(true),
+                    min_length: Some(1),
+                    max_length: Some(16),
+                    ..parameter(Type::String(None))
+                },
+            ),
+            (
+                "limit".to_owned(),
+                Parameter {
+                    default: Some(parameter::Default::Integer(50)),
+                    minimum: Some(1),
+                    maximum: Some(100),
+                    ..parameter(Type::Integer)
+                },
+            ),
+            (
+                "ratio".to_owned(),
+                Parameter {
+                    minimum: Some(0),
+                    maximum: Some(1),
+                    ..parameter(Type::Number)
+                },
+            ),
+            ("reverse".to_owned(), parameter(Type::Boolean)),
+            (
+                "tags".to_owned(),
+                Parameter {
+                    max_length: Some(2),
+                    ..parameter(Type::Array(Box::new(Parameter {
+                        max_length: Some(8),
+                        ..parameter(Type::String(None))
+                    })))
+                },
+            ),
+        ])
+    }
+
+    fn values<const N: usize>(values: [(&str, Value); N]) -> HashMap<String, Value> {
+        values
+            .into_iter()
+            .map(|(name, value)| (name.to_owned(), value))
+            .collect()
+    }
+
+    fn tags(tags: &[&str]) -> Value {
+        Value::Array(
+            tags.iter()
+                .map(|tag| Value::String(tag.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_encode() {
+        let query = encode(
+            &schema(),
+            &values([
+                ("user", Value::String("alice & bob".to_owned())),
+                ("ratio", Value::Number(0.25)),
+                ("reverse", Value::Boolean(true)),
+                ("tags", tags(&["a", "b c"])),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(
+            query,
+            "ratio=0.25&reverse=true&tags=a&tags=b+c&user=alice+%26+bob"
+        );
+    }
+
+    #[test]
+    fn test_encode_errors() {
+        let err = encode(&schema(), &values([("ratio", Value::Number(0.5))])).unwrap_err();
+        assert_eq!(err.parameter(), "user");
+        assert_eq!(err.kind(), &ErrorKind::Missing);
+
+        let err = encode(
+            &schema(),
+            &values([
+                ("user", Value::String("alice".to_owned())),
+                ("limit", Value::Number(10.0)),
+            ]),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "parameter `limit` must be of type integer, not number"
+        );
+
+        let err = encode(
+            &schema(),
+            &values([
+                ("user", Value::String("alice".to_owned())),
+                ("tags", tags(&["a", "b", "c"])),
+            ]),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "parameter `tags` must be given at most 2 times, but is given 3"
+        );
+
+        let err = encode(
+            &schema(),
+            &values([
+                ("user", Value::String("alice".to_owned())),
+                ("cursor", Value::String("abc".to_owned())),
+            ]),
+        )
+        .unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::Unknown);
+    }
+
+    #[test]
+    fn test_decode() {
+        let decoded = decode(&schema(), "user=alice+%26+bob&ratio=0.5&cursor=abc").unwrap();
+        assert_eq!(
+            decoded,
+            values([
+                ("user", Value::String("alice & bob".to_owned())),
+                ("limit", Value::Integer(50)),
+                ("ratio", Value::Number(0.5)),
+            ])
+        );
+
+        let schema = schema();
+        let values = values([
+            ("user", Value::String("alice".to_owned())),
+            ("limit", Value::Integer(10)),
+            ("reverse", Value::Boolean(false)),
+            ("tags", tags(&["a", "b"])),
+        ]);
+        assert_eq!(
+            decode(&schema, &encode(&schema, &values).unwrap()).unwrap(),
+            values
+        );
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let err = decode(&schema(), "limit=10").unwrap_err();
+        assert_eq!(err.to_string(), "parameter `user` is required");
+
+        let err = decode(&schema(), "user=alice&limit=101").unwrap_err();
+        assert_eq!(err.parameter(), "limit");
+        assert_eq!(
+            err.kind(),
+            &ErrorKind::TooLarge {
+                maximum: 100,
+                value: Value::Integer(101)
+            }
+        );
+
+        let err = decode(&schema(), "user=alice&ratio=-0.5").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "parameter `ratio` must be at least 0, but is -0.5"
+        );
+
+        let err = decode(&schema(), "user=alice&limit=1.5").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "parameter `limit` must be of type integer, but is `1.5`"
+        );
+
+        let err = decode(&schema(), "user=").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "parameter `user` must be at least 1 characters long, but is 0"
+        );
+
+        let err = decode(&schema(), "user=alice&reverse=yes").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "parameter `reverse` must be of type boolean, but is `yes`"
+        );
+
+        let err = decode(&schema(), "user=alice&tags=much+too+long").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "parameter `tags` must be at most 8 characters long, but is 13"
+        );
+
+        let err = decode(&schema(), "user=alice&user=bob").unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::Repeated);
+    }
+
+    #[test]
+    fn test_format_const_and_enum() {
+        let schema = HashMap::from([
+            (
+                "actor".to_owned(),
+                parameter(Type::String(Some(parameter::Format::Did))),
+            ),
+            (
+                "sort".to_owned(),
+                Parameter {
+                    r#enum: Some(vec![
+                        parameter::Default::String("new".to_owned()),
+                        parameter::Default::String("top".to_owned()),
+                    ]),
+                    ..parameter(Type::String(None))
+                },
+            ),
+            (
+                "version".to_owned(),
+                Parameter {
+                    r#const: Some(parameter::Default::Integer(1)),
+                    ..parameter(Type::Integer)
+                },
+            ),
+        ]);
+        assert_eq!(
+            decode(&schema, "actor=did:plc:1234&sort=top&version=1").unwrap(),
+            values([
+                ("actor", Value::String("did:plc:1234".to_owned())),
+                ("sort", Value::String("top".to_owned())),
+                ("version", Value::Integer(1)),
+            ])
+        );
+
+        let err = decode(&schema, "actor=notadid").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "parameter `actor` must be a valid did, but is \"notadid\""
+        );
+
+        let err = decode(&schema, "sort=bogus").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "parameter `sort` must be one of the allowed values, but is bogus"
+        );
+
+        let err = encode(&schema, &values([("version", Value::Integer(2))])).unwrap_err();
+        assert_eq!(err.to_string(), "parameter `version` must be 1, but is 2");
+    }
+
+    #[test]
+    fn test_unsupported_type() {
+        let schema = HashMap::from([("blob".to_owned(), parameter(Type::Blob))]);
+        let err = decode(&schema, "blob=abc").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "parameter `blob` is of type blob, which can't be sent in a query"
+        );
+    }
+}
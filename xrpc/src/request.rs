@@ -1,21 +1,118 @@
+use crate::Nsid;
+use bytes::Bytes;
+
+/// Whether a method reads (`Query`, sent as a `GET`) or changes (`Procedure`, sent as a `POST`)
+/// state on the server
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Type {
     Query,
     Procedure,
 }
 
+/// A call to an XRPC method, ready to be sent to `host`.
+///
+/// With the `http` feature, it can be converted into an `http::Request<Bytes>`.
+#[derive(Debug, Clone)]
 pub struct XrpcRequest {
-    _type: Type,
+    r#type: Type,
+    nsid: Nsid,
+    host: String,
+    parameters: Vec<(String, String)>,
+    headers: Vec<(String, String)>,
+    body: Option<Bytes>,
+    encoding: Option<String>,
 }
 
 impl XrpcRequest {
     pub fn builder() -> Builder {
         Builder::default()
     }
+
+    pub fn r#type(&self) -> Type {
+        self.r#type
+    }
+
+    pub fn nsid(&self) -> &Nsid {
+        &self.nsid
+    }
+
+    /// The scheme and authority of the server, e.g. `https://bsky.social`
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// Send the request to a different server
+    #[cfg(feature = "client")]
+    pub(crate) fn set_host(&mut self, host: &str) {
+        self.host = host.trim_end_matches('/').to_owned();
+    }
+
+    pub fn parameters(&self) -> &[(String, String)] {
+        &self.parameters
+    }
+
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+
+    pub fn body(&self) -> Option<&Bytes> {
+        self.body.as_ref()
+    }
+
+    /// The MIME type of the body
+    pub fn encoding(&self) -> Option<&str> {
+        self.encoding.as_deref()
+    }
+
+    /// `/xrpc/<nsid>`, followed by the URL encoded query parameters if there are any
+    pub fn path_and_query(&self) -> String {
+        let path = format!("/xrpc/{}", self.nsid);
+        if self.parameters.is_empty() {
+            return path;
+        }
+
+        let query = form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(&self.parameters)
+            .finish();
+        format!("{path}?{query}")
+    }
+
+    /// The full URI to send the request to
+    pub fn uri(&self) -> String {
+        format!("{}{}", self.host, self.path_and_query())
+    }
+}
+
+#[cfg(feature = "http")]
+impl TryFrom<XrpcRequest> for http::Request<Bytes> {
+    type Error = Error;
+
+    fn try_from(request: XrpcRequest) -> Result<Self, Self::Error> {
+        let method = match request.r#type {
+            Type::Query => http::Method::GET,
+            Type::Procedure => http::Method::POST,
+        };
+        let mut builder = http::Request::builder().method(method).uri(request.uri());
+        for (name, value) in &request.headers {
+            builder = builder.header(name, value);
+        }
+        if let Some(encoding) = &request.encoding {
+            builder = builder.header(http::header::CONTENT_TYPE, encoding);
+        }
+
+        Ok(builder.body(request.body.unwrap_or_default())?)
+    }
 }
 
 #[derive(Default)]
 pub struct Builder {
     r#type: Option<Type>,
+    nsid: Option<Nsid>,
+    host: Option<String>,
+    parameters: Vec<(String, String)>,
+    headers: Vec<(String, String)>,
+    body: Option<Bytes>,
+    encoding: Option<String>,
 }
 
 impl Builder {
@@ -29,10 +126,91 @@ impl Builder {
         self
     }
 
+    pub fn nsid(mut self, nsid: Nsid) -> Self {
+        self.nsid = Some(nsid);
+        self
+    }
+
+    pub fn set_nsid(&mut self, nsid: Option<Nsid>) -> &mut Self {
+        self.nsid = nsid;
+        self
+    }
+
+    /// The scheme and authority of the server, e.g. `https://bsky.social`
+    pub fn host(mut self, host: impl Into<String>) -> Self {
+        self.host = Some(host.into());
+        self
+    }
+
+    pub fn set_host(&mut self, host: Option<String>) -> &mut Self {
+        self.host = host;
+        self
+    }
+
+    /// Add a query parameter. Parameters can be repeated to send a list.
+    pub fn parameter(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.parameters.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn set_parameters(&mut self, parameters: Vec<(String, String)>) -> &mut Self {
+        self.parameters = parameters;
+        self
+    }
+
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn set_headers(&mut self, headers: Vec<(String, String)>) -> &mut Self {
+        self.headers = headers;
+        self
+    }
+
+    /// The body of a procedure. It must be sent along with its `encoding`.
+    pub fn body(mut self, body: impl Into<Bytes>) -> Self {
+        self.body = Some(body.into());
+        self
+    }
+
+    pub fn set_body(&mut self, body: Option<Bytes>) -> &mut Self {
+        self.body = body;
+        self
+    }
+
+    /// The MIME type of the body, e.g. `application/json`
+    pub fn encoding(mut self, encoding: impl Into<String>) -> Self {
+        self.encoding = Some(encoding.into());
+        self
+    }
+
+    pub fn set_encoding(&mut self, encoding: Option<String>) -> &mut Self {
+        self.encoding = encoding;
+        self
+    }
+
     pub fn build(self) -> Result<XrpcRequest, Error> {
         let r#type = self.r#type.ok_or(Error::MissingField("type"))?;
+        let nsid = self.nsid.ok_or(Error::MissingField("nsid"))?;
+        let host = self.host.ok_or(Error::MissingField("host"))?;
+        match (r#type, &self.body, &self.encoding) {
+            (Type::Query, Some(_), _) | (Type::Query, _, Some(_)) => {
+                return Err(Error::QueryWithBody(nsid))
+            }
+            (Type::Procedure, Some(_), None) => return Err(Error::MissingField("encoding")),
+            _ => (),
+        }
 
-        Ok(XrpcRequest { _type: r#type })
+        Ok(XrpcRequest {
+            r#type,
+            nsid,
+            host: host.trim_end_matches('/').to_owned(),
+            parameters: self.parameters,
+            headers: self.headers,
+            body: self.body,
+            encoding: self.encoding,
+        })
     }
 }
 
@@ -40,4 +218,147 @@ impl Builder {
 pub enum Error {
     #[error("missing field `{0}`")]
     MissingField(&'static str),
+    #[error("`{0}` is a query, so it can't be sent with a body")]
+    QueryWithBody(Nsid),
+    #[cfg(feature = "http")]
+    #[error("couldn't build an HTTP request")]
+    Http(#[from] http::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Error, Type, XrpcRequest};
+    use crate::Nsid;
+
+    fn nsid(nsid: &str) -> Nsid {
+        Nsid::new(nsid).unwrap()
+    }
+
+    #[test]
+    fn test_path_and_query() {
+        let request = XrpcRequest::builder()
+            .r#type(Type::Query)
+            .nsid(nsid("app.bsky.getProfile"))
+            .host("https://bsky.social/")
+            .parameter("user", "alice.test")
+            .parameter("tags", "a b")
+            .parameter("tags", "c&d")
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            request.path_and_query(),
+            "/xrpc/app.bsky.getProfile?user=alice.test&tags=a+b&tags=c%26d"
+        );
+        assert_eq!(
+            request.uri(),
+            "https://bsky.social/xrpc/app.bsky.getProfile?user=alice.test&tags=a+b&tags=c%26d"
+        );
+
+        let request = XrpcRequest::builder()
+            .r#type(Type::Procedure)
+            .nsid(nsid("com.atproto.createAccount"))
+            .host("http://localhost:2583")
+            .build()
+            .unwrap();
+        assert_eq!(
+            request.uri(),
+            "http://localhost:2583/xrpc/com.atproto.createAccount"
+        );
+    }
+
+    #[test]
+    fn test_build_errors() {
+        let err = XrpcRequest::builder()
+            .r#type(Type::Query)
+            .host("https://bsky.social")
+            .build()
+            .unwrap_err();
+        assert!(matches!(err, Error::MissingField("nsid")));
+
+        let err = XrpcRequest::builder()
+            .r#type(Type::Query)
+            .nsid(nsid("app.bsky.getProfile"))
+            .host("https://bsky.social")
+            .body("{}")
+            .encoding("application/json")
+            .build()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "`app.bsky.getProfile` is a query, so it can't be sent with a body"
+        );
+
+        let err = XrpcRequest::builder()
+            .r#type(Type::Procedure)
+            .nsid(nsid("com.atproto.createAccount"))
+            .host("https://bsky.social")
+            .body("{}")
+            .build()
+            .unwrap_err();
+        assert!(matches!(err, Error::MissingField("encoding")));
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_into_http_request() {
+        let request: http::Request<bytes::Bytes> = XrpcRequest::builder()
+            .r#type(Type::Query)
+            .nsid(nsid("app.bsky.getProfile"))
+            .host("https://bsky.social")
+            .parameter("user", "alice.test")
+            .header("authorization", "Bearer token")
+            .build()
+            .unwrap()
+            .try_into()
+            .unwrap();
+
+        assert_eq!(request.method(), http::Method::GET);
+        assert_eq!(
+            request.uri(),
+            "https://bsky.social/xrpc/app.bsky.getProfile?user=alice.test"
+        );
+        assert_eq!(request.headers()["authorization"], "Bearer token");
+        assert!(request.headers().get(http::header::CONTENT_TYPE).is_none());
+        assert!(request.body().is_empty());
+
+        let request: http::Request<bytes::Bytes> = XrpcRequest::builder()
+            .r#type(Type::Procedure)
+            .nsid(nsid("com.atproto.createAccount"))
+            .host("https://bsky.social")
+            .body(r#"{"username":"alice.test"}"#)
+            .encoding("application/json")
+            .build()
+            .unwrap()
+            .try_into()
+            .unwrap();
+
+        assert_eq!(request.method(), http::Method::POST);
+        assert_eq!(
+            request.uri(),
+            "https://bsky.social/xrpc/com.atproto.createAccount"
+        );
+        assert_eq!(
+            request.headers()[http::header::CONTENT_TYPE],
+            "application/json"
+        );
+        assert_eq!(request.body().as_ref(), br#"{"username":"alice.test"}"#);
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_invalid_header() {
+        let request = XrpcRequest::builder()
+            .r#type(Type::Query)
+            .nsid(nsid("app.bsky.getProfile"))
+            .host("https://bsky.social")
+            .header("bad header", "value")
+            .build()
+            .unwrap();
+        let err = http::Request::<bytes::Bytes>::try_from(request).unwrap_err();
+        assert!(matches!(err, Error::Http(_)));
+        // The HTTP error is the source, so it isn't repeated in the message
+        assert_eq!(err.to_string(), "couldn't build an HTTP request");
+        assert!(std::error::Error::source(&err).is_some());
+    }
 }
@@ -1 +1,459 @@
+use crate::XrpcError;
+use bytes::Bytes;
+use std::fmt;
 
+#[cfg(feature = "serde_json")]
+use serde_json::Value;
+
+/// The status codes that XRPC servers respond with, and the error names that go with them.
+///
+/// https://atproto.com/specs/xrpc#response-types
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ResponseType {
+    Success,
+    InvalidRequest,
+    AuthenticationRequired,
+    Forbidden,
+    XrpcNotSupported,
+    PayloadTooLarge,
+    RateLimitExceeded,
+    InternalServerError,
+    MethodNotImplemented,
+    UpstreamFailure,
+    NotEnoughResources,
+    UpstreamTimeout,
+    /// A status code that the spec doesn't give a meaning to
+    Unknown(u16),
+}
+
+impl ResponseType {
+    pub fn from_status(status: u16) -> Self {
+        match status {
+            200 => Self::Success,
+            400 => Self::InvalidRequest,
+            401 => Self::AuthenticationRequired,
+            403 => Self::Forbidden,
+            404 => Self::XrpcNotSupported,
+            413 => Self::PayloadTooLarge,
+            429 => Self::RateLimitExceeded,
+            500 => Self::InternalServerError,
+            501 => Self::MethodNotImplemented,
+            502 => Self::UpstreamFailure,
+            503 => Self::NotEnoughResources,
+            504 => Self::UpstreamTimeout,
+            status => Self::Unknown(status),
+        }
+    }
+
+    /// The response type with this error name, if it's one of the standard ones
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::STANDARD
+            .into_iter()
+            .find(|response_type| response_type.name() == Some(name))
+    }
+
+    pub fn status(&self) -> u16 {
+        match self {
+            Self::Success => 200,
+            Self::InvalidRequest => 400,
+            Self::AuthenticationRequired => 401,
+            Self::Forbidden => 403,
+            Self::XrpcNotSupported => 404,
+            Self::PayloadTooLarge => 413,
+            Self::RateLimitExceeded => 429,
+            Self::InternalServerError => 500,
+            Self::MethodNotImplemented => 501,
+            Self::UpstreamFailure => 502,
+            Self::NotEnoughResources => 503,
+            Self::UpstreamTimeout => 504,
+            Self::Unknown(status) => *status,
+        }
+    }
+
+    /// The name used for this response type in an error envelope
+    pub fn name(&self) -> Option<&'static str> {
+        match self {
+            Self::Success => Some("Success"),
+            Self::InvalidRequest => Some("InvalidRequest"),
+            Self::AuthenticationRequired => Some("AuthenticationRequired"),
+            Self::Forbidden => Some("Forbidden"),
+            Self::XrpcNotSupported => Some("XRPCNotSupported"),
+            Self::PayloadTooLarge => Some("PayloadTooLarge"),
+            Self::RateLimitExceeded => Some("RateLimitExceeded"),
+            Self::InternalServerError => Some("InternalServerError"),
+            Self::MethodNotImplemented => Some("MethodNotImplemented"),
+            Self::UpstreamFailure => Some("UpstreamFailure"),
+            Self::NotEnoughResources => Some("NotEnoughResources"),
+            Self::UpstreamTimeout => Some("UpstreamTimeout"),
+            Self::Unknown(_) => None,
+        }
+    }
+
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status())
+    }
+
+    /// Whether the same request might succeed if it's sent again later
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            Self::RateLimitExceeded
+                | Self::InternalServerError
+                | Self::UpstreamFailure
+                | Self::NotEnoughResources
+                | Self::UpstreamTimeout
+        )
+    }
+
+    const STANDARD: [Self; 12] = [
+        Self::Success,
+        Self::InvalidRequest,
+        Self::AuthenticationRequired,
+        Self::Forbidden,
+        Self::XrpcNotSupported,
+        Self::PayloadTooLarge,
+        Self::RateLimitExceeded,
+        Self::InternalServerError,
+        Self::MethodNotImplemented,
+        Self::UpstreamFailure,
+        Self::NotEnoughResources,
+        Self::UpstreamTimeout,
+    ];
+}
+
+impl fmt::Display for ResponseType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "{} {}", self.status(), name),
+            None => write!(f, "{}", self.status()),
+        }
+    }
+}
+
+/// A response body, decoded according to its content type
+#[derive(Debug, Clone, PartialEq)]
+pub enum Body {
+    /// An `application/json` body
+    #[cfg(feature = "serde_json")]
+    Json(Value),
+    /// A `text/*` body. Without the `serde_json` feature, JSON bodies are text too.
+    Text(String),
+    /// A body of any other type
+    Bytes(Bytes),
+    Empty,
+}
+
+impl Body {
+    fn decode(encoding: Option<&str>, body: Bytes) -> Result<Self, Error> {
+        let Some(encoding) = encoding else {
+            return Ok(if body.is_empty() {
+                Self::Empty
+            } else {
+                Self::Bytes(body)
+            });
+        };
+
+        let essence = essence(encoding);
+        let decode_error = |message: String| Error::Decode {
+            encoding: encoding.to_owned(),
+            message,
+        };
+        #[cfg(feature = "serde_json")]
+        if essence == "application/json" {
+            return serde_json::from_slice(&body)
+                .map(Self::Json)
+                .map_err(|e| decode_error(e.to_string()));
+        }
+        if essence.starts_with("text/") || essence == "application/json" {
+            return String::from_utf8(body.to_vec())
+                .map(Self::Text)
+                .map_err(|e| decode_error(e.to_string()));
+        }
+
+        Ok(Self::Bytes(body))
+    }
+}
+
+/// The MIME type without any parameters, e.g. `text/plain` for `text/plain; charset=utf-8`
+fn essence(encoding: &str) -> String {
+    encoding
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
+}
+
+/// A successful response from an XRPC server
+#[derive(Debug, Clone, PartialEq)]
+pub struct XrpcResponse {
+    r#type: ResponseType,
+    encoding: Option<String>,
+    body: Body,
+}
+
+impl XrpcResponse {
+    /// Check the status of a response and decode its body.
+    ///
+    /// A response with a status other than 2xx is returned as an error, with its error envelope if
+    /// it has one.
+    pub fn new(status: u16, encoding: Option<&str>, body: Bytes) -> Result<Self, Error> {
+        let r#type = ResponseType::from_status(status);
+        if !r#type.is_success() {
+            return Err(Error::from_envelope(r#type, encoding, &body));
+        }
+
+        Ok(Self {
+            r#type,
+            encoding: encoding.map(ToOwned::to_owned),
+            body: Body::decode(encoding, body)?,
+        })
+    }
+
+    pub fn r#type(&self) -> ResponseType {
+        self.r#type
+    }
+
+    /// The MIME type of the body
+    pub fn encoding(&self) -> Option<&str> {
+        self.encoding.as_deref()
+    }
+
+    pub fn body(&self) -> &Body {
+        &self.body
+    }
+
+    pub fn into_body(self) -> Body {
+        self.body
+    }
+}
+
+#[cfg(feature = "http")]
+impl TryFrom<http::Response<Bytes>> for XrpcResponse {
+    type Error = Error;
+
+    fn try_from(response: http::Response<Bytes>) -> Result<Self, Self::Error> {
+        let (parts, body) = response.into_parts();
+        let encoding = parts
+            .headers
+            .get(http::header::CONTENT_TYPE)
+            .and_then(|encoding| encoding.to_str().ok());
+
+        Self::new(parts.status.as_u16(), encoding, body)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The request couldn't be sent, or no response came back
+    #[error("couldn't send the request")]
+    Transport(#[source] Box<dyn std::error::Error + Send + Sync>),
+    /// The server responded with a status other than 2xx, and either no error envelope or one with
+    /// a standard error name
+    #[error("the server responded with {status}{}", .error.as_ref().map(|e| format!(": {e}")).unwrap_or_default())]
+    Http {
+        status: ResponseType,
+        error: Option<XrpcError>,
+    },
+    /// The server responded with an error that isn't a standard one, so it's declared by the
+    /// method's lexicon
+    #[error("the server responded with {status}: {error}")]
+    Lexicon {
+        status: ResponseType,
+        error: XrpcError,
+    },
+    #[error("couldn't decode a `{encoding}` response body: {message}")]
+    Decode { encoding: String, message: String },
+}
+
+impl Error {
+    /// Whether the same request might succeed if it's sent again later
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Transport(_) => true,
+            Self::Http { status, .. } => status.is_retryable(),
+            Self::Lexicon { .. } | Self::Decode { .. } => false,
+        }
+    }
+
+    fn from_envelope(status: ResponseType, encoding: Option<&str>, body: &[u8]) -> Self {
+        match XrpcError::from_envelope(encoding, body) {
+            Some(error) if ResponseType::from_name(&error.name).is_none() => {
+                Self::Lexicon { status, error }
+            }
+            error => Self::Http { status, error },
+        }
+    }
+}
+
+impl XrpcError {
+    /// Parse an `{ "error": ..., "message": ... }` envelope
+    #[cfg(feature = "serde_json")]
+    fn from_envelope(encoding: Option<&str>, body: &[u8]) -> Option<Self> {
+        if essence(encoding?) != "application/json" {
+            return None;
+        }
+
+        let envelope: Value = serde_json::from_slice(body).ok()?;
+        Some(Self {
+            name: envelope.get("error")?.as_str()?.to_owned(),
+            description: envelope
+                .get("message")
+                .and_then(Value::as_str)
+                .map(ToOwned::to_owned),
+        })
+    }
+
+    #[cfg(not(feature = "serde_json"))]
+    fn from_envelope(_encoding: Option<&str>, _body: &[u8]) -> Option<Self> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Body, Error, ResponseType, XrpcResponse};
+    use bytes::Bytes;
+
+    #[test]
+    fn test_response_type() {
+        for status in [200, 400, 401, 403, 404, 413, 429, 500, 501, 502, 503, 504] {
+            let response_type = ResponseType::from_status(status);
+            assert_eq!(response_type.status(), status);
+            assert_eq!(
+                ResponseType::from_name(response_type.name().unwrap()),
+                Some(response_type)
+            );
+        }
+        assert_eq!(ResponseType::from_status(418), ResponseType::Unknown(418));
+        assert_eq!(ResponseType::from_name("RepoNotFound"), None);
+        assert_eq!(
+            ResponseType::XrpcNotSupported.to_string(),
+            "404 XRPCNotSupported"
+        );
+        assert!(ResponseType::from_status(204).is_success());
+    }
+
+    #[test]
+    fn test_body() {
+        let response =
+            XrpcResponse::new(200, Some("text/plain; charset=utf-8"), "hi".into()).unwrap();
+        assert_eq!(response.body(), &Body::Text("hi".to_owned()));
+
+        let response =
+            XrpcResponse::new(200, Some("application/cbor"), Bytes::from_static(&[0xa0])).unwrap();
+        assert_eq!(response.body(), &Body::Bytes(Bytes::from_static(&[0xa0])));
+
+        let response = XrpcResponse::new(200, None, Bytes::new()).unwrap();
+        assert_eq!(response.body(), &Body::Empty);
+
+        let err =
+            XrpcResponse::new(200, Some("text/plain"), Bytes::from_static(&[0xff])).unwrap_err();
+        assert!(matches!(err, Error::Decode { .. }));
+    }
+
+    #[cfg(feature = "serde_json")]
+    #[test]
+    fn test_json_body() {
+        let response = XrpcResponse::new(
+            200,
+            Some("application/json"),
+            r#"{"did":"did:plc:1234"}"#.into(),
+        )
+        .unwrap();
+        assert_eq!(
+            response.into_body(),
+            Body::Json(serde_json::json!({ "did": "did:plc:1234" }))
+        );
+
+        let err = XrpcResponse::new(200, Some("application/json"), "{".into()).unwrap_err();
+        assert!(!err.is_retryable());
+        assert!(err
+            .to_string()
+            .starts_with("couldn't decode a `application/json` response body"));
+    }
+
+    #[cfg(feature = "serde_json")]
+    #[test]
+    fn test_error_envelope() {
+        use crate::XrpcError;
+
+        let err = XrpcResponse::new(
+            400,
+            Some("application/json"),
+            r#"{"error":"RepoNotFound","message":"no repo for alice.test"}"#.into(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the server responded with 400 InvalidRequest: RepoNotFound: no repo for alice.test"
+        );
+        let Error::Lexicon { status, error } = err else {
+            panic!("expected a lexicon error, got {err:?}");
+        };
+        assert_eq!(status, ResponseType::InvalidRequest);
+        assert_eq!(
+            error,
+            XrpcError {
+                name: "RepoNotFound".to_owned(),
+                description: Some("no repo for alice.test".to_owned()),
+            }
+        );
+
+        let err = XrpcResponse::new(
+            429,
+            Some("application/json"),
+            r#"{"error":"RateLimitExceeded"}"#.into(),
+        )
+        .unwrap_err();
+        assert!(err.is_retryable());
+        assert!(matches!(
+            err,
+            Error::Http {
+                status: ResponseType::RateLimitExceeded,
+                error: Some(XrpcError {
+                    description: None,
+                    ..
+                }),
+            }
+        ));
+    }
+
+    #[test]
+    fn test_error_without_envelope() {
+        let err =
+            XrpcResponse::new(502, Some("text/html"), "<h1>Bad Gateway</h1>".into()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the server responded with 502 UpstreamFailure"
+        );
+        assert!(err.is_retryable());
+        assert!(matches!(err, Error::Http { error: None, .. }));
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_from_http_response() {
+        let response = http::Response::builder()
+            .status(200)
+            .header(http::header::CONTENT_TYPE, "text/plain")
+            .body(Bytes::from("ok"))
+            .unwrap();
+        let response = XrpcResponse::try_from(response).unwrap();
+        assert_eq!(response.encoding(), Some("text/plain"));
+        assert_eq!(response.body(), &Body::Text("ok".to_owned()));
+
+        let response = http::Response::builder()
+            .status(501)
+            .body(Bytes::new())
+            .unwrap();
+        let err = XrpcResponse::try_from(response).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Http {
+                status: ResponseType::MethodNotImplemented,
+                error: None,
+            }
+        ));
+    }
+}
@@ -0,0 +1,336 @@
+//! Serving XRPC methods with axum.
+
+use crate::request::Type;
+use crate::response::ResponseType;
+use crate::{Nsid, NsidNamespace, XrpcError};
+use axum::body::Body;
+use axum::extract::Path;
+use axum::handler::Handler;
+use axum::http::{Request, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{on, MethodFilter, MethodRouter};
+use axum::{Json, Router};
+use serde_json::json;
+use std::collections::BTreeMap;
+use tower::ServiceExt;
+
+/// Routes `/xrpc/<nsid>` to a handler for each method a server implements.
+///
+/// Queries are only routed for `GET` requests and procedures for `POST` requests; other methods
+/// are rejected with an `InvalidRequest` error envelope. Requests for NSIDs without a handler get
+/// a `MethodNotImplemented` error envelope, unless they're under a namespace with a handler.
+///
+/// Handlers are axum handlers, so they can use any of axum's extractors. Convert the router into
+/// an axum [`Router`] to serve it, or to merge it with other routes.
+#[derive(Default)]
+pub struct XrpcRouter {
+    routes: BTreeMap<Nsid, MethodRouter>,
+    namespaces: Vec<(NsidNamespace, MethodRouter)>,
+}
+
+impl XrpcRouter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Handle requests for `nsid`, which is a query or procedure depending on `type`.
+    ///
+    /// # Panics
+    ///
+    /// If `nsid` already has a handler.
+    #[track_caller]
+    pub fn route<H, T>(mut self, nsid: Nsid, r#type: Type, handler: H) -> Self
+    where
+        H: Handler<T>,
+        T: 'static,
+    {
+        let method = match r#type {
+            Type::Query => MethodFilter::GET,
+            Type::Procedure => MethodFilter::POST,
+        };
+        let wrong_method = {
+            let message = match r#type {
+                Type::Query => format!("`{nsid}` is a query, so it must be sent with GET"),
+                Type::Procedure => format!("`{nsid}` is a procedure, so it must be sent with POST"),
+            };
+            move || async move { ErrorResponse::standard(ResponseType::InvalidRequest, message) }
+        };
+        let method_router = on(method, handler).fallback(wrong_method.into_service());
+
+        if self.routes.contains_key(&nsid) {
+            panic!("`{nsid}` already has a handler");
+        }
+        self.routes.insert(nsid, method_router);
+        self
+    }
+
+    /// Handle requests for every NSID under `namespace` that doesn't have a handler of its own,
+    /// e.g. to proxy all of `app.bsky.*` to another server. The handler can get the NSID with
+    /// axum's `Path` extractor.
+    ///
+    /// Queries and procedures can both be under a namespace, so requests are routed for both `GET`
+    /// and `POST`. When namespaces are nested, the most specific one handles the request.
+    ///
+    /// # Panics
+    ///
+    /// If `namespace` already has a handler.
+    #[track_caller]
+    pub fn route_namespace<H, T>(mut self, namespace: NsidNamespace, handler: H) -> Self
+    where
+        H: Handler<T>,
+        T: 'static,
+    {
+        let wrong_method = {
+            let message = format!("methods under `{namespace}` must be sent with GET or POST");
+            move || async move { ErrorResponse::standard(ResponseType::InvalidRequest, message) }
+        };
+        let method_router = on(MethodFilter::GET | MethodFilter::POST, handler)
+            .fallback(wrong_method.into_service());
+
+        if self
+            .namespaces
+            .iter()
+            .any(|(existing, _)| *existing == namespace)
+        {
+            panic!("`{namespace}` already has a handler");
+        }
+        self.namespaces.push((namespace, method_router));
+        self
+    }
+}
+
+impl From<XrpcRouter> for Router {
+    fn from(xrpc_router: XrpcRouter) -> Self {
+        let namespaces = xrpc_router.namespaces;
+        let by_namespace = move |Path(nsid): Path<String>, request: Request<Body>| async move {
+            let method_router = Nsid::new(nsid).ok().and_then(|nsid| {
+                namespaces
+                    .iter()
+                    .filter(|(namespace, _)| namespace.matches(&nsid))
+                    .max_by_key(|(namespace, _)| namespace.authority_segments().len())
+                    .map(|(_, method_router)| method_router.clone())
+            });
+            let Some(method_router) = method_router else {
+                return ErrorResponse::standard(
+                    ResponseType::MethodNotImplemented,
+                    "the server doesn't implement this method",
+                )
+                .into_response();
+            };
+
+            match method_router.oneshot(request).await {
+                Ok(response) => response,
+                Err(infallible) => match infallible {},
+            }
+        };
+
+        xrpc_router
+            .routes
+            .into_iter()
+            .fold(Router::new(), |router, (nsid, method_router)| {
+                router.route(&format!("/xrpc/{nsid}"), method_router)
+            })
+            .route("/xrpc/:nsid", axum::routing::any(by_namespace))
+    }
+}
+
+/// An error for a handler to respond with, sent in an `{ "error": ..., "message": ... }` envelope
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorResponse {
+    pub status: ResponseType,
+    pub error: XrpcError,
+}
+
+impl ErrorResponse {
+    /// An error named after its response type, like `InvalidRequest`.
+    ///
+    /// A [`ResponseType::Unknown`] doesn't have a standard name, so it gets the generic one for
+    /// its class of status: `InvalidRequest` for a 4xx, otherwise `InternalServerError`. The
+    /// status itself is kept.
+    pub fn standard(status: ResponseType, message: impl Into<String>) -> Self {
+        let name = status.name().unwrap_or(match status.status() {
+            400..=499 => "InvalidRequest",
+            _ => "InternalServerError",
+        });
+
+        Self {
+            status,
+            error: XrpcError {
+                name: name.to_owned(),
+                description: Some(message.into()),
+            },
+        }
+    }
+}
+
+impl IntoResponse for ErrorResponse {
+    fn into_response(self) -> Response {
+        let status =
+            StatusCode::from_u16(self.status.status()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let mut envelope = json!({ "error": self.error.name });
+        if let Some(message) = self.error.description {
+            envelope["message"] = message.into();
+        }
+
+        (status, Json(envelope)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ErrorResponse, XrpcRouter};
+    use crate::request::Type;
+    use crate::response::ResponseType;
+    use crate::{Nsid, NsidNamespace, XrpcError};
+    use axum::body::Body;
+    use axum::extract::{Path, Query};
+    use axum::http::{Method, Request, StatusCode};
+    use axum::Router;
+    use std::collections::HashMap;
+    use tower::ServiceExt;
+
+    fn router() -> Router {
+        XrpcRouter::new()
+            .route_namespace(
+                NsidNamespace::new("io.example.*").unwrap(),
+                |Path(nsid): Path<String>| async move { format!("io.example.* handled {nsid}") },
+            )
+            .route_namespace(
+                NsidNamespace::new("io.example.admin.*").unwrap(),
+                || async { "io.example.admin.* handled it" },
+            )
+            .route(
+                Nsid::new("io.example.ping").unwrap(),
+                Type::Query,
+                |Query(params): Query<HashMap<String, String>>| async move {
+                    params.get("message").cloned().unwrap_or_default()
+                },
+            )
+            .route(
+                Nsid::new("io.example.createThing").unwrap(),
+                Type::Procedure,
+                || async {
+                    ErrorResponse {
+                        status: ResponseType::InvalidRequest,
+                        error: XrpcError {
+                            name: "ThingExists".to_owned(),
+                            description: None,
+                        },
+                    }
+                },
+            )
+            .into()
+    }
+
+    async fn send(method: Method, uri: &str) -> (StatusCode, String) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap();
+        let response = router().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_routes() {
+        assert_eq!(
+            send(Method::GET, "/xrpc/io.example.ping?message=hi").await,
+            (StatusCode::OK, "hi".to_owned())
+        );
+        assert_eq!(
+            send(Method::POST, "/xrpc/io.example.createThing").await,
+            (
+                StatusCode::BAD_REQUEST,
+                r#"{"error":"ThingExists"}"#.to_owned()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_wrong_method() {
+        assert_eq!(
+            send(Method::POST, "/xrpc/io.example.ping").await,
+            (
+                StatusCode::BAD_REQUEST,
+                r#"{"error":"InvalidRequest","message":"`io.example.ping` is a query, so it must be sent with GET"}"#.to_owned()
+            )
+        );
+        assert_eq!(
+            send(Method::GET, "/xrpc/io.example.createThing").await,
+            (
+                StatusCode::BAD_REQUEST,
+                r#"{"error":"InvalidRequest","message":"`io.example.createThing` is a procedure, so it must be sent with POST"}"#.to_owned()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_namespaces() {
+        assert_eq!(
+            send(Method::GET, "/xrpc/io.example.unknown").await,
+            (
+                StatusCode::OK,
+                "io.example.* handled io.example.unknown".to_owned()
+            )
+        );
+        assert_eq!(
+            send(Method::POST, "/xrpc/io.example.admin.deleteThing").await,
+            (StatusCode::OK, "io.example.admin.* handled it".to_owned())
+        );
+        assert_eq!(
+            send(Method::PUT, "/xrpc/io.example.unknown").await,
+            (
+                StatusCode::BAD_REQUEST,
+                r#"{"error":"InvalidRequest","message":"methods under `io.example.*` must be sent with GET or POST"}"#.to_owned()
+            )
+        );
+        // NSIDs with handlers of their own aren't routed by namespace
+        assert_eq!(
+            send(Method::GET, "/xrpc/io.example.ping?message=hi").await,
+            (StatusCode::OK, "hi".to_owned())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_not_implemented() {
+        assert_eq!(
+            send(Method::GET, "/xrpc/com.example.unknown").await,
+            (
+                StatusCode::NOT_IMPLEMENTED,
+                r#"{"error":"MethodNotImplemented","message":"the server doesn't implement this method"}"#.to_owned()
+            )
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "`io.example.ping` already has a handler")]
+    fn test_duplicate_route() {
+        let nsid = Nsid::new("io.example.ping").unwrap();
+        let _ = XrpcRouter::new()
+            .route(nsid.clone(), Type::Query, || async { "" })
+            .route(nsid, Type::Procedure, || async { "" });
+    }
+
+    #[test]
+    fn test_nonstandard_error() {
+        let err = ErrorResponse::standard(ResponseType::Unknown(418), "I'm a teapot");
+        assert_eq!(err.status.status(), 418);
+        assert_eq!(err.error.name, "InvalidRequest");
+
+        let err = ErrorResponse::standard(ResponseType::Unknown(599), "network timeout");
+        assert_eq!(err.error.name, "InternalServerError");
+    }
+
+    #[test]
+    #[should_panic(expected = "`io.example.*` already has a handler")]
+    fn test_duplicate_namespace() {
+        let namespace = NsidNamespace::new("io.example.*").unwrap();
+        let _ = XrpcRouter::new()
+            .route_namespace(namespace.clone(), || async { "" })
+            .route_namespace(namespace, || async { "" });
+    }
+}
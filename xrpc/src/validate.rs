@@ -0,0 +1,558 @@
+//! Validating JSON values, like records and procedure bodies, against the schemas in a lexicon.
+
+use crate::check::{self, Limit};
+use crate::parameter::{Default, Format, Type};
+use crate::Parameter;
+use serde_json::{Map, Number, Value};
+use std::fmt;
+
+impl Parameter {
+    /// Check that `value` fits this schema, reporting every way in which it doesn't.
+    ///
+    /// References to other definitions, in `ref`s and the members of unions, aren't followed since
+    /// they can only be resolved along with the rest of the lexicon.
+    pub fn validate(&self, value: &Value) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors(Vec::new());
+        errors.parameter(self, value, "");
+
+        if errors.0.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Every way in which a value doesn't fit a schema
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationErrors(Vec<ValidationError>);
+
+impl ValidationErrors {
+    pub fn iter(&self) -> impl Iterator<Item = &ValidationError> {
+        self.0.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn push(&mut self, path: &str, kind: ValidationErrorKind) {
+        self.0.push(ValidationError {
+            path: path.to_owned(),
+            kind,
+        });
+    }
+
+    fn parameter(&mut self, parameter: &Parameter, value: &Value, path: &str) {
+        match (&parameter.r#type, value) {
+            (Type::String(format), Value::String(s)) => {
+                self.length(parameter, s.chars().count(), path);
+                match format {
+                    Some(format) if !check::is_valid(*format, s) => self.push(
+                        path,
+                        ValidationErrorKind::InvalidFormat {
+                            format: *format,
+                            value: s.clone(),
+                        },
+                    ),
+                    _ => (),
+                }
+            }
+            (Type::Number, Value::Number(n)) => self.range(parameter, n, path),
+            (Type::Integer, Value::Number(n)) if !n.is_f64() => self.range(parameter, n, path),
+            (Type::Boolean, Value::Bool(_)) | (Type::Unknown, Value::Object(_)) => (),
+            (Type::Bytes, Value::Object(object)) => self.bytes(parameter, object, path),
+            (Type::CidLink, Value::Object(object)) => self.link(object, path),
+            (Type::Blob, Value::Object(object)) => self.blob(object, path),
+            (Type::Array(items), Value::Array(values)) => {
+                self.length(parameter, values.len(), path);
+                for (i, value) in values.iter().enumerate() {
+                    self.parameter(items, value, &child(path, &i.to_string()));
+                }
+            }
+            (
+                Type::Object {
+                    properties,
+                    required,
+                },
+                Value::Object(object),
+            ) => {
+                let mut names: Vec<_> = properties.keys().chain(required).collect();
+                names.sort();
+                names.dedup();
+                for name in names {
+                    let path = child(path, name);
+                    match (object.get(name), properties.get(name)) {
+                        (Some(value), Some(property)) => self.parameter(property, value, &path),
+                        (Some(_), None) => (),
+                        (None, property) => {
+                            if required.contains(name)
+                                || property.and_then(|p| p.required).unwrap_or(false)
+                            {
+                                self.push(&path, ValidationErrorKind::Missing);
+                            }
+                        }
+                    }
+                }
+            }
+            (Type::Ref(_), _) => (),
+            (Type::Union { refs, closed }, Value::Object(object)) => {
+                if let Some(r#type) = self.string(object, "$type", path) {
+                    if *closed && !in_union(refs, r#type) {
+                        self.push(
+                            &child(path, "$type"),
+                            ValidationErrorKind::NotInUnion {
+                                r#type: r#type.to_owned(),
+                            },
+                        );
+                    }
+                }
+            }
+            (r#type, value) => {
+                return self.push(
+                    path,
+                    ValidationErrorKind::WrongType {
+                        expected: r#type.as_str(),
+                        found: json_type(value),
+                    },
+                );
+            }
+        }
+
+        for limit in check::allowed(parameter, |expected| equals(expected, value)) {
+            let kind = match limit {
+                Limit::Const(expected) => ValidationErrorKind::NotConst {
+                    expected: to_json(expected),
+                    value: value.clone(),
+                },
+                _ => ValidationErrorKind::NotInEnum {
+                    value: value.clone(),
+                },
+            };
+            self.push(path, kind);
+        }
+    }
+
+    /// Check the length of a string, array or bytes
+    fn length(&mut self, parameter: &Parameter, length: usize, path: &str) {
+        let kind = match check::length(parameter, length) {
+            Some(Limit::MinLength(min_length)) => ValidationErrorKind::TooShort {
+                min_length,
+                length: length as u64,
+            },
+            Some(Limit::MaxLength(max_length)) => ValidationErrorKind::TooLong {
+                max_length,
+                length: length as u64,
+            },
+            _ => return,
+        };
+        self.push(path, kind);
+    }
+
+    fn range(&mut self, parameter: &Parameter, n: &Number, path: &str) {
+        let compare = |bound: i64| match n.as_i64() {
+            Some(i) => Some(i.cmp(&bound)),
+            None => n.as_f64()?.partial_cmp(&(bound as f64)),
+        };
+        let kind = match check::range(parameter, compare) {
+            Some(Limit::Minimum(minimum)) => ValidationErrorKind::TooSmall {
+                minimum,
+                value: n.clone(),
+            },
+            Some(Limit::Maximum(maximum)) => ValidationErrorKind::TooLarge {
+                maximum,
+                value: n.clone(),
+            },
+            _ => return,
+        };
+        self.push(path, kind);
+    }
+
+    /// Bytes are base64 encoded, as `{ "$bytes": "..." }`
+    fn bytes(&mut self, parameter: &Parameter, object: &Map<String, Value>, path: &str) {
+        let Some(bytes) = self.string(object, "$bytes", path) else {
+            return;
+        };
+        let unpadded = bytes.trim_end_matches('=');
+        if unpadded
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'+' || b == b'/')
+        {
+            self.length(parameter, unpadded.len() * 3 / 4, path);
+        } else {
+            self.push(&child(path, "$bytes"), ValidationErrorKind::InvalidBytes);
+        }
+    }
+
+    /// Links are CIDs, as `{ "$link": "..." }`
+    fn link(&mut self, object: &Map<String, Value>, path: &str) {
+        match self.string(object, "$link", path) {
+            Some(cid) if !check::is_valid(Format::Cid, cid) => self.push(
+                &child(path, "$link"),
+                ValidationErrorKind::InvalidFormat {
+                    format: Format::Cid,
+                    value: cid.to_owned(),
+                },
+            ),
+            _ => (),
+        }
+    }
+
+    /// Blobs are `{ "$type": "blob", "ref": { "$link": "..." }, "mimeType": "...", "size": ... }`
+    fn blob(&mut self, object: &Map<String, Value>, path: &str) {
+        match self.string(object, "$type", path) {
+            Some(r#type) if r#type != "blob" => self.push(
+                &child(path, "$type"),
+                ValidationErrorKind::NotConst {
+                    expected: "blob".into(),
+                    value: r#type.into(),
+                },
+            ),
+            _ => (),
+        }
+        match object.get("ref") {
+            Some(Value::Object(link)) => self.link(link, &child(path, "ref")),
+            Some(value) => self.push(
+                &child(path, "ref"),
+                ValidationErrorKind::WrongType {
+                    expected: Type::CidLink.as_str(),
+                    found: json_type(value),
+                },
+            ),
+            None => self.push(&child(path, "ref"), ValidationErrorKind::Missing),
+        }
+        self.string(object, "mimeType", path);
+        match object.get("size") {
+            Some(Value::Number(size)) if !size.is_f64() => (),
+            Some(value) => self.push(
+                &child(path, "size"),
+                ValidationErrorKind::WrongType {
+                    expected: Type::Integer.as_str(),
+                    found: json_type(value),
+                },
+            ),
+            None => self.push(&child(path, "size"), ValidationErrorKind::Missing),
+        }
+    }
+
+    /// Get a string field of an object, reporting it if it's missing or not a string
+    fn string<'v>(
+        &mut self,
+        object: &'v Map<String, Value>,
+        name: &str,
+        path: &str,
+    ) -> Option<&'v str> {
+        match object.get(name) {
+            Some(Value::String(s)) => Some(s),
+            Some(value) => {
+                self.push(
+                    &child(path, name),
+                    ValidationErrorKind::WrongType {
+                        expected: "string",
+                        found: json_type(value),
+                    },
+                );
+                None
+            }
+            None => {
+                self.push(&child(path, name), ValidationErrorKind::Missing);
+                None
+            }
+        }
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let errors: Vec<_> = self.0.iter().map(ToString::to_string).collect();
+        write!(f, "{}", errors.join("; "))
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+impl IntoIterator for ValidationErrors {
+    type Item = ValidationError;
+    type IntoIter = std::vec::IntoIter<ValidationError>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+/// A way in which part of a value doesn't fit its schema
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationError {
+    path: String,
+    kind: ValidationErrorKind,
+}
+
+impl ValidationError {
+    /// A JSON pointer to the part of the value that doesn't fit, e.g. `/embed/images/0/alt`. It's
+    /// empty for the whole value.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn kind(&self) -> &ValidationErrorKind {
+        &self.kind
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "value {}", self.kind)
+        } else {
+            write!(f, "`{}` {}", self.path, self.kind)
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[non_exhaustive]
+pub enum ValidationErrorKind {
+    #[error("is required")]
+    Missing,
+    #[error("must be of type {expected}, not {found}")]
+    WrongType {
+        expected: &'static str,
+        found: &'static str,
+    },
+    #[error("must have a length of at least {min_length}, but has {length}")]
+    TooShort { min_length: u64, length: u64 },
+    #[error("must have a length of at most {max_length}, but has {length}")]
+    TooLong { max_length: u64, length: u64 },
+    #[error("must be at least {minimum}, but is {value}")]
+    TooSmall { minimum: i64, value: Number },
+    #[error("must be at most {maximum}, but is {value}")]
+    TooLarge { maximum: i64, value: Number },
+    #[error("must be {expected}, but is {value}")]
+    NotConst { expected: Value, value: Value },
+    #[error("must be one of the allowed values, but is {value}")]
+    NotInEnum { value: Value },
+    #[error("must be a valid {format}, but is {value:?}")]
+    InvalidFormat { format: Format, value: String },
+    #[error("must be base64 encoded")]
+    InvalidBytes,
+    #[error("is {:?}, which isn't one of the union's types", .r#type)]
+    NotInUnion { r#type: String },
+}
+
+/// A JSON pointer to `key` within the value at `path`
+fn child(path: &str, key: &str) -> String {
+    format!("{path}/{}", key.replace('~', "~0").replace('/', "~1"))
+}
+
+fn json_type(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn equals(expected: &Default, value: &Value) -> bool {
+    match expected {
+        Default::String(s) => value.as_str() == Some(s),
+        Default::Boolean(b) => value.as_bool() == Some(*b),
+        Default::Integer(i) => value.as_i64() == Some(*i),
+        Default::Number(n) => value.as_f64() == Some(*n),
+    }
+}
+
+fn to_json(value: &Default) -> Value {
+    match value {
+        Default::String(s) => s.as_str().into(),
+        Default::Boolean(b) => (*b).into(),
+        Default::Integer(i) => (*i).into(),
+        Default::Number(n) => (*n).into(),
+    }
+}
+
+/// Whether a union member's `$type` is one of the union's `refs`. Refs to a definition in the same
+/// lexicon, like `#view`, match any `$type` with that fragment.
+fn in_union(refs: &[String], r#type: &str) -> bool {
+    refs.iter().any(|r#ref| {
+        r#ref == r#type
+            || r#ref.strip_suffix("#main") == Some(r#type)
+            || (r#ref.starts_with('#') && r#type.ends_with(r#ref.as_str()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ValidationErrorKind;
+    use crate::Parameter;
+    use serde_json::{json, Value};
+
+    fn parameter(schema: Value) -> Parameter {
+        Parameter::try_from(schema.as_object().unwrap()).unwrap()
+    }
+
+    fn post() -> Parameter {
+        parameter(json!({
+            "type": "object",
+            "required": ["text", "createdAt"],
+            "properties": {
+                "text": { "type": "string", "maxLength": 300 },
+                "langs": {
+                    "type": "array",
+                    "maxLength": 3,
+                    "items": { "type": "string", "format": "language" }
+                },
+                "reply": { "type": "ref", "ref": "#replyRef" },
+                "embed": {
+                    "type": "union",
+                    "refs": ["app.bsky.embed.images", "app.bsky.embed.external#main"],
+                    "closed": true
+                },
+                "visibility": { "type": "string", "enum": ["public", "followers"] },
+                "version": { "type": "integer", "const": 1 },
+                "likes": { "type": "integer", "minimum": 0 },
+                "createdAt": { "type": "string", "format": "datetime" }
+            }
+        }))
+    }
+
+    /// The path and message of each error
+    fn errors(parameter: &Parameter, value: Value) -> Vec<(String, String)> {
+        parameter
+            .validate(&value)
+            .unwrap_err()
+            .into_iter()
+            .map(|e| (e.path().to_owned(), e.kind().to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_valid() {
+        let post = post();
+        post.validate(&json!({
+            "text": "hello",
+            "langs": ["en", "pt-BR"],
+            "reply": { "anything": "goes" },
+            "embed": { "$type": "app.bsky.embed.external", "uri": "https://example.com" },
+            "visibility": "public",
+            "version": 1,
+            "likes": 0,
+            "createdAt": "2022-11-17T01:05:26.123Z",
+            "extra": true
+        }))
+        .unwrap();
+        post.validate(&json!({ "text": "", "createdAt": "2022-11-17T01:05:26+09:00" }))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_reports_every_error() {
+        let errors = errors(
+            &post(),
+            json!({
+                "text": 1,
+                "langs": ["en", "not a language", "fr", "de"],
+                "embed": { "$type": "app.bsky.embed.record" },
+                "visibility": "private",
+                "version": 2,
+                "likes": -1
+            }),
+        );
+        let expected: Vec<(String, String)> = [
+            ("/createdAt", "is required"),
+            (
+                "/embed/$type",
+                "is \"app.bsky.embed.record\", which isn't one of the union's types",
+            ),
+            ("/langs", "must have a length of at most 3, but has 4"),
+            (
+                "/langs/1",
+                "must be a valid language, but is \"not a language\"",
+            ),
+            ("/likes", "must be at least 0, but is -1"),
+            ("/text", "must be of type string, not integer"),
+            ("/version", "must be 1, but is 2"),
+            (
+                "/visibility",
+                "must be one of the allowed values, but is \"private\"",
+            ),
+        ]
+        .into_iter()
+        .map(|(path, message)| (path.to_owned(), message.to_owned()))
+        .collect();
+        assert_eq!(errors, expected);
+    }
+
+    #[test]
+    fn test_display() {
+        let err = post().validate(&json!([])).unwrap_err();
+        assert_eq!(err.len(), 1);
+        assert_eq!(err.to_string(), "value must be of type object, not array");
+
+        let schema = parameter(json!({
+            "type": "object",
+            "properties": { "a/b~c": { "type": "boolean", "required": true } }
+        }));
+        let err = schema.validate(&json!({})).unwrap_err();
+        assert_eq!(err.to_string(), "`/a~1b~0c` is required");
+        assert_eq!(
+            err.iter().next().unwrap().kind(),
+            &ValidationErrorKind::Missing
+        );
+    }
+
+    #[test]
+    fn test_bytes_links_and_blobs() {
+        let schema = parameter(json!({
+            "type": "object",
+            "properties": {
+                "data": { "type": "bytes", "maxLength": 4 },
+                "link": { "type": "cid-link" },
+                "image": { "type": "blob" }
+            }
+        }));
+        let cid = "bafyreidfayvfuwqa7qlnopdjiqrxzs6blmoeu4rujcjtnci5beludirz2a";
+        schema
+            .validate(&json!({
+                "data": { "$bytes": "AAECAw==" },
+                "link": { "$link": cid },
+                "image": {
+                    "$type": "blob",
+                    "ref": { "$link": cid },
+                    "mimeType": "image/png",
+                    "size": 1024
+                }
+            }))
+            .unwrap();
+
+        let errors = errors(
+            &schema,
+            json!({
+                "data": { "$bytes": "AAECAwQF" },
+                "link": { "$link": "not a cid" },
+                "image": { "$type": "image", "ref": "abc", "size": 1.5 }
+            }),
+        );
+        let paths: Vec<_> = errors.iter().map(|(path, _)| path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                "/data",
+                "/image/$type",
+                "/image/ref",
+                "/image/mimeType",
+                "/image/size",
+                "/link/$link"
+            ]
+        );
+    }
+}